@@ -0,0 +1,390 @@
+//! An async version of [BTCDClient](crate::client::BTCDClient) and of the [BtcdRpc] trait, for
+//! use from tokio without `spawn_blocking`. Every RPC has the same arguments and returns the
+//! same `json_types` results as its blocking counterpart.
+use crate::client::{levelspec_value, BTCDConfigs};
+use crate::error::UtreexodError;
+use crate::{impl_verbosity_bool, impl_verbosity_level, QueryBlock};
+use async_trait::async_trait;
+use json_types::blockchain::{GetBlockHeaderResult, GetBlockResult};
+use json_types::transaction::{BestBlock, VerboseGetRawTransactionResult};
+use json_types::{
+    self,
+    transaction::{DecodeRawTransactionResult, Outpoint, Recipient},
+};
+use json_types::{general::*, VerbosityOutput};
+
+#[cfg(feature = "utreexod")]
+use json_types::blockchain::GetUtreexoProofResult;
+use jsonrpc::simple_http::DEFAULT_TIMEOUT;
+use jsonrpc::AsyncClient;
+use serde_json::{from_value, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+
+type Result<T> = std::result::Result<T, UtreexodError>;
+
+pub struct AsyncBTCDClient {
+    client: AsyncClient,
+    /// How long calls to some methods may take
+    method_timeouts: HashMap<String, Duration>,
+    /// How long other calls may take, when the transport was given a longer timeout for the
+    /// methods above
+    timeout: Option<Duration>,
+}
+
+impl AsyncBTCDClient {
+    async fn call<T: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        cmd: &str,
+        args: &[Value],
+    ) -> Result<T> {
+        let raw_args: Vec<_> = args.iter().flat_map(|a| from_value(a.clone())).collect();
+
+        // Sends the request, checks that the response answers it and deserializes the result
+        let call = self.client.call(cmd, &raw_args);
+        let timeout = self.method_timeouts.get(cmd).copied().or(self.timeout);
+        let result = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, call)
+                .await
+                .map_err(|_| jsonrpc::Error::DeadlineExceeded)?,
+            None => call.await,
+        };
+        Ok(result?)
+    }
+
+    pub fn new(cfg: BTCDConfigs) -> Result<AsyncBTCDClient> {
+        if cfg.proxy.is_some() {
+            return Err(UtreexodError::ProxyNotSupported);
+        }
+        let scheme = if cfg.tls { "https" } else { "http" };
+        let url = format!(
+            "{}://{}:{}",
+            scheme,
+            cfg.host.as_ref().expect("No hostname provided"),
+            cfg.port()
+        );
+        let mut builder = jsonrpc::async_http::Builder::new().url(&url)?;
+        if let Some(provider) = cfg.credentials() {
+            builder = builder.credentials(provider);
+        }
+        if let Some(size) = cfg.max_response_size {
+            builder = builder.max_response_size(size);
+        }
+        if cfg.tls {
+            builder = AsyncBTCDClient::with_tls(builder, cfg.cert, cfg.ca_bundle)?;
+        }
+        // The transport waits as long as the longest method timeout, the other calls are
+        // still cut at the default timeout
+        let longest = cfg
+            .method_timeouts
+            .iter()
+            .map(|&(_, timeout)| timeout)
+            .max();
+        let mut timeout = None;
+        if let Some(longest) = longest {
+            builder = builder.timeout(longest.max(DEFAULT_TIMEOUT));
+            timeout = Some(DEFAULT_TIMEOUT);
+        }
+        let client = AsyncClient::with_transport(builder.build()).with_version(cfg.version);
+        Ok(AsyncBTCDClient {
+            client,
+            method_timeouts: cfg.method_timeouts.into_iter().collect(),
+            timeout,
+        })
+    }
+
+    #[cfg(feature = "tls")]
+    fn with_tls(
+        builder: jsonrpc::async_http::Builder,
+        cert: Option<std::path::PathBuf>,
+        ca_bundle: Option<std::path::PathBuf>,
+    ) -> Result<jsonrpc::async_http::Builder> {
+        match (cert, ca_bundle) {
+            (Some(cert), _) => Ok(builder.tls_pinned_cert(cert)?),
+            (None, Some(bundle)) => Ok(builder.tls_ca_bundle(bundle)?),
+            (None, None) => Err(UtreexodError::MissingCertificate),
+        }
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn with_tls(
+        _builder: jsonrpc::async_http::Builder,
+        _cert: Option<std::path::PathBuf>,
+        _ca_bundle: Option<std::path::PathBuf>,
+    ) -> Result<jsonrpc::async_http::Builder> {
+        Err(UtreexodError::TlsNotEnabled)
+    }
+
+    /// Creates a client that talks to btcd over any async JSON-RPC transport.
+    pub fn with_transport<T: jsonrpc::AsyncTransport>(transport: T) -> AsyncBTCDClient {
+        AsyncBTCDClient {
+            client: AsyncClient::with_transport(transport),
+            method_timeouts: HashMap::new(),
+            timeout: None,
+        }
+    }
+
+    /// Sets the version of JSON-RPC spoken with the node, see
+    /// [BTCDClient::with_version](crate::client::BTCDClient::with_version).
+    pub fn with_version(self, version: jsonrpc::Version) -> AsyncBTCDClient {
+        AsyncBTCDClient {
+            client: self.client.with_version(version),
+            ..self
+        }
+    }
+}
+
+/// The async version of [BtcdRpc](crate::client::BtcdRpc). See there for the documentation of
+/// each RPC.
+#[async_trait]
+pub trait AsyncBtcdRpc: Sync {
+    /// Calls an arbitrary command, see [BtcdRpc::call](crate::client::BtcdRpc::call).
+    async fn call<T: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        command: &'static str,
+        args: &[Value],
+    ) -> Result<T>;
+    /// Returns a JSON object containing various state info.
+    async fn getinfo(&self) -> Result<GetInfoResult> {
+        self.call("getinfo", &[]).await
+    }
+    /// Returns the hash of a block, given it's height.
+    async fn getblockhash(&self, height: usize) -> Result<String> {
+        let height = Value::from(height);
+        self.call("getblockhash", &[height]).await
+    }
+    #[cfg(feature = "utreexod")]
+    /// Returns the Batch Proof for a given block
+    async fn getutreexoproof(
+        &self,
+        hash: String,
+        verbosity: bool,
+    ) -> Result<VerbosityOutput<GetUtreexoProofResult>> {
+        let hash = Value::from(hash);
+        impl_verbosity_level!(self, "getutreexoproof", hash, verbosity, await)
+    }
+    /// Adds, removes or lists manually added peers.
+    async fn addnode(&self, addr: &str, cmd: &str) -> Result<()> {
+        let addr = Value::from(addr);
+        let cmd = Value::from(cmd);
+        self.call("addnode", &[addr, cmd]).await
+    }
+    /// Creates a new unsigned raw transactions sending funds to `destinations`.
+    async fn createrawtransaction(
+        &self,
+        inputs: Vec<Outpoint>,
+        destinations: Vec<Recipient>,
+        locktime: usize,
+    ) -> Result<()> {
+        let inputs = serde_json::to_value(inputs)?;
+        let destinations = serde_json::to_value(destinations)?;
+        let locktime = Value::from(locktime);
+
+        self.call("createrawtransaction", &[inputs, destinations, locktime])
+            .await
+    }
+    /// Dynamically changes the debug logging level.
+    async fn debuglevel(&self, levelspec: LevelSpec) -> Result<()> {
+        let levelspec = levelspec_value(levelspec)?;
+        self.call("debuglevel", &[levelspec]).await
+    }
+    /// Returns an object representing the provided serialized, hex-encoded transaction.
+    async fn decoderawtransaction(&self, hextx: String) -> Result<DecodeRawTransactionResult> {
+        let hextx = serde_json::to_value(hextx)?;
+        self.call("decoderawtransaction", &[hextx]).await
+    }
+    /// Returns the current best-known block' hash and height
+    async fn getbestblock(&self) -> Result<BestBlock> {
+        self.call("getbestblock", &[]).await
+    }
+    /// Returns only the hash of the best known block
+    async fn getbestblockhash(&self) -> Result<String> {
+        self.call("getbestblockhash", &[]).await
+    }
+    /// Returns how many blocks we known about
+    async fn getblockcount(&self) -> Result<usize> {
+        self.call("getblockcount", &[]).await
+    }
+    /// Broadcast a hex-encoded transaction to the network
+    async fn sendrawtransaction(&self, rawtx: String) -> Result<String> {
+        let rawtx = serde_json::to_value(rawtx)?;
+        self.call("sendrawtransaction", &[rawtx]).await
+    }
+    /// Estimates the required fee for a given expected confirmation time, in blocks
+    async fn estimatefee(&self, blocks: u32) -> Result<f64> {
+        let blocks = serde_json::to_value(blocks)?;
+        self.call("estimatefee", &[blocks]).await
+    }
+    /// Returns the raw transaction, given it's hash
+    async fn getrawtransaction(
+        &self,
+        transaction_hash: String,
+        verbosity: bool,
+    ) -> Result<VerbosityOutput<VerboseGetRawTransactionResult>> {
+        let transaction_hash = serde_json::to_value(transaction_hash)?;
+
+        impl_verbosity_level!(
+            self,
+            "getrawtransaction",
+            transaction_hash,
+            verbosity,
+            await
+        )
+    }
+    /// Returns a block, given it's hash
+    async fn getblock(
+        &self,
+        hash: String,
+        verbosity: bool,
+    ) -> Result<VerbosityOutput<GetBlockResult>> {
+        let hash = serde_json::to_value(hash)?;
+        impl_verbosity_level!(self, "getblock", hash, verbosity, await)
+    }
+    /// Returns a block, given its hash or its height. Given a height, its hash is asked
+    /// first.
+    async fn getblock_by(
+        &self,
+        block: QueryBlock,
+        verbosity: bool,
+    ) -> Result<VerbosityOutput<GetBlockResult>> {
+        let hash = match block {
+            QueryBlock::ByHeight(height) => self.getblockhash(height).await?,
+            QueryBlock::ByHash(hash) => hash,
+        };
+        self.getblock(hash, verbosity).await
+    }
+    /// Returns the block's header
+    async fn getblockheader(
+        &self,
+        hash: String,
+        verbosity: bool,
+    ) -> Result<VerbosityOutput<GetBlockHeaderResult>> {
+        let hash = serde_json::to_value(hash)?;
+        impl_verbosity_bool!(self, "getblockheader", hash, verbosity, await)
+    }
+    /// Returns a batch of headers
+    async fn getheaders(&self, locator: Vec<String>, stop_hash: String) -> Result<Vec<String>> {
+        let locator = serde_json::to_value(locator)?;
+        let stop_hash = serde_json::to_value(stop_hash)?;
+        self.call("getheaders", &[locator, stop_hash]).await
+    }
+}
+
+#[async_trait]
+impl AsyncBtcdRpc for AsyncBTCDClient {
+    async fn call<T: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        command: &'static str,
+        args: &[Value],
+    ) -> Result<T> {
+        self.call(command, args).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AsyncBTCDClient, AsyncBtcdRpc};
+    use crate::client::{BTCDConfigs, Options};
+    use crate::error::ErrorKind;
+    use crate::QueryBlock;
+    use serde_json::{json, Value};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Serves one request per connection, answering with `result` after `delay`.
+    fn serve(results: Vec<Value>, delay: Duration) -> u16 {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for result in results {
+                let (stream, _) = server.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(len) = line.strip_prefix("Content-Length: ") {
+                        content_length = len.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                let req: Value = serde_json::from_slice(&body).unwrap();
+                thread::sleep(delay);
+                let resp = json!({"result": result, "error": null, "id": req["id"]}).to_string();
+                // The client may have given up already
+                let _ = write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    resp.len(),
+                    resp
+                );
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_async_client() {
+        let port = serve(vec![json!(42), json!("00000020")], Duration::from_millis(0));
+        let config = BTCDConfigs::new(
+            false,
+            Some("SomeUsername".into()),
+            Some("CorrectHorseBattleStaple".into()),
+            Some("127.0.0.1".into()),
+            Some(port as usize),
+        );
+        let client = AsyncBTCDClient::new(config).unwrap();
+
+        assert_eq!(client.getblockcount().await.unwrap(), 42);
+        let header = client.getblockheader("00".into(), false).await.unwrap();
+        assert_eq!(header.get_simple(), "00000020");
+    }
+
+    #[tokio::test]
+    async fn test_async_getblock_by() {
+        let port = serve(vec![json!("00aa"), json!("0100")], Duration::from_millis(0));
+        let config = BTCDConfigs::new(
+            false,
+            None,
+            None,
+            Some("127.0.0.1".into()),
+            Some(port as usize),
+        );
+        let client = AsyncBTCDClient::new(config).unwrap();
+        let block = client
+            .getblock_by(QueryBlock::ByHeight(1), false)
+            .await
+            .unwrap();
+        assert_eq!(block.get_simple(), "0100");
+    }
+
+    #[tokio::test]
+    async fn test_async_method_timeouts() {
+        let port = serve(vec![json!(42), json!(42)], Duration::from_millis(300));
+        let config = BTCDConfigs::new(
+            false,
+            None,
+            None,
+            Some("127.0.0.1".into()),
+            Some(port as usize),
+        )
+        .set(Options::MethodTimeout(
+            "getblockcount".into(),
+            Duration::from_millis(50),
+        ))
+        .set(Options::MethodTimeout(
+            "getbestblockhash".into(),
+            Duration::from_secs(60),
+        ));
+        let client = AsyncBTCDClient::new(config).unwrap();
+        let err = client.getblockcount().await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+        assert_eq!(client.call::<u64>("getinfo", &[]).await.unwrap(), 42);
+    }
+}
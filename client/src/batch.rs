@@ -0,0 +1,362 @@
+//! Typed batches of RPCs. A [Batch] queues calls, like [Batch::getblockhash], and sends them
+//! together, so that looking up a thousand blocks takes a handful of round trips instead of a
+//! thousand. Each queued call gives a [Pending] handle, used to get its own result, or its own
+//! error, out of the [BatchResults]. Batches larger than the chunk size are split into several
+//! JSON-RPC batches, sent one after the other.
+use crate::client::{levelspec_value, BTCDClient};
+use crate::error::UtreexodError;
+use json_types::blockchain::{GetBlockHeaderResult, GetBlockResult};
+use json_types::general::{GetInfoResult, LevelSpec};
+use json_types::transaction::{
+    BestBlock, DecodeRawTransactionResult, VerboseGetRawTransactionResult,
+};
+use json_types::VerbosityOutput;
+
+#[cfg(feature = "utreexod")]
+use json_types::blockchain::GetUtreexoProofResult;
+use jsonrpc::Response;
+use serde::de::DeserializeOwned;
+use serde_json::value::RawValue;
+use serde_json::{from_value, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+type Result<T> = std::result::Result<T, UtreexodError>;
+
+/// How many calls are sent in a single JSON-RPC batch by default.
+pub const DEFAULT_CHUNK_SIZE: usize = 500;
+
+/// The id of the next batch, which tells whose results a [Pending] can be looked up in.
+static NEXT_BATCH: AtomicUsize = AtomicUsize::new(0);
+
+/// A call queued in a [Batch]. Its result is taken out of the [BatchResults] of that batch.
+pub struct Pending<T> {
+    batch: usize,
+    index: usize,
+    decode: fn(&str) -> serde_json::Result<T>,
+}
+
+impl<T> Clone for Pending<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Pending<T> {}
+
+/// A batch of calls, built with [BTCDClient::batch].
+pub struct Batch<'c> {
+    id: usize,
+    client: &'c BTCDClient,
+    calls: Vec<(&'static str, Vec<Box<RawValue>>)>,
+    chunk_size: usize,
+}
+
+impl<'c> Batch<'c> {
+    pub(crate) fn new(client: &'c BTCDClient) -> Batch<'c> {
+        Batch {
+            id: NEXT_BATCH.fetch_add(1, Ordering::Relaxed),
+            client,
+            calls: Vec::new(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Sets how many calls are sent in a single JSON-RPC batch, for servers that limit their
+    /// size. Defaults to [DEFAULT_CHUNK_SIZE].
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size.max(1);
+        self
+    }
+
+    /// Returns the number of calls queued so far.
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// Returns whether no call was queued yet.
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// Queues an arbitrary command, whose result is deserialized as [T].
+    pub fn call<T: DeserializeOwned>(
+        &mut self,
+        command: &'static str,
+        args: &[Value],
+    ) -> Result<Pending<T>> {
+        self.queue(command, args, decode)
+    }
+
+    fn queue<T>(
+        &mut self,
+        command: &'static str,
+        args: &[Value],
+        decode: fn(&str) -> serde_json::Result<T>,
+    ) -> Result<Pending<T>> {
+        let args = args
+            .iter()
+            .map(|a| from_value(a.clone()))
+            .collect::<serde_json::Result<_>>()?;
+        self.calls.push((command, args));
+        Ok(Pending {
+            batch: self.id,
+            index: self.calls.len() - 1,
+            decode,
+        })
+    }
+
+    /// Queues a `getinfo`, see [BtcdRpc::getinfo](crate::client::BtcdRpc::getinfo).
+    pub fn getinfo(&mut self) -> Result<Pending<GetInfoResult>> {
+        self.call("getinfo", &[])
+    }
+
+    /// Queues a `getblockhash`, see
+    /// [BtcdRpc::getblockhash](crate::client::BtcdRpc::getblockhash).
+    pub fn getblockhash(&mut self, height: usize) -> Result<Pending<String>> {
+        self.call("getblockhash", &[Value::from(height)])
+    }
+
+    #[cfg(feature = "utreexod")]
+    /// Queues a `getutreexoproof`, see
+    /// [BtcdRpc::getutreexoproof](crate::client::BtcdRpc::getutreexoproof).
+    pub fn getutreexoproof(
+        &mut self,
+        hash: String,
+        verbosity: bool,
+    ) -> Result<Pending<VerbosityOutput<GetUtreexoProofResult>>> {
+        let args = [Value::from(hash), Value::from(verbosity as u8)];
+        self.queue("getutreexoproof", &args, verbosity_output(verbosity))
+    }
+
+    /// Queues a `debuglevel`, see [BtcdRpc::debuglevel](crate::client::BtcdRpc::debuglevel).
+    pub fn debuglevel(&mut self, levelspec: LevelSpec) -> Result<Pending<()>> {
+        let levelspec = levelspec_value(levelspec)?;
+        self.call("debuglevel", &[levelspec])
+    }
+
+    /// Queues a `decoderawtransaction`, see
+    /// [BtcdRpc::decoderawtransaction](crate::client::BtcdRpc::decoderawtransaction).
+    pub fn decoderawtransaction(
+        &mut self,
+        hextx: String,
+    ) -> Result<Pending<DecodeRawTransactionResult>> {
+        self.call("decoderawtransaction", &[Value::from(hextx)])
+    }
+
+    /// Queues a `getbestblock`, see [BtcdRpc::getbestblock](crate::client::BtcdRpc::getbestblock).
+    pub fn getbestblock(&mut self) -> Result<Pending<BestBlock>> {
+        self.call("getbestblock", &[])
+    }
+
+    /// Queues a `getbestblockhash`, see
+    /// [BtcdRpc::getbestblockhash](crate::client::BtcdRpc::getbestblockhash).
+    pub fn getbestblockhash(&mut self) -> Result<Pending<String>> {
+        self.call("getbestblockhash", &[])
+    }
+
+    /// Queues a `getblockcount`, see
+    /// [BtcdRpc::getblockcount](crate::client::BtcdRpc::getblockcount).
+    pub fn getblockcount(&mut self) -> Result<Pending<usize>> {
+        self.call("getblockcount", &[])
+    }
+
+    /// Queues a `sendrawtransaction`, see
+    /// [BtcdRpc::sendrawtransaction](crate::client::BtcdRpc::sendrawtransaction).
+    pub fn sendrawtransaction(&mut self, rawtx: String) -> Result<Pending<String>> {
+        self.call("sendrawtransaction", &[Value::from(rawtx)])
+    }
+
+    /// Queues an `estimatefee`, see [BtcdRpc::estimatefee](crate::client::BtcdRpc::estimatefee).
+    pub fn estimatefee(&mut self, blocks: u32) -> Result<Pending<f64>> {
+        self.call("estimatefee", &[Value::from(blocks)])
+    }
+
+    /// Queues a `getrawtransaction`, see
+    /// [BtcdRpc::getrawtransaction](crate::client::BtcdRpc::getrawtransaction).
+    pub fn getrawtransaction(
+        &mut self,
+        transaction_hash: String,
+        verbosity: bool,
+    ) -> Result<Pending<VerbosityOutput<VerboseGetRawTransactionResult>>> {
+        let args = [Value::from(transaction_hash), Value::from(verbosity as u8)];
+        self.queue("getrawtransaction", &args, verbosity_output(verbosity))
+    }
+
+    /// Queues a `getblock`, see [BtcdRpc::getblock](crate::client::BtcdRpc::getblock).
+    pub fn getblock(
+        &mut self,
+        hash: String,
+        verbosity: bool,
+    ) -> Result<Pending<VerbosityOutput<GetBlockResult>>> {
+        let args = [Value::from(hash), Value::from(verbosity as u8)];
+        self.queue("getblock", &args, verbosity_output(verbosity))
+    }
+
+    /// Queues a `getblockheader`, see
+    /// [BtcdRpc::getblockheader](crate::client::BtcdRpc::getblockheader).
+    pub fn getblockheader(
+        &mut self,
+        hash: String,
+        verbosity: bool,
+    ) -> Result<Pending<VerbosityOutput<GetBlockHeaderResult>>> {
+        let args = [Value::from(hash), Value::from(verbosity)];
+        self.queue("getblockheader", &args, verbosity_output(verbosity))
+    }
+
+    /// Queues a `getheaders`, see [BtcdRpc::getheaders](crate::client::BtcdRpc::getheaders).
+    pub fn getheaders(
+        &mut self,
+        locator: Vec<String>,
+        stop_hash: String,
+    ) -> Result<Pending<Vec<String>>> {
+        self.call(
+            "getheaders",
+            &[Value::from(locator), Value::from(stop_hash)],
+        )
+    }
+
+    /// Sends the queued calls, in chunks of at most [Batch::chunk_size] calls. This fails
+    /// only if a chunk couldn't be sent or its responses didn't match its calls, the errors
+    /// of the calls themselves are returned by [BatchResults::get].
+    pub fn send(self) -> Result<BatchResults> {
+        let mut responses = Vec::with_capacity(self.calls.len());
+        for chunk in self.calls.chunks(self.chunk_size) {
+            let requests: Vec<_> = chunk
+                .iter()
+                .map(|(command, args)| self.client.0.build_request(command, args))
+                .collect();
+            responses.extend(self.client.0.send_batch(&requests)?);
+        }
+        Ok(BatchResults {
+            batch: self.id,
+            responses,
+        })
+    }
+}
+
+/// The responses to the calls of a [Batch].
+pub struct BatchResults {
+    batch: usize,
+    responses: Vec<Option<Response>>,
+}
+
+impl BatchResults {
+    /// Returns the result of a call queued in the batch these are the results of. A call
+    /// queued in another batch gives [UtreexodError::WrongBatch].
+    pub fn get<T>(&self, call: Pending<T>) -> Result<T> {
+        if call.batch != self.batch {
+            return Err(UtreexodError::WrongBatch);
+        }
+        let response = match self.responses.get(call.index) {
+            Some(Some(response)) => response,
+            _ => return Err(UtreexodError::EmptyResponseFromServer),
+        };
+        if let Some(ref error) = response.error {
+            return Err(UtreexodError::JsonRpcError(jsonrpc::Error::Rpc(
+                error.clone(),
+            )));
+        }
+        let result = response.result.as_ref().map_or("null", |r| r.get());
+        Ok((call.decode)(result)?)
+    }
+}
+
+fn decode<T: DeserializeOwned>(result: &str) -> serde_json::Result<T> {
+    serde_json::from_str(result)
+}
+
+/// Returns how to decode the result of a call made with the given verbosity, since it tells
+/// which variant of [VerbosityOutput] the result is.
+fn verbosity_output<T: DeserializeOwned>(
+    verbose: bool,
+) -> fn(&str) -> serde_json::Result<VerbosityOutput<T>> {
+    if verbose {
+        |result| Ok(VerbosityOutput::Verbose(serde_json::from_str(result)?))
+    } else {
+        |result| Ok(VerbosityOutput::Simple(serde_json::from_str(result)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::client::BTCDClient;
+    use crate::error::UtreexodError;
+    use json_types::VerbosityOutput;
+    use jsonrpc::error::RpcError;
+    use jsonrpc::interceptor::Interceptor;
+    use jsonrpc::server::Server;
+    use jsonrpc::{Request, Response, Transport};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Counts the batches sent.
+    struct Batches(Arc<AtomicUsize>);
+
+    impl Interceptor for Batches {
+        fn send_batch(
+            &self,
+            reqs: &[Request],
+            next: &dyn Transport,
+        ) -> Result<Vec<Response>, jsonrpc::Error> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            next.send_batch(reqs)
+        }
+    }
+
+    #[test]
+    fn chunks() {
+        let server = Server::new()
+            .register("getblockhash", |(height,): (usize,)| {
+                if height > 4 {
+                    return Err(RpcError {
+                        code: -1,
+                        message: "Block number out of range".to_owned(),
+                        data: None,
+                    });
+                }
+                Ok(format!("{:064x}", height))
+            })
+            .register("getblock", |(hash, _): (String, u8)| Ok(hash));
+        let batches = Arc::new(AtomicUsize::new(0));
+        let client = BTCDClient::with_transport(server).with_interceptor(Batches(batches.clone()));
+
+        let mut batch = client.batch().chunk_size(2);
+        let hashes: Vec<_> = (0..6).map(|h| batch.getblockhash(h).unwrap()).collect();
+        let block = batch.getblock("00".to_owned(), false).unwrap();
+        assert_eq!(batch.len(), 7);
+        let results = batch.send().unwrap();
+        assert_eq!(batches.load(Ordering::SeqCst), 4);
+
+        for (height, &hash) in hashes.iter().enumerate().take(5) {
+            assert_eq!(results.get(hash).unwrap(), format!("{:064x}", height));
+        }
+        match results.get(hashes[5]) {
+            Err(UtreexodError::JsonRpcError(jsonrpc::Error::Rpc(e))) => assert_eq!(e.code, -1),
+            res => panic!("expected an error, got {:?}", res),
+        }
+        match results.get(block).unwrap() {
+            VerbosityOutput::Simple(hex) => assert_eq!(hex, "00"),
+            VerbosityOutput::Verbose(_) => panic!("expected a hex block"),
+        }
+
+        assert!(client.batch().send().is_ok());
+        assert_eq!(batches.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn handles_of_another_batch() {
+        let server = Server::new().register("getblockcount", |()| Ok(7));
+        let client = BTCDClient::with_transport(server);
+
+        let mut first = client.batch();
+        let count = first.getblockcount().unwrap();
+        let mut second = client.batch();
+        second.getblockcount().unwrap();
+        let results = second.send().unwrap();
+        match results.get(count) {
+            Err(UtreexodError::WrongBatch) => {}
+            res => panic!("expected a wrong batch error, got {:?}", res),
+        }
+        assert_eq!(first.send().unwrap().get(count).unwrap(), 7);
+    }
+}
@@ -1,5 +1,6 @@
+use crate::batch::Batch;
 use crate::error::UtreexodError;
-use crate::{impl_verbosity_bool, impl_verbosity_level};
+use crate::{impl_verbosity_bool, impl_verbosity_level, QueryBlock};
 use json_types::blockchain::{GetBlockHeaderResult, GetBlockResult};
 use json_types::transaction::{BestBlock, VerboseGetRawTransactionResult};
 use json_types::{
@@ -10,43 +11,166 @@ use json_types::{general::*, VerbosityOutput};
 
 #[cfg(feature = "utreexod")]
 use json_types::blockchain::GetUtreexoProofResult;
-use jsonrpc::{self, Client};
+use jsonrpc::auth::{CookieFile, CredentialProvider, Credentials, Secret, StaticCredentials};
+use jsonrpc::{self, CallOptions, Client};
 use serde_json::{from_value, Value};
-pub struct BTCDClient(Client);
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+pub struct BTCDClient(pub(crate) Client);
 
 impl BTCDClient {
     fn call<T: for<'a> serde::de::Deserialize<'a>>(&self, cmd: &str, args: &[Value]) -> Result<T> {
-        let raw_args: Vec<_> = args
+        let raw_args: Vec<_> = args.iter().flat_map(|a| from_value(a.clone())).collect();
+
+        // Sends the request, checks that the response answers it and deserializes the result
+        Ok(self.0.call(cmd, &raw_args)?)
+    }
+
+    pub fn new(cfg: BTCDConfigs) -> Result<BTCDClient> {
+        if cfg.tls {
+            return BTCDClient::new_tls(cfg);
+        }
+        let url = format!(
+            "{}:{}",
+            cfg.host.as_ref().expect("No hostname provided"),
+            cfg.port()
+        );
+        let mut builder = jsonrpc::simple_http::Builder::new().url(&url)?;
+        if let Some(provider) = cfg.credentials() {
+            builder = builder.credentials(provider);
+        }
+        if let Some(size) = cfg.max_response_size {
+            builder = builder.max_response_size(size);
+        }
+        builder = BTCDClient::with_proxy(builder, cfg.proxy, cfg.proxy_auth)?;
+        let client = BTCDClient::with_transport(builder.build()).with_version(cfg.version);
+        Ok(client.with_method_timeouts(cfg.method_timeouts))
+    }
+
+    /// Sends requests through a SOCKS5 proxy, if one was configured. The proxy resolves the
+    /// node's host name itself, so `.onion` hosts work through Tor.
+    fn with_proxy(
+        mut builder: jsonrpc::simple_http::Builder,
+        proxy: Option<String>,
+        auth: Option<(String, Secret)>,
+    ) -> Result<jsonrpc::simple_http::Builder> {
+        if let Some(proxy) = proxy {
+            builder = builder.proxy_addr(proxy)?;
+            if let Some((user, pass)) = auth {
+                builder = builder.proxy_auth(user.as_str(), pass.expose());
+            }
+        }
+        Ok(builder)
+    }
+
+    /// Creates a client that talks to btcd over any JSON-RPC transport, like the WebSocket
+    /// transport from `jsonrpc::simple_ws`.
+    ///
+    /// With the `instrument` feature, requests are recorded in spans and metrics, without
+    /// their parameters, see `jsonrpc::instrument`.
+    pub fn with_transport<T: jsonrpc::Transport>(transport: T) -> BTCDClient {
+        let client = Client::with_transport(transport);
+        #[cfg(feature = "instrument")]
+        let client = client.with_interceptor(jsonrpc::instrument::Instrument::new());
+        BTCDClient(client)
+    }
+
+    /// Runs `interceptor` around every request this client sends, to log, time or rewrite
+    /// them, see `jsonrpc::interceptor`.
+    pub fn with_interceptor<I: jsonrpc::interceptor::Interceptor>(
+        self,
+        interceptor: I,
+    ) -> BTCDClient {
+        BTCDClient(self.0.with_interceptor(interceptor))
+    }
+
+    /// Starts a batch of calls, sent together with [Batch::send]. See [crate::batch].
+    pub fn batch(&self) -> Batch<'_> {
+        Batch::new(self)
+    }
+
+    /// Sets the version of JSON-RPC spoken with the node. With [jsonrpc::Version::V1],
+    /// requests are sent like btcd's own `btcjson` clients send them, and responses claiming
+    /// another version are rejected. Responses are otherwise read the same way for both
+    /// versions.
+    pub fn with_version(self, version: jsonrpc::Version) -> BTCDClient {
+        BTCDClient(self.0.with_version(version))
+    }
+
+    /// Gives the calls to `method` `timeout` to complete by default, like a short one for
+    /// `getblockcount` and a long one for `getblock`, instead of the transport's timeout.
+    pub fn with_method_timeout(self, method: &str, timeout: Duration) -> BTCDClient {
+        BTCDClient(self.0.with_method_timeout(method, timeout))
+    }
+
+    pub(crate) fn with_method_timeouts(self, timeouts: Vec<(String, Duration)>) -> BTCDClient {
+        timeouts
             .into_iter()
-            .map(|a| from_value(a.clone()))
-            .filter(|a| a.is_ok())
-            .map(|a| a.unwrap())
-            .collect();
-
-        // Builds a request
-        let req = self.0.build_request(&cmd, &raw_args);
-        // Sends it and collects the response in `resp`
-        let resp = self.0.send_request(req)?;
-        if let Some(error) = resp.error {
-            return Err(UtreexodError::JsonRpcError(jsonrpc::Error::Rpc(error)));
+            .fold(self, |client, (method, timeout)| {
+                client.with_method_timeout(&method, timeout)
+            })
+    }
+
+    /// Runs `f` with `options` applying to all the calls it makes: a deadline shared by all
+    /// of them, or a token to cancel them from another thread, see [jsonrpc::context].
+    /// ```
+    /// use btcd_rpc::client::{BTCDClient, BtcdRpc};
+    /// use btcd_rpc::QueryBlock;
+    /// use jsonrpc::{CallOptions, CancelToken};
+    /// use std::time::Duration;
+    /// # let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/getblockhash.json");
+    /// # let client = BTCDClient::with_transport(
+    /// #     jsonrpc::replay::ReplayTransport::open(fixture).unwrap(),
+    /// # );
+    ///
+    /// let token = CancelToken::new();
+    /// let options = CallOptions::new()
+    ///     .timeout(Duration::from_secs(5))
+    ///     .cancel_token(token.clone());
+    /// let hash = client.with_options(&options, |client| client.getblockhash(0));
+    /// assert!(hash.is_ok());
+    ///
+    /// // Once cancelled, calls fail without being sent
+    /// token.cancel();
+    /// let block = client.with_options(&options, |client| {
+    ///     client.getblock_by(QueryBlock::ByHeight(0), false)
+    /// });
+    /// assert!(block.is_err());
+    /// ```
+    pub fn with_options<T, F: FnOnce(&BTCDClient) -> T>(&self, options: &CallOptions, f: F) -> T {
+        jsonrpc::context::scope(options, || f(self))
+    }
+
+    /// Connects to btcd over TLS, either pinning the node's own certificate (usually
+    /// `rpc.cert` in btcd's data directory) or verifying it against a CA bundle.
+    #[cfg(feature = "tls")]
+    fn new_tls(cfg: BTCDConfigs) -> Result<BTCDClient> {
+        let url = format!(
+            "https://{}:{}",
+            cfg.host.as_ref().expect("No hostname provided"),
+            cfg.port()
+        );
+        let mut builder = jsonrpc::simple_http::Builder::new().url(&url)?;
+        if let Some(provider) = cfg.credentials() {
+            builder = builder.credentials(provider);
         }
-        Ok(serde_json::from_str::<T>(
-            resp.result.unwrap_or_default().get(),
-        )?)
+        builder = match (cfg.cert, cfg.ca_bundle) {
+            (Some(cert), _) => builder.tls_pinned_cert(cert)?,
+            (None, Some(bundle)) => builder.tls_ca_bundle(bundle)?,
+            (None, None) => return Err(UtreexodError::MissingCertificate),
+        };
+        if let Some(size) = cfg.max_response_size {
+            builder = builder.max_response_size(size);
+        }
+        builder = BTCDClient::with_proxy(builder, cfg.proxy, cfg.proxy_auth)?;
+        let client = BTCDClient::with_transport(builder.build()).with_version(cfg.version);
+        Ok(client.with_method_timeouts(cfg.method_timeouts))
     }
 
-    pub fn new(cfg: BTCDConfigs) -> Result<BTCDClient> {
-        let client = Client::simple_http(
-            format!(
-                "{}:{}",
-                cfg.host.expect("No hostname provided"),
-                cfg.port.unwrap_or(8332)
-            )
-            .as_str(),
-            cfg.username,
-            cfg.password,
-        )?;
-        Ok(BTCDClient(client))
+    #[cfg(not(feature = "tls"))]
+    fn new_tls(_cfg: BTCDConfigs) -> Result<BTCDClient> {
+        Err(UtreexodError::TlsNotEnabled)
     }
 }
 
@@ -77,6 +201,10 @@ pub trait BtcdRpc {
     /// );
     ///
     /// let client = BTCDClient::new(config).unwrap();
+    /// # let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/getinfo.json");
+    /// # let client = BTCDClient::with_transport(
+    /// #     jsonrpc::replay::ReplayTransport::open(fixture).unwrap(),
+    /// # );
     /// assert!(client.getinfo().is_ok());
     /// ```
     fn getinfo(&self) -> Result<GetInfoResult> {
@@ -94,6 +222,10 @@ pub trait BtcdRpc {
     /// );
     ///
     /// let client = BTCDClient::new(config).unwrap();
+    /// # let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/getblockhash.json");
+    /// # let client = BTCDClient::with_transport(
+    /// #     jsonrpc::replay::ReplayTransport::open(fixture).unwrap(),
+    /// # );
     /// // This is a signet block
     /// assert_eq!(client.getblockhash(0).unwrap(), String::from("00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6"));
     /// ```
@@ -104,10 +236,22 @@ pub trait BtcdRpc {
     #[cfg(feature = "utreexod")]
     /// Returns the Batch Proof for a given block
     /// ```
-    /// use btcd_rpc::client::{BTCDClient, BtcdRpc};
-    /// let client = BTCDClient::new().unwrap();
-    /// // This is a signet block
-    /// // assert!(client.getblockhash(0).unwrap(), String::from("00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6"));
+    /// use btcd_rpc::client::{BTCDConfigs, BTCDClient, BtcdRpc};
+    /// let config = BTCDConfigs::new(
+    ///     false,
+    ///     Some("SomeUsername".into()),
+    ///     Some("CorrectHorseBattleStaple".into()),
+    ///     Some("localhost".into()),
+    ///     Some(38332),
+    /// );
+    ///
+    /// let client = BTCDClient::new(config).unwrap();
+    /// # let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/getutreexoproof.json");
+    /// # let client = BTCDClient::with_transport(
+    /// #     jsonrpc::replay::ReplayTransport::open(fixture).unwrap(),
+    /// # );
+    /// let hash = client.getblockhash(0).unwrap();
+    /// assert!(client.getutreexoproof(hash, true).is_ok());
     /// ```
     fn getutreexoproof(
         &self,
@@ -131,6 +275,10 @@ pub trait BtcdRpc {
     /// );
     ///
     /// let client = BTCDClient::new(config).unwrap();
+    /// # let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/addnode.json");
+    /// # let client = BTCDClient::with_transport(
+    /// #     jsonrpc::replay::ReplayTransport::open(fixture).unwrap(),
+    /// # );
     /// // This is a signet block
     /// assert!(client.addnode(&"127.0.0.1", &"add").is_ok());
     /// ```
@@ -160,18 +308,7 @@ pub trait BtcdRpc {
     /// The valid subsystems are AMGR, ADXR, BCDB, BMGR, BTCD, CHAN, DISC, PEER, RPCS, SCRP, SRVR, and TXMP.
     /// Finally the keyword 'show' will return a list of the available subsystems.
     fn debuglevel(&self, levelspec: LevelSpec) -> Result<()> {
-        let levelspec = match levelspec {
-            LevelSpec::Global(val) => serde_json::to_value(val),
-            LevelSpec::Subsystem(values) => {
-                let mut spec = String::new();
-                for (system, level) in values {
-                    spec = format!("{}={},", system.to_string(), level.to_string());
-                }
-                spec.pop(); // This removes any trailing comma
-                serde_json::to_value(spec)
-            }
-        }?;
-
+        let levelspec = levelspec_value(levelspec)?;
         self.call("debuglevel", &[levelspec])
     }
     /// Returns an object representing the provided serialized, hex-encoded transaction.
@@ -220,6 +357,19 @@ pub trait BtcdRpc {
         let hash = serde_json::to_value(hash)?;
         impl_verbosity_level!(self, "getblock", hash, verbosity)
     }
+    /// Returns a block, given its hash or its height. Given a height, its hash is asked
+    /// first: both round trips share the deadline set with [BTCDClient::with_options].
+    fn getblock_by(
+        &self,
+        block: QueryBlock,
+        verbosity: bool,
+    ) -> Result<VerbosityOutput<GetBlockResult>> {
+        let hash = match block {
+            QueryBlock::ByHeight(height) => self.getblockhash(height)?,
+            QueryBlock::ByHash(hash) => hash,
+        };
+        self.getblock(hash, verbosity)
+    }
     /// Returns the block's header
     fn getblockheader(
         &self,
@@ -246,12 +396,43 @@ impl BtcdRpc for BTCDClient {
     }
 }
 
+/// Serializes a levelspec to the form expected by `debuglevel`.
+pub(crate) fn levelspec_value(levelspec: LevelSpec) -> serde_json::Result<Value> {
+    match levelspec {
+        LevelSpec::Global(val) => serde_json::to_value(val),
+        LevelSpec::Subsystem(values) => {
+            let spec: Vec<_> = values
+                .iter()
+                .map(|(system, level)| format!("{}={}", system, level))
+                .collect();
+            serde_json::to_value(spec.join(","))
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct BTCDConfigs {
-    tls: bool,
-    username: Option<String>,
-    password: Option<String>,
-    host: Option<String>,
-    port: Option<usize>,
+    pub(crate) tls: bool,
+    pub(crate) username: Option<String>,
+    pub(crate) password: Option<Secret>,
+    /// Where to get the credentials from, instead of `username` and `password`
+    pub(crate) credentials: Option<Arc<dyn CredentialProvider>>,
+    pub(crate) host: Option<String>,
+    pub(crate) port: Option<usize>,
+    /// The node's own certificate, pinned when using TLS
+    pub(crate) cert: Option<PathBuf>,
+    /// CA certificates used to verify the node's certificate when using TLS
+    pub(crate) ca_bundle: Option<PathBuf>,
+    /// SOCKS5 proxy to connect through, like Tor's `127.0.0.1:9050`
+    pub(crate) proxy: Option<String>,
+    /// Username and password for the SOCKS5 proxy
+    pub(crate) proxy_auth: Option<(String, Secret)>,
+    /// The largest response accepted from the node, in bytes
+    pub(crate) max_response_size: Option<usize>,
+    /// The version of JSON-RPC spoken with the node
+    pub(crate) version: jsonrpc::Version,
+    /// Default timeouts for some methods
+    pub(crate) method_timeouts: Vec<(String, Duration)>,
 }
 
 impl BTCDConfigs {
@@ -265,65 +446,162 @@ impl BTCDConfigs {
         BTCDConfigs {
             tls,
             username,
-            password,
+            password: password.map(Secret::new),
+            credentials: None,
             host,
             port,
+            cert: None,
+            ca_bundle: None,
+            proxy: None,
+            proxy_auth: None,
+            max_response_size: None,
+            version: jsonrpc::Version::V2,
+            method_timeouts: Vec::new(),
         }
     }
-    pub fn set(mut self, opt: Options) {
+    pub fn set(mut self, opt: Options) -> BTCDConfigs {
         match opt {
             Options::Username(username) => self.username = Some(username),
-            Options::Password(password) => self.password = Some(password),
+            Options::Password(password) => self.password = Some(Secret::new(password)),
+            Options::CookieFile(path) => self.credentials = Some(Arc::new(CookieFile::new(path))),
+            Options::Credentials(provider) => self.credentials = Some(provider),
             Options::Hostname(hostname) => self.host = Some(hostname),
             Options::Port(port) => self.port = Some(port),
             Options::TLS(tls) => self.tls = tls,
+            Options::Certificate(cert) => self.cert = Some(cert),
+            Options::CaBundle(bundle) => self.ca_bundle = Some(bundle),
+            Options::Proxy(proxy) => self.proxy = Some(proxy),
+            Options::ProxyAuth(user, pass) => self.proxy_auth = Some((user, Secret::new(pass))),
+            Options::MaxResponseSize(size) => self.max_response_size = Some(size),
+            Options::JsonRpc1(true) => self.version = jsonrpc::Version::V1,
+            Options::JsonRpc1(false) => self.version = jsonrpc::Version::V2,
+            Options::MethodTimeout(method, timeout) => self.method_timeouts.push((method, timeout)),
         }
+        self
+    }
+
+    /// Where the client gets its credentials from: the configured provider, or the user
+    /// name and password.
+    pub(crate) fn credentials(&self) -> Option<Arc<dyn CredentialProvider>> {
+        if let Some(ref provider) = self.credentials {
+            return Some(provider.clone());
+        }
+        let user = self.username.as_ref()?;
+        let password = self.password.clone().unwrap_or_default();
+        Some(Arc::new(StaticCredentials(Credentials::new(
+            user.as_str(),
+            password,
+        ))))
+    }
+
+    /// The port of the node: the configured one, or the one btcd serves RPC on for mainnet.
+    pub(crate) fn port(&self) -> usize {
+        self.port.unwrap_or(Network::Bitcoin.rpc_port() as usize)
     }
 }
 
 pub enum Options {
     Username(String),
     Password(String),
+    /// Path to the node's cookie file, read again when the node rotates it
+    CookieFile(PathBuf),
+    /// Where to get the credentials from, like environment variables or a command, see
+    /// [jsonrpc::auth]
+    Credentials(Arc<dyn CredentialProvider>),
     Hostname(String),
     Port(usize),
     TLS(bool),
+    /// Path to the node's certificate (btcd's `rpc.cert`), pinned when using TLS
+    Certificate(PathBuf),
+    /// Path to a PEM file with the CA certificates that signed the node's certificate
+    CaBundle(PathBuf),
+    /// Address of a SOCKS5 proxy to connect through, as `host:port`. The node's host name is
+    /// resolved by the proxy, so `.onion` nodes can be reached through Tor.
+    Proxy(String),
+    /// Username and password for the SOCKS5 proxy, also used by Tor to isolate streams
+    ProxyAuth(String, String),
+    /// The largest response accepted from the node, in bytes. Defaults to
+    /// [jsonrpc::DEFAULT_MAX_RESPONSE_SIZE]
+    MaxResponseSize(usize),
+    /// Speak JSON-RPC 1.0 with the node, like btcd's `btcjson` clients do. Off by
+    /// default, JSON-RPC 2.0 is used
+    JsonRpc1(bool),
+    /// How long calls to a method may take, instead of the transport's timeout
+    MethodTimeout(String, Duration),
 }
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Network {
     Bitcoin,
     Testnet,
     Signet,
     Regtest,
+    Simnet,
+}
+
+impl Network {
+    /// Returns the port btcd and utreexod serve RPC on by default for this network.
+    pub fn rpc_port(self) -> u16 {
+        match self {
+            Network::Bitcoin => 8334,
+            Network::Testnet | Network::Regtest => 18334,
+            Network::Signet => 38332,
+            Network::Simnet => 18556,
+        }
+    }
 }
 #[cfg(test)]
 mod test {
+    use super::BTCDClient;
+    use jsonrpc::replay::{RecordingTransport, ReplayTransport};
+
+    /// Returns a client answering from the fixture `name` in tests/fixtures. With
+    /// `BTCD_RPC_RECORD` set, the fixture is recorded from a signet btcd on localhost:38332
+    /// instead.
+    fn client(name: &str) -> BTCDClient {
+        let fixture = format!(
+            "{}/tests/fixtures/{}.json",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        );
+        if std::env::var_os("BTCD_RPC_RECORD").is_some() {
+            let transport = jsonrpc::simple_http::Builder::new()
+                .url("localhost:38332")
+                .unwrap()
+                .auth("SomeUsername", Some("CorrectHorseBattleStaple"))
+                .build();
+            return BTCDClient::with_transport(RecordingTransport::new(transport, fixture));
+        }
+        BTCDClient::with_transport(ReplayTransport::open(fixture).unwrap())
+    }
+
+    #[test]
+    fn test_levelspec_value() {
+        use super::levelspec_value;
+        use json_types::general::{DebugLevel, LevelSpec, Subsystem};
+
+        let spec = LevelSpec::Subsystem(vec![
+            (Subsystem::AMGR, DebugLevel::Trace),
+            (Subsystem::PEER, DebugLevel::Debug),
+        ]);
+        assert_eq!(levelspec_value(spec).unwrap(), "AMGR=trace,PEER=debug");
+        let spec = LevelSpec::Subsystem(vec![(Subsystem::BTCD, DebugLevel::Info)]);
+        assert_eq!(levelspec_value(spec).unwrap(), "BTCD=info");
+    }
+
     #[test]
     fn test_basic_command() {
-        use super::{BTCDClient, BTCDConfigs, BtcdRpc};
-        let config = BTCDConfigs::new(
-            false,
-            Some("SomeUsername".into()),
-            Some("CorrectHorseBattleStaple".into()),
-            Some("localhost".into()),
-            Some(38332),
-        );
+        use super::BtcdRpc;
 
-        let client = BTCDClient::new(config).unwrap();
+        let client = client("getinfo");
         let res = client.getinfo();
 
         assert!(res.is_ok());
     }
     #[test]
     fn test_get_block_hash() {
-        use super::{BTCDClient, BTCDConfigs, BtcdRpc};
-        let config = BTCDConfigs::new(
-            false,
-            Some("SomeUsername".into()),
-            Some("CorrectHorseBattleStaple".into()),
-            Some("localhost".into()),
-            Some(38332),
-        );
+        use super::BtcdRpc;
 
-        let client = BTCDClient::new(config).unwrap();
+        let client = client("getblockhash");
         let hash = client.getblockhash(0);
         assert_eq!(
             hash.unwrap(),
@@ -332,17 +610,9 @@ mod test {
     }
     #[test]
     fn test_decoderawtransaction() {
-        use super::{BTCDClient, BTCDConfigs, BtcdRpc};
+        use super::BtcdRpc;
 
-        let config = BTCDConfigs::new(
-            false,
-            Some("SomeUsername".into()),
-            Some("CorrectHorseBattleStaple".into()),
-            Some("localhost".into()),
-            Some(38332),
-        );
-
-        let client = BTCDClient::new(config).unwrap();
+        let client = client("decoderawtransaction");
         let raw_transaction = "020000000001014224f25afde5dd27f5b6c2ebaa6732ba0d2ceabd1e4046eb1c1e59eecda554fa0100000000feffffff0240420f0000000000225120ac2270ec1eac011410b7b0cab2022bcaba9061fe62008fed43b884b4ba1db783dd077e975d0600001600145e1d306e58c306e5e84cfddb4152a73a12d33d4602453042021f19f6cc924d0bed97024a7eac8357a1ddc30443819e1317a4d474757a61fee4021f0b1fcbe9dbb52016f660acae8b61f73cb003cd16119b0a8a453ec01a74231e012102d56ee7e2a5122db55bed1b21ed76078441a8075b27ffe13a8577a93d81c8ccc0f3c70100";
         let raw_transaction = client
             .decoderawtransaction(raw_transaction.into())
@@ -359,18 +629,10 @@ mod test {
     #[cfg(feature = "utreexod")]
     #[test]
     fn test_getutreexoproof() {
-        use super::{BTCDClient, BTCDConfigs, BtcdRpc};
-
-        let config = BTCDConfigs::new(
-            false,
-            Some("SomeUsername".into()),
-            Some("CorrectHorseBattleStaple".into()),
-            Some("localhost".into()),
-            Some(38332),
-        );
+        use super::BtcdRpc;
 
-        let client = BTCDClient::new(config).unwrap();
-        let hash = client.getblockhash(10);
+        let client = client("getutreexoproof");
+        let hash = client.getblockhash(0);
         let proof = client.getutreexoproof(hash.unwrap(), true);
         assert!(proof.is_ok())
     }
@@ -386,10 +648,97 @@ mod test {
             Some(38332),
         );
 
-        assert_eq!(config.tls, false);
+        assert!(!config.tls);
         assert_eq!(config.username, Some("SomeUsername".into()));
-        assert_eq!(config.password, Some("CorrectHorseBattleStaple".into()));
+        assert_eq!(
+            config.password.as_ref().map(|p| p.expose()),
+            Some("CorrectHorseBattleStaple")
+        );
         assert_eq!(config.host, Some("localhost".into()));
         assert_eq!(config.port, Some(38332));
+        assert!(!format!("{:?}", config).contains("CorrectHorseBattleStaple"));
+
+        // Without a port, the client goes to the one of mainnet
+        let config = BTCDConfigs::new(false, None, None, Some("localhost".into()), None);
+        assert_eq!(config.port(), 8334);
+    }
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_tls_without_certificate() {
+        use super::{BTCDClient, BTCDConfigs, Options};
+        use crate::error::UtreexodError;
+
+        let config = BTCDConfigs::new(
+            true,
+            Some("SomeUsername".into()),
+            Some("CorrectHorseBattleStaple".into()),
+            Some("localhost".into()),
+            Some(38332),
+        );
+        match BTCDClient::new(config) {
+            Err(UtreexodError::MissingCertificate) => {}
+            _ => panic!("expected a missing certificate error"),
+        }
+
+        let config = BTCDConfigs::new(false, None, None, Some("localhost".into()), None)
+            .set(Options::TLS(true))
+            .set(Options::Certificate("/nonexistent/rpc.cert".into()));
+        assert!(config.tls);
+        assert!(BTCDClient::new(config).is_err());
+    }
+    #[test]
+    fn test_credentials_config() {
+        use super::{BTCDConfigs, Options};
+        use jsonrpc::auth::{CredentialProvider, Credentials};
+
+        let config = BTCDConfigs::new(false, Some("user".into()), None, None, None);
+        let provider = config.credentials().unwrap();
+        assert_eq!(
+            provider.credentials().unwrap(),
+            Credentials::new("user", "")
+        );
+
+        // A cookie file takes precedence over the user name and password
+        let cookie = std::env::temp_dir().join(format!("btcd_cookie_{}", std::process::id()));
+        std::fs::write(&cookie, "__cookie__:abcdef\n").unwrap();
+        let config = config
+            .set(Options::Password("pass".into()))
+            .set(Options::CookieFile(cookie.clone()));
+        let provider = config.credentials().unwrap();
+        assert_eq!(
+            provider.credentials().unwrap(),
+            Credentials::new("__cookie__", "abcdef")
+        );
+        std::fs::remove_file(&cookie).unwrap();
+
+        assert!(BTCDConfigs::new(false, None, None, None, None)
+            .credentials()
+            .is_none());
+    }
+    #[test]
+    fn test_proxy_config() {
+        use super::{BTCDClient, BTCDConfigs, Options};
+        use crate::error::UtreexodError;
+
+        let onion = Some("abcdefghijklmnop.onion".into());
+        let config = BTCDConfigs::new(false, None, None, onion.clone(), Some(38332))
+            .set(Options::Proxy("socks5h://127.0.0.1:9050".into()))
+            .set(Options::ProxyAuth("user".into(), "isolation".into()));
+        assert_eq!(config.proxy, Some("socks5h://127.0.0.1:9050".into()));
+        let proxy_auth = config
+            .proxy_auth
+            .as_ref()
+            .map(|(u, p)| (u.as_str(), p.expose()));
+        assert_eq!(proxy_auth, Some(("user", "isolation")));
+        assert!(!format!("{:?}", config).contains("isolation"));
+        // The onion address is only resolved by the proxy, once a request is sent
+        assert!(BTCDClient::new(config).is_ok());
+
+        let config = BTCDConfigs::new(false, None, None, onion, Some(38332))
+            .set(Options::Proxy("socks4://127.0.0.1".into()));
+        match BTCDClient::new(config) {
+            Err(UtreexodError::SimpleHttpError(_)) => {}
+            _ => panic!("expected an invalid proxy address"),
+        }
     }
 }
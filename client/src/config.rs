@@ -0,0 +1,441 @@
+//! Loads the RPC settings of a local node from its conf file, like `~/.btcd/btcd.conf`.
+//!
+//! The options the client needs are read from the file: `rpcuser`, `rpcpass`, `rpclisten`,
+//! `rpccert`, `notls` and the network flags `testnet`, `signet`, `regtest` and `simnet`. The
+//! other ones are ignored. Each of them can be overridden by an environment variable named
+//! after it, upper-cased and prefixed with `BTCD_`, or `UTREEXOD_` for utreexod, like
+//! `BTCD_RPCPASS`. The node is reached on the first `rpclisten` address, or on localhost and
+//! the default RPC port of the network.
+//! ```no_run
+//! use btcd_rpc::client::{BTCDClient, BTCDConfigs};
+//! use btcd_rpc::config::Node;
+//!
+//! let cfg = BTCDConfigs::from_conf(Node::Btcd).unwrap();
+//! let client = BTCDClient::new(cfg).unwrap();
+//! ```
+use crate::client::{BTCDConfigs, Network, Options};
+use std::path::{Path, PathBuf};
+use std::{env, error, fmt, fs, io};
+
+/// The node whose conf file is read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Node {
+    Btcd,
+    Utreexod,
+}
+
+impl Node {
+    fn name(self) -> &'static str {
+        match self {
+            Node::Btcd => "btcd",
+            Node::Utreexod => "utreexod",
+        }
+    }
+
+    /// Returns the default data directory of the node: `~/.btcd` on Unix,
+    /// `~/Library/Application Support/Btcd` on macOS and `%LOCALAPPDATA%\Btcd` on Windows.
+    pub fn data_dir(self) -> Option<PathBuf> {
+        let name = self.name();
+        let capitalized = format!("{}{}", name[..1].to_uppercase(), &name[1..]);
+        if cfg!(windows) {
+            let dir = env::var_os("LOCALAPPDATA").or_else(|| env::var_os("APPDATA"))?;
+            return Some(PathBuf::from(dir).join(capitalized));
+        }
+        let home = PathBuf::from(env::var_os("HOME")?);
+        if cfg!(target_os = "macos") {
+            Some(home.join("Library/Application Support").join(capitalized))
+        } else {
+            Some(home.join(format!(".{}", name)))
+        }
+    }
+
+    /// Returns the path of the conf file in the default data directory.
+    pub fn conf_file(self) -> Option<PathBuf> {
+        Some(self.data_dir()?.join(format!("{}.conf", self.name())))
+    }
+
+    fn env_prefix(self) -> &'static str {
+        match self {
+            Node::Btcd => "BTCD_",
+            Node::Utreexod => "UTREEXOD_",
+        }
+    }
+}
+
+/// The RPC settings found in a conf file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfFile {
+    pub rpcuser: Option<String>,
+    pub rpcpass: Option<String>,
+    /// The first `rpclisten` address
+    pub rpclisten: Option<String>,
+    pub rpccert: Option<PathBuf>,
+    pub notls: bool,
+    pub network: Option<Network>,
+}
+
+impl ConfFile {
+    /// Reads and parses the conf file at `path`.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<ConfFile, ConfigError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|error| ConfigError::Io {
+            path: path.to_owned(),
+            error,
+        })?;
+        ConfFile::parse(&contents)
+    }
+
+    /// Parses the contents of a conf file: `key=value` lines, with `;` and `#` comments and
+    /// `[section]` headers, which are ignored.
+    pub fn parse(contents: &str) -> Result<ConfFile, ConfigError> {
+        let mut conf = ConfFile::default();
+        let mut networks = Vec::new();
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                continue;
+            }
+            let (key, value) = match line.find('=') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => (line, ""),
+            };
+            let value = value.trim_matches('"');
+            if key == "rpclisten" && conf.rpclisten.is_some() {
+                continue;
+            }
+            if let Some(network) = conf.set(key, value)? {
+                networks.push(network);
+            }
+        }
+        conf.network = only_network(&networks)?;
+        Ok(conf)
+    }
+
+    /// Overrides the settings with the environment variables of `node`, like `BTCD_RPCUSER`.
+    /// Setting a network flag there replaces the network of the file, and clearing the
+    /// file's one, like `BTCD_TESTNET=0`, goes back to mainnet.
+    pub fn with_env(self, node: Node) -> Result<ConfFile, ConfigError> {
+        self.with_vars(node.env_prefix(), |name| env::var(name).ok())
+    }
+
+    fn with_vars<F: Fn(&str) -> Option<String>>(
+        mut self,
+        prefix: &str,
+        var: F,
+    ) -> Result<ConfFile, ConfigError> {
+        let mut networks = Vec::new();
+        for key in KEYS {
+            if let Some(value) = var(&format!("{}{}", prefix, key.to_uppercase())) {
+                let network = flag_network(key);
+                if network.is_some() && network == self.network && !parse_bool(key, &value)? {
+                    self.network = None;
+                }
+                if let Some(network) = self.set(key, &value)? {
+                    networks.push(network);
+                }
+            }
+        }
+        if !networks.is_empty() {
+            self.network = only_network(&networks)?;
+        }
+        Ok(self)
+    }
+
+    /// Sets the option `key`, returning the network it selects if it's a network flag.
+    fn set(&mut self, key: &str, value: &str) -> Result<Option<Network>, ConfigError> {
+        match key {
+            "rpcuser" => self.rpcuser = Some(value.to_owned()),
+            "rpcpass" => self.rpcpass = Some(value.to_owned()),
+            "rpclisten" => self.rpclisten = Some(value.to_owned()),
+            "rpccert" => self.rpccert = Some(expand_home(value)),
+            "notls" => self.notls = parse_bool(key, value)?,
+            "testnet" | "signet" | "regtest" | "simnet" if parse_bool(key, value)? => {
+                return Ok(flag_network(key));
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    /// Returns the address to reach the node at: the `rpclisten` address, with localhost
+    /// instead of an unspecified or missing host, and the default port of the network if
+    /// there's none.
+    pub fn rpc_address(&self) -> Result<(String, u16), ConfigError> {
+        let default_port = self.network.unwrap_or(Network::Bitcoin).rpc_port();
+        let listen = match self.rpclisten {
+            Some(ref listen) => listen.as_str(),
+            None => return Ok(("127.0.0.1".to_owned(), default_port)),
+        };
+        let invalid = |reason| ConfigError::Invalid {
+            key: "rpclisten".to_owned(),
+            value: listen.to_owned(),
+            reason,
+        };
+        let (host, port) = if listen.starts_with('[') {
+            let end = listen.find(']').ok_or_else(|| invalid("missing ']'"))?;
+            (&listen[..=end], listen[end + 1..].strip_prefix(':'))
+        } else if listen.matches(':').count() > 1 {
+            // A bare IPv6 address, without port
+            (listen, None)
+        } else {
+            match listen.rfind(':') {
+                Some(i) => (&listen[..i], Some(&listen[i + 1..])),
+                None => (listen, None),
+            }
+        };
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| invalid("invalid port"))?,
+            None => default_port,
+        };
+        let host = match host {
+            "" | "0.0.0.0" => "127.0.0.1".to_owned(),
+            "[::]" | "::" => "[::1]".to_owned(),
+            host if host.contains(':') && !host.starts_with('[') => format!("[{}]", host),
+            host => host.to_owned(),
+        };
+        Ok((host, port))
+    }
+
+    /// Returns the client configuration for these settings. Unless TLS is off, the node's
+    /// certificate is pinned: `rpccert`, or `rpc.cert` in `data_dir`.
+    pub fn to_configs(&self, data_dir: Option<&Path>) -> Result<BTCDConfigs, ConfigError> {
+        let (host, port) = self.rpc_address()?;
+        let mut cfg = BTCDConfigs::new(
+            !self.notls,
+            self.rpcuser.clone(),
+            self.rpcpass.clone(),
+            Some(host),
+            Some(port as usize),
+        );
+        if !self.notls {
+            let cert = self
+                .rpccert
+                .clone()
+                .or_else(|| data_dir.map(|dir| dir.join("rpc.cert")));
+            if let Some(cert) = cert {
+                cfg = cfg.set(Options::Certificate(cert));
+            }
+        }
+        Ok(cfg)
+    }
+}
+
+/// The options read from conf files and the environment.
+const KEYS: &[&str] = &[
+    "rpcuser",
+    "rpcpass",
+    "rpclisten",
+    "rpccert",
+    "notls",
+    "testnet",
+    "signet",
+    "regtest",
+    "simnet",
+];
+
+/// Returns the network a network flag selects.
+fn flag_network(key: &str) -> Option<Network> {
+    match key {
+        "testnet" => Some(Network::Testnet),
+        "signet" => Some(Network::Signet),
+        "regtest" => Some(Network::Regtest),
+        "simnet" => Some(Network::Simnet),
+        _ => None,
+    }
+}
+
+/// Returns the network selected by the flags, btcd refuses to start with several.
+fn only_network(networks: &[Network]) -> Result<Option<Network>, ConfigError> {
+    match networks {
+        [] => Ok(None),
+        [network, rest @ ..] if rest.iter().all(|n| n == network) => Ok(Some(*network)),
+        _ => Err(ConfigError::Invalid {
+            key: "network".to_owned(),
+            value: format!("{:?}", networks),
+            reason: "only one network can be selected",
+        }),
+    }
+}
+
+/// Parses a boolean like btcd does. A flag without value is set.
+fn parse_bool(key: &str, value: &str) -> Result<bool, ConfigError> {
+    match value {
+        "" | "1" | "t" | "T" | "true" | "TRUE" | "True" => Ok(true),
+        "0" | "f" | "F" | "false" | "FALSE" | "False" => Ok(false),
+        _ => Err(ConfigError::Invalid {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            reason: "not a boolean",
+        }),
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+impl BTCDConfigs {
+    /// Loads the configuration of a local `node` from its conf file in the default data
+    /// directory, if there's one, and the environment. See [crate::config].
+    pub fn from_conf(node: Node) -> Result<BTCDConfigs, ConfigError> {
+        let data_dir = node.data_dir().ok_or(ConfigError::NoDataDir)?;
+        let path = data_dir.join(format!("{}.conf", node.name()));
+        let conf = if path.exists() {
+            ConfFile::read(&path)?
+        } else {
+            ConfFile::default()
+        };
+        conf.with_env(node)?.to_configs(Some(&data_dir))
+    }
+
+    /// Loads the configuration of `node` from the conf file at `path` and the environment.
+    /// The certificate is looked for next to the file.
+    pub fn from_conf_file<P: AsRef<Path>>(node: Node, path: P) -> Result<BTCDConfigs, ConfigError> {
+        let path = path.as_ref();
+        let conf = ConfFile::read(path)?.with_env(node)?;
+        conf.to_configs(path.parent())
+    }
+}
+
+/// Error while loading a conf file.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The conf file couldn't be read
+    Io { path: PathBuf, error: io::Error },
+    /// An option of the conf file or the environment has an invalid value
+    Invalid {
+        key: String,
+        value: String,
+        reason: &'static str,
+    },
+    /// The default data directory is unknown, since the home directory isn't set
+    NoDataDir,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => {
+                write!(f, "couldn't read '{}': {}", path.display(), error)
+            }
+            ConfigError::Invalid { key, value, reason } => {
+                write!(f, "invalid {} '{}': {}", key, value, reason)
+            }
+            ConfigError::NoDataDir => f.write_str("couldn't find the home directory"),
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ConfigError::Io { error, .. } => Some(error),
+            ConfigError::Invalid { .. } | ConfigError::NoDataDir => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn parses_conf_file() {
+        let conf = ConfFile::parse(
+            "[Application Options]\n\
+             ; rpcuser=commented\n\
+             rpcuser = alice\n\
+             rpcpass=\"secret=1\"\n\
+             rpclisten=:18335\n\
+             rpclisten=127.0.0.2\n\
+             testnet=1\n\
+             notls\n\
+             debuglevel=info\n",
+        )
+        .unwrap();
+        assert_eq!(conf.rpcuser.as_deref(), Some("alice"));
+        assert_eq!(conf.rpcpass.as_deref(), Some("secret=1"));
+        assert_eq!(conf.network, Some(Network::Testnet));
+        assert!(conf.notls);
+        assert_eq!(conf.rpc_address().unwrap(), ("127.0.0.1".to_owned(), 18335));
+
+        assert!(ConfFile::parse("testnet=1\nregtest=1\n").is_err());
+        assert!(ConfFile::parse("notls=maybe\n").is_err());
+    }
+
+    #[test]
+    fn rpc_addresses() {
+        let address = |listen: &str, network| {
+            ConfFile {
+                rpclisten: Some(listen.to_owned()),
+                network,
+                ..ConfFile::default()
+            }
+            .rpc_address()
+        };
+        let expect = |host: &str, port| (host.to_owned(), port);
+        assert_eq!(
+            address("10.0.0.1:1234", None).unwrap(),
+            expect("10.0.0.1", 1234)
+        );
+        assert_eq!(address("0.0.0.0", None).unwrap(), expect("127.0.0.1", 8334));
+        let signet = Some(Network::Signet);
+        assert_eq!(address("[::]:8000", signet).unwrap(), expect("[::1]", 8000));
+        let simnet = Some(Network::Simnet);
+        assert_eq!(address("::1", simnet).unwrap(), expect("[::1]", 18556));
+        assert!(address("localhost:port", None).is_err());
+
+        let conf = ConfFile {
+            network: Some(Network::Signet),
+            ..ConfFile::default()
+        };
+        assert_eq!(conf.rpc_address().unwrap(), ("127.0.0.1".to_owned(), 38332));
+    }
+
+    #[test]
+    fn environment_wins() {
+        let conf =
+            ConfFile::parse("rpcuser=alice\nrpcpass=file\ntestnet=1\nrpccert=/a.cert\n").unwrap();
+        let vars: HashMap<_, _> = vec![
+            ("UTREEXOD_RPCPASS", "env"),
+            ("UTREEXOD_REGTEST", "1"),
+            ("UTREEXOD_NOTLS", "1"),
+        ]
+        .into_iter()
+        .collect();
+        let conf = conf
+            .with_vars("UTREEXOD_", |name| vars.get(name).map(|v| v.to_string()))
+            .unwrap();
+        assert_eq!(conf.rpcuser.as_deref(), Some("alice"));
+        assert_eq!(conf.rpcpass.as_deref(), Some("env"));
+        assert_eq!(conf.network, Some(Network::Regtest));
+
+        let cfg = conf.to_configs(Some(Path::new("/data"))).unwrap();
+        assert!(!cfg.tls);
+        assert_eq!(cfg.port, Some(18334));
+        assert_eq!(cfg.cert, None);
+
+        // Clearing the file's network flag goes back to mainnet
+        let conf = ConfFile::parse("testnet=1\n").unwrap();
+        let vars: HashMap<_, _> = vec![("BTCD_TESTNET", "0"), ("BTCD_SIMNET", "0")]
+            .into_iter()
+            .collect();
+        let conf = conf
+            .with_vars("BTCD_", |name| vars.get(name).map(|v| v.to_string()))
+            .unwrap();
+        assert_eq!(conf.network, None);
+        assert_eq!(conf.rpc_address().unwrap().1, 8334);
+
+        let cfg = ConfFile::default()
+            .to_configs(Some(Path::new("/data")))
+            .unwrap();
+        assert!(cfg.tls);
+        assert_eq!(cfg.cert, Some(PathBuf::from("/data/rpc.cert")));
+    }
+}
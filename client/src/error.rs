@@ -1,10 +1,242 @@
+use std::{error, fmt};
+
+use crate::config::ConfigError;
+use jsonrpc::error::RpcError;
 use jsonrpc::simple_http;
+pub use jsonrpc::ErrorKind;
+
 #[derive(Debug)]
 pub enum UtreexodError {
+    /// The call failed in the JSON-RPC layer, or the node answered with an error
     JsonRpcError(jsonrpc::Error),
+    /// The HTTP transport couldn't be set up
     SimpleHttpError(simple_http::Error),
+    /// The WebSocket connection couldn't be set up
+    #[cfg(feature = "ws")]
+    WebSocketError(jsonrpc::simple_ws::Error),
+    /// The result of the call couldn't be deserialized
     DeserializationError(serde_json::Error),
+    /// The node answered without a result
     EmptyResponseFromServer,
+    /// TLS was requested, but no certificate or CA bundle was provided
+    MissingCertificate,
+    /// TLS was requested, but this crate was built without the `tls` feature
+    TlsNotEnabled,
+    /// A proxy was configured for a client that can't connect through one
+    ProxyNotSupported,
+    /// The conf file of the node couldn't be loaded
+    ConfigError(ConfigError),
+    /// The result of a call was looked up in the results of another batch
+    WrongBatch,
+}
+
+impl UtreexodError {
+    /// Returns the kind of the error: connection, timeout, auth, protocol, RPC error of the
+    /// node, deserialization...
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            UtreexodError::JsonRpcError(e) => e.kind(),
+            UtreexodError::SimpleHttpError(e) => e.kind(),
+            #[cfg(feature = "ws")]
+            UtreexodError::WebSocketError(e) => e.kind(),
+            UtreexodError::DeserializationError(_) => ErrorKind::Deserialization,
+            UtreexodError::EmptyResponseFromServer => ErrorKind::Protocol,
+            UtreexodError::MissingCertificate
+            | UtreexodError::TlsNotEnabled
+            | UtreexodError::ProxyNotSupported
+            | UtreexodError::ConfigError(_) => ErrorKind::Config,
+            UtreexodError::WrongBatch => ErrorKind::Other,
+        }
+    }
+
+    /// Returns whether making the call again may succeed, like after a connection error or
+    /// while the node is warming up. This doesn't tell whether the node ran the first one.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            UtreexodError::JsonRpcError(e) => e.is_retryable(),
+            UtreexodError::SimpleHttpError(e) => e.is_retryable(),
+            #[cfg(feature = "ws")]
+            UtreexodError::WebSocketError(e) => e.is_retryable(),
+            _ => false,
+        }
+    }
+
+    /// Returns the error the node answered with, if it did.
+    pub fn rpc_error(&self) -> Option<&RpcError> {
+        match self {
+            UtreexodError::JsonRpcError(jsonrpc::Error::Rpc(e)) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Returns the code of the error the node answered with, if it did.
+    pub fn rpc_code(&self) -> Option<RpcErrorCode> {
+        self.rpc_error().map(RpcErrorCode::from_error)
+    }
+}
+
+impl fmt::Display for UtreexodError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UtreexodError::JsonRpcError(jsonrpc::Error::Rpc(e)) => {
+                write!(f, "node error {}: {}", e.code, e.message)
+            }
+            UtreexodError::JsonRpcError(e) => write!(f, "{}", e),
+            UtreexodError::SimpleHttpError(e) => write!(f, "HTTP transport error: {}", e),
+            #[cfg(feature = "ws")]
+            UtreexodError::WebSocketError(e) => write!(f, "WebSocket error: {}", e),
+            UtreexodError::DeserializationError(e) => {
+                write!(f, "couldn't deserialize the result: {}", e)
+            }
+            UtreexodError::EmptyResponseFromServer => f.write_str("the node sent no result"),
+            UtreexodError::MissingCertificate => {
+                f.write_str("TLS requires a certificate or a CA bundle")
+            }
+            UtreexodError::TlsNotEnabled => f.write_str("TLS support wasn't built in"),
+            UtreexodError::ProxyNotSupported => f.write_str("this client can't use a proxy"),
+            UtreexodError::ConfigError(e) => write!(f, "couldn't load the configuration: {}", e),
+            UtreexodError::WrongBatch => f.write_str("the call wasn't queued in this batch"),
+        }
+    }
+}
+
+impl error::Error for UtreexodError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            // The node's error is all there is to it
+            UtreexodError::JsonRpcError(jsonrpc::Error::Rpc(_)) => None,
+            UtreexodError::JsonRpcError(e) => Some(e),
+            UtreexodError::SimpleHttpError(e) => Some(e),
+            #[cfg(feature = "ws")]
+            UtreexodError::WebSocketError(e) => Some(e),
+            UtreexodError::DeserializationError(e) => Some(e),
+            UtreexodError::ConfigError(e) => Some(e),
+            UtreexodError::EmptyResponseFromServer
+            | UtreexodError::MissingCertificate
+            | UtreexodError::TlsNotEnabled
+            | UtreexodError::ProxyNotSupported
+            | UtreexodError::WrongBatch => None,
+        }
+    }
+}
+
+/// The errors btcd answers with, from their code. btcd uses some codes for several errors,
+/// those are told apart by their message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RpcErrorCode {
+    /// -1, any other error
+    Misc,
+    /// -1, a block height out of range
+    OutOfRange,
+    /// -2, the node is in safe mode
+    ForbiddenBySafeMode,
+    /// -3, a parameter has the wrong type
+    Type,
+    /// -4, a wallet error
+    Wallet,
+    /// -5, an invalid address or key
+    InvalidAddressOrKey,
+    /// -5, the block isn't known to the node
+    BlockNotFound,
+    /// -5, the transaction isn't known to the node
+    NoTxInfo,
+    /// -7, the node ran out of memory
+    OutOfMemory,
+    /// -8, a parameter has an invalid value
+    InvalidParameter,
+    /// -9, the node isn't connected to any peer
+    ClientNotConnected,
+    /// -10, the node is still downloading the initial blocks
+    ClientInInitialDownload,
+    /// -20, a database error
+    Database,
+    /// -22, a transaction or block couldn't be decoded
+    Deserialization,
+    /// -24, the peer wasn't added with `addnode`
+    NodeNotAdded,
+    /// -25, a transaction or block was rejected
+    Verify,
+    /// -28, the node is still starting up
+    InWarmup,
+    /// -32700, the node couldn't parse the request
+    ParseError,
+    /// -32600, the request isn't a valid JSON-RPC request
+    InvalidRequest,
+    /// -32601, the node doesn't know the method
+    MethodNotFound,
+    /// -32602, the parameters don't fit the method
+    InvalidParams,
+    /// -32603, an internal error of the node
+    InternalError,
+    /// A code btcd doesn't use
+    Unknown(i32),
+}
+
+impl RpcErrorCode {
+    /// Returns the code of an error the node answered with.
+    pub fn from_error(error: &RpcError) -> RpcErrorCode {
+        let message = error.message.as_str();
+        match error.code {
+            -1 if message.contains("out of range") => RpcErrorCode::OutOfRange,
+            -1 => RpcErrorCode::Misc,
+            -2 => RpcErrorCode::ForbiddenBySafeMode,
+            -3 => RpcErrorCode::Type,
+            -4 => RpcErrorCode::Wallet,
+            -5 if message.starts_with("Block not found") => RpcErrorCode::BlockNotFound,
+            -5 if message.starts_with("No information available") => RpcErrorCode::NoTxInfo,
+            -5 => RpcErrorCode::InvalidAddressOrKey,
+            -7 => RpcErrorCode::OutOfMemory,
+            -8 => RpcErrorCode::InvalidParameter,
+            -9 => RpcErrorCode::ClientNotConnected,
+            -10 => RpcErrorCode::ClientInInitialDownload,
+            -20 => RpcErrorCode::Database,
+            -22 => RpcErrorCode::Deserialization,
+            -24 => RpcErrorCode::NodeNotAdded,
+            -25 => RpcErrorCode::Verify,
+            -28 => RpcErrorCode::InWarmup,
+            -32700 => RpcErrorCode::ParseError,
+            -32600 => RpcErrorCode::InvalidRequest,
+            -32601 => RpcErrorCode::MethodNotFound,
+            -32602 => RpcErrorCode::InvalidParams,
+            -32603 => RpcErrorCode::InternalError,
+            code => RpcErrorCode::Unknown(code),
+        }
+    }
+
+    /// Returns the numeric code.
+    pub fn code(self) -> i32 {
+        match self {
+            RpcErrorCode::Misc | RpcErrorCode::OutOfRange => -1,
+            RpcErrorCode::ForbiddenBySafeMode => -2,
+            RpcErrorCode::Type => -3,
+            RpcErrorCode::Wallet => -4,
+            RpcErrorCode::InvalidAddressOrKey
+            | RpcErrorCode::BlockNotFound
+            | RpcErrorCode::NoTxInfo => -5,
+            RpcErrorCode::OutOfMemory => -7,
+            RpcErrorCode::InvalidParameter => -8,
+            RpcErrorCode::ClientNotConnected => -9,
+            RpcErrorCode::ClientInInitialDownload => -10,
+            RpcErrorCode::Database => -20,
+            RpcErrorCode::Deserialization => -22,
+            RpcErrorCode::NodeNotAdded => -24,
+            RpcErrorCode::Verify => -25,
+            RpcErrorCode::InWarmup => -28,
+            RpcErrorCode::ParseError => -32700,
+            RpcErrorCode::InvalidRequest => -32600,
+            RpcErrorCode::MethodNotFound => -32601,
+            RpcErrorCode::InvalidParams => -32602,
+            RpcErrorCode::InternalError => -32603,
+            RpcErrorCode::Unknown(code) => code,
+        }
+    }
+
+    /// Returns whether the node may run the call if it's made again shortly, like once it's
+    /// done warming up. These are the codes of [jsonrpc::retry::DEFAULT_RETRY_CODES], which
+    /// [jsonrpc::Error::is_retryable] and the retrying transport go by too.
+    pub fn is_retryable(self) -> bool {
+        jsonrpc::retry::DEFAULT_RETRY_CODES.contains(&self.code())
+    }
 }
 
 impl From<jsonrpc::Error> for UtreexodError {
@@ -18,8 +250,19 @@ impl From<simple_http::Error> for UtreexodError {
         UtreexodError::SimpleHttpError(error)
     }
 }
+#[cfg(feature = "ws")]
+impl From<jsonrpc::simple_ws::Error> for UtreexodError {
+    fn from(error: jsonrpc::simple_ws::Error) -> Self {
+        UtreexodError::WebSocketError(error)
+    }
+}
 impl From<serde_json::Error> for UtreexodError {
     fn from(error: serde_json::Error) -> Self {
         UtreexodError::DeserializationError(error)
     }
 }
+impl From<ConfigError> for UtreexodError {
+    fn from(error: ConfigError) -> Self {
+        UtreexodError::ConfigError(error)
+    }
+}
@@ -1,5 +1,11 @@
+#[cfg(feature = "async")]
+pub mod async_client;
+pub mod batch;
 pub mod client;
+pub mod config;
 pub mod error;
+#[cfg(feature = "ws")]
+pub mod notifications;
 pub use json_types;
 /// Some RPCs requires a given block, usually as a hash. But we might only have a height.
 /// In order to save some time while programming, instead of asking for a hash and then
@@ -14,16 +20,16 @@ pub enum QueryBlock {
 
 #[macro_export]
 macro_rules! impl_verbosity_bool {
-    ($self: ident, $cmd: literal, $params: expr, $verbosity: ident) => {
+    ($self: ident, $cmd: literal, $params: expr, $verbosity: ident $(, $await: tt)?) => {
         match $verbosity {
             true => {
                 let verbosity = serde_json::to_value(true)?;
-                let rpc_res = $self.call($cmd, &[$params, verbosity])?;
+                let rpc_res = $self.call($cmd, &[$params, verbosity])$(.$await)? ?;
                 Ok(VerbosityOutput::Verbose(rpc_res))
             }
             false => {
                 let verbosity = serde_json::to_value(false)?;
-                let rpc_res = $self.call($cmd, &[$params, verbosity])?;
+                let rpc_res = $self.call($cmd, &[$params, verbosity])$(.$await)? ?;
                 Ok(VerbosityOutput::Simple(rpc_res))
             }
         }
@@ -31,18 +37,18 @@ macro_rules! impl_verbosity_bool {
 }
 #[macro_export]
 macro_rules! impl_verbosity_level {
-    ($self: ident, $cmd: literal, $params: expr, $verbosity: ident) => {
+    ($self: ident, $cmd: literal, $params: expr, $verbosity: ident $(, $await: tt)?) => {
         match $verbosity {
             true => {
                 let verbosity = serde_json::to_value(1)?;
-                let rpc_res = $self.call($cmd, &[$params, verbosity])?;
+                let rpc_res = $self.call($cmd, &[$params, verbosity])$(.$await)? ?;
                 Ok(VerbosityOutput::Verbose(rpc_res))
             }
             false => {
                 let verbosity = serde_json::to_value(0)?;
-                let rpc_res = $self.call($cmd, &[$params, verbosity])?;
+                let rpc_res = $self.call($cmd, &[$params, verbosity])$(.$await)? ?;
                 Ok(VerbosityOutput::Simple(rpc_res))
             }
         }
     };
-}
\ No newline at end of file
+}
@@ -0,0 +1,415 @@
+//! Subscriptions to the notifications btcd sends over its websocket endpoint. A [BtcdNotifier]
+//! is a regular client, so every RPC is available on it, that can also subscribe to new blocks,
+//! new transactions or to transactions touching some addresses and outpoints. Notifications
+//! arrive on a channel, and subscriptions are renewed each time the connection is reopened.
+use crate::client::{BTCDClient, BTCDConfigs, BtcdRpc};
+use crate::error::UtreexodError;
+use json_types::notifications::{Notification, RescannedBlock, WatchedOutpoint};
+use jsonrpc::simple_ws::{self, Event, WsTransport};
+use serde_json::Value;
+use std::sync::{mpsc, Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+type Result<T> = std::result::Result<T, UtreexodError>;
+
+/// How long to wait before trying to reconnect, if renewing subscriptions failed
+const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A subscription to renew after reconnecting.
+struct Subscription {
+    method: &'static str,
+    params: Vec<Value>,
+}
+
+struct Inner {
+    client: BTCDClient,
+    /// The subscriptions made so far, in order
+    subscriptions: Mutex<Vec<Subscription>>,
+}
+
+impl Inner {
+    /// Sends a subscription request and records it if the node accepted it. `replaces`
+    /// tells whether it overrides former subscriptions made with the same method.
+    fn subscribe(&self, method: &'static str, params: Vec<Value>, replaces: bool) -> Result<()> {
+        let mut subscriptions = self.subscriptions.lock().unwrap_or_else(|e| e.into_inner());
+        self.client.call::<Value>(method, &params)?;
+        if replaces {
+            subscriptions.retain(|s| s.method != method);
+        }
+        subscriptions.push(Subscription { method, params });
+        Ok(())
+    }
+
+    /// Sends all subscriptions again, on a new connection.
+    fn resubscribe(&self) -> Result<()> {
+        let subscriptions = self.subscriptions.lock().unwrap_or_else(|e| e.into_inner());
+        for subscription in subscriptions.iter() {
+            self.client
+                .call::<Value>(subscription.method, &subscription.params)?;
+        }
+        Ok(())
+    }
+}
+
+/// A client connected to btcd's websocket, that can subscribe to notifications.
+pub struct BtcdNotifier {
+    inner: Arc<Inner>,
+}
+
+impl BtcdNotifier {
+    /// Connects to btcd's websocket endpoint. Returns the client, and the receiving end of
+    /// the channel notifications will be sent to.
+    pub fn new(cfg: BTCDConfigs) -> Result<(BtcdNotifier, mpsc::Receiver<Notification>)> {
+        let scheme = if cfg.tls { "wss" } else { "ws" };
+        let url = format!(
+            "{}://{}:{}",
+            scheme,
+            cfg.host.as_ref().expect("No hostname provided"),
+            cfg.port()
+        );
+        let mut builder = simple_ws::Builder::new().url(&url)?;
+        if let Some(provider) = cfg.credentials() {
+            builder = builder.credentials(provider);
+        }
+        if let Some(size) = cfg.max_response_size {
+            builder = builder.max_response_size(size);
+        }
+        if let Some(proxy) = cfg.proxy {
+            builder = builder.proxy_addr(proxy)?;
+            if let Some((user, pass)) = cfg.proxy_auth {
+                builder = builder.proxy_auth(user.as_str(), pass.expose());
+            }
+        }
+        if cfg.tls {
+            builder = BtcdNotifier::with_tls(builder, cfg.cert, cfg.ca_bundle)?;
+        }
+        let transport = builder.build();
+        let events = transport.events();
+        let client = BTCDClient::with_transport(transport).with_version(cfg.version);
+        let client = client.with_method_timeouts(cfg.method_timeouts);
+        Ok(BtcdNotifier::with_client(client, events))
+    }
+
+    #[cfg(feature = "tls")]
+    fn with_tls(
+        builder: simple_ws::Builder,
+        cert: Option<std::path::PathBuf>,
+        ca_bundle: Option<std::path::PathBuf>,
+    ) -> Result<simple_ws::Builder> {
+        match (cert, ca_bundle) {
+            (Some(cert), _) => Ok(builder.tls_pinned_cert(cert)?),
+            (None, Some(bundle)) => Ok(builder.tls_ca_bundle(bundle)?),
+            (None, None) => Err(UtreexodError::MissingCertificate),
+        }
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn with_tls(
+        _builder: simple_ws::Builder,
+        _cert: Option<std::path::PathBuf>,
+        _ca_bundle: Option<std::path::PathBuf>,
+    ) -> Result<simple_ws::Builder> {
+        Err(UtreexodError::TlsNotEnabled)
+    }
+
+    /// Uses an already configured websocket transport.
+    pub fn with_transport(transport: WsTransport) -> (BtcdNotifier, mpsc::Receiver<Notification>) {
+        let events = transport.events();
+        BtcdNotifier::with_client(BTCDClient::with_transport(transport), events)
+    }
+
+    /// Makes the calls with `client`, whose transport sends its events to `events`.
+    fn with_client(
+        client: BTCDClient,
+        events: mpsc::Receiver<Event>,
+    ) -> (BtcdNotifier, mpsc::Receiver<Notification>) {
+        let inner = Arc::new(Inner {
+            client,
+            subscriptions: Mutex::new(Vec::new()),
+        });
+        let (tx, rx) = mpsc::channel();
+        let weak = Arc::downgrade(&inner);
+        thread::spawn(move || forward(weak, events, tx));
+        (BtcdNotifier { inner }, rx)
+    }
+
+    /// Asks for [Notification::BlockConnected] and [Notification::BlockDisconnected] (or their
+    /// filtered versions, if a filter is loaded) each time the chain tip changes.
+    pub fn notifyblocks(&self) -> Result<()> {
+        self.inner.subscribe("notifyblocks", vec![], true)
+    }
+    /// Asks for a notification each time a transaction is accepted to the mempool. If
+    /// `verbose` is set, [Notification::TxAcceptedVerbose] is sent with the whole transaction,
+    /// otherwise [Notification::TxAccepted] only carries its id and amount.
+    pub fn notifynewtransactions(&self, verbose: bool) -> Result<()> {
+        self.inner
+            .subscribe("notifynewtransactions", vec![Value::from(verbose)], true)
+    }
+    /// Asks for a [Notification::RedeemingTx] when any of `outpoints` is spent.
+    pub fn notifyspent(&self, outpoints: Vec<WatchedOutpoint>) -> Result<()> {
+        let outpoints = serde_json::to_value(outpoints)?;
+        self.inner.subscribe("notifyspent", vec![outpoints], false)
+    }
+    /// Asks for a [Notification::RecvTx] when a transaction pays to any of `addresses`.
+    pub fn notifyreceived(&self, addresses: Vec<String>) -> Result<()> {
+        let addresses = serde_json::to_value(addresses)?;
+        self.inner
+            .subscribe("notifyreceived", vec![addresses], false)
+    }
+    /// Loads a filter used by [Notification::RelevantTxAccepted], filtered block notifications
+    /// and `rescanblocks`. If `reload` is set, the filter replaces the current one, otherwise
+    /// `addresses` and `outpoints` are added to it.
+    pub fn loadtxfilter(
+        &self,
+        reload: bool,
+        addresses: Vec<String>,
+        outpoints: Vec<WatchedOutpoint>,
+    ) -> Result<()> {
+        let params = vec![
+            Value::from(reload),
+            serde_json::to_value(addresses)?,
+            serde_json::to_value(outpoints)?,
+        ];
+        self.inner.subscribe("loadtxfilter", params, reload)
+    }
+    /// Scans the given blocks for transactions matching the loaded filter.
+    pub fn rescanblocks(&self, blockhashes: Vec<String>) -> Result<Vec<RescannedBlock>> {
+        let blockhashes = serde_json::to_value(blockhashes)?;
+        self.inner.client.call("rescanblocks", &[blockhashes])
+    }
+}
+
+impl BtcdRpc for BtcdNotifier {
+    fn call<T: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        command: &'static str,
+        args: &[Value],
+    ) -> Result<T> {
+        self.inner.client.call(command, args)
+    }
+}
+
+/// Parses notifications and sends them to `tx`, renewing subscriptions when the connection
+/// drops. Runs until the notifier is dropped.
+fn forward(inner: Weak<Inner>, events: mpsc::Receiver<Event>, tx: mpsc::Sender<Notification>) {
+    for event in events {
+        match event {
+            Event::Notification(notification) => {
+                let params: Value =
+                    serde_json::from_str(notification.params.get()).unwrap_or(Value::Null);
+                let parsed = Notification::parse(&notification.method, params.clone()).unwrap_or(
+                    Notification::Other {
+                        method: notification.method,
+                        params,
+                    },
+                );
+                // Nobody listens anymore, but subscriptions are still renewed for the
+                // calls made on the notifier.
+                let _ = tx.send(parsed);
+            }
+            Event::Disconnected => loop {
+                let inner = match inner.upgrade() {
+                    Some(inner) => inner,
+                    None => return,
+                };
+                if inner.resubscribe().is_ok() {
+                    break;
+                }
+                drop(inner);
+                thread::sleep(RESUBSCRIBE_INTERVAL);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BtcdNotifier;
+    use crate::client::{BTCDConfigs, Options};
+    use crate::error::ErrorKind;
+    use json_types::notifications::{Notification, WatchedOutpoint};
+    use jsonrpc::simple_ws::{ServerConnection, WsTransport};
+    use serde_json::{json, Value};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Accepts a connection and completes its websocket handshake.
+    fn accept(server: &TcpListener) -> ServerConnection<TcpStream> {
+        let (stream, _) = server.accept().unwrap();
+        ServerConnection::accept(stream).unwrap()
+    }
+
+    /// Reads a request sent by the client.
+    fn read_message(conn: &mut ServerConnection<TcpStream>) -> Value {
+        serde_json::from_slice(&conn.recv().unwrap()).unwrap()
+    }
+
+    fn send_message(conn: &mut ServerConnection<TcpStream>, message: Value) {
+        conn.send(&serde_json::to_vec(&message).unwrap()).unwrap();
+    }
+
+    /// Answers a subscription request, checking it is the expected one.
+    fn answer(conn: &mut ServerConnection<TcpStream>, method: &str) {
+        let req = read_message(conn);
+        assert_eq!(req["method"], method);
+        send_message(
+            conn,
+            json!({"result": null, "error": null, "id": req["id"]}),
+        );
+    }
+
+    fn notifier(addr: SocketAddr) -> (BtcdNotifier, std::sync::mpsc::Receiver<Notification>) {
+        let transport = WsTransport::builder()
+            .url(&format!("ws://{}", addr))
+            .unwrap()
+            .build();
+        BtcdNotifier::with_transport(transport)
+    }
+
+    #[test]
+    fn test_notifications_survive_reconnect() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            // The first connection gets a notification, then drops
+            let mut conn = accept(&server);
+            answer(&mut conn, "notifyblocks");
+            answer(&mut conn, "notifyspent");
+            send_message(
+                &mut conn,
+                json!({"jsonrpc": "1.0", "method": "blockconnected", "id": null,
+                       "params": ["00000000000000000001", 100, 1231006505]}),
+            );
+            drop(conn);
+
+            // Subscriptions are renewed on the next one
+            let mut conn = accept(&server);
+            answer(&mut conn, "notifyblocks");
+            answer(&mut conn, "notifyspent");
+            send_message(
+                &mut conn,
+                json!({"jsonrpc": "1.0", "method": "redeemingtx", "id": null,
+                       "params": ["0200"]}),
+            );
+            // Wait for the client to go away
+            let _ = conn.recv();
+        });
+
+        let (notifier, notifications) = notifier(addr);
+        notifier.notifyblocks().unwrap();
+        let outpoint = WatchedOutpoint {
+            hash: "aa".repeat(32),
+            index: 0,
+        };
+        notifier.notifyspent(vec![outpoint]).unwrap();
+
+        match notifications.recv_timeout(Duration::from_secs(5)).unwrap() {
+            Notification::BlockConnected { hash, height, time } => {
+                assert_eq!(hash, "00000000000000000001");
+                assert_eq!(height, 100);
+                assert_eq!(time, 1231006505);
+            }
+            n => panic!("unexpected notification {:?}", n),
+        }
+        match notifications.recv_timeout(Duration::from_secs(5)).unwrap() {
+            Notification::RedeemingTx { transaction, block } => {
+                assert_eq!(transaction, "0200");
+                assert!(block.is_none());
+            }
+            n => panic!("unexpected notification {:?}", n),
+        }
+        drop(notifier);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_notifier_speaks_configured_version() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut conn = accept(&server);
+            let req = read_message(&mut conn);
+            send_message(
+                &mut conn,
+                json!({"jsonrpc": "1.0", "result": null, "error": null, "id": req["id"]}),
+            );
+            req
+        });
+
+        let cfg = BTCDConfigs::new(
+            false,
+            None,
+            None,
+            Some(addr.ip().to_string()),
+            Some(addr.port() as usize),
+        )
+        .set(Options::JsonRpc1(true));
+        let (notifier, _) = BtcdNotifier::new(cfg).unwrap();
+        notifier.notifyblocks().unwrap();
+        assert_eq!(handle.join().unwrap()["jsonrpc"], "1.0");
+    }
+
+    #[test]
+    fn test_notifier_method_timeouts() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut conn = accept(&server);
+            // Never answer, until the client goes away
+            read_message(&mut conn);
+            let _ = conn.recv();
+        });
+
+        let cfg = BTCDConfigs::new(
+            false,
+            None,
+            None,
+            Some(addr.ip().to_string()),
+            Some(addr.port() as usize),
+        )
+        .set(Options::MethodTimeout(
+            "notifyblocks".to_owned(),
+            Duration::from_millis(100),
+        ));
+        let (notifier, _) = BtcdNotifier::new(cfg).unwrap();
+        let start = Instant::now();
+        let err = notifier.notifyblocks().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        drop(notifier);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_parse_notifications() {
+        let n = Notification::parse("filteredblockconnected", json!([10, "00", null])).unwrap();
+        match n {
+            Notification::FilteredBlockConnected {
+                height,
+                header,
+                transactions,
+            } => {
+                assert_eq!(height, 10);
+                assert_eq!(header, "00");
+                assert!(transactions.is_empty());
+            }
+            n => panic!("unexpected notification {:?}", n),
+        }
+
+        let n = Notification::parse("txaccepted", json!(["ff", 0.5])).unwrap();
+        assert!(matches!(n, Notification::TxAccepted { amount, .. } if amount == 0.5));
+
+        let block = json!({"height": 1, "hash": "00", "index": 2, "time": 3});
+        let n = Notification::parse("recvtx", json!(["0200", block])).unwrap();
+        match n {
+            Notification::RecvTx { block, .. } => assert_eq!(block.unwrap().index, 2),
+            n => panic!("unexpected notification {:?}", n),
+        }
+
+        let n = Notification::parse("rescanfinished", json!([])).unwrap();
+        assert!(matches!(n, Notification::Other { .. }));
+        assert!(Notification::parse("blockconnected", json!(["00"])).is_err());
+    }
+}
@@ -0,0 +1,357 @@
+//! The simulated chain behind the fake node: blocks, a UTXO set and a mempool, without any
+//! proof of work or script validation.
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::block::{Header, Version as BlockVersion};
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::hashes::Hash;
+use bitcoin::script::PushBytesBuf;
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Amount, Block, BlockHash, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn,
+    TxMerkleNode, TxOut, Txid, Witness,
+};
+
+/// Why a transaction was refused by the mempool.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RejectReason {
+    /// The transaction is already in the mempool or in the chain
+    AlreadyHave(Txid),
+    /// Coinbase transactions can only be mined
+    Coinbase,
+    /// An input spends an output that doesn't exist, or was spent already
+    MissingInput(OutPoint),
+    /// An input spends an output also spent by a transaction in the mempool
+    DoubleSpend(OutPoint),
+    /// The outputs are worth more than the inputs
+    Overspend,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::AlreadyHave(txid) => write!(f, "already have transaction {}", txid),
+            RejectReason::Coinbase => write!(f, "transaction is an individual coinbase"),
+            RejectReason::MissingInput(prevout) => {
+                write!(f, "transaction spends unknown or spent output {}", prevout)
+            }
+            RejectReason::DoubleSpend(prevout) => {
+                write!(
+                    f,
+                    "output {} already spent by transaction in the memory pool",
+                    prevout
+                )
+            }
+            RejectReason::Overspend => {
+                write!(f, "total value of all transaction outputs exceeds inputs")
+            }
+        }
+    }
+}
+
+/// Where a transaction is: in a block of the main chain, or in the mempool.
+pub enum TxLocation<'a> {
+    Block {
+        tx: &'a Transaction,
+        block: &'a Block,
+        height: u32,
+    },
+    Mempool(&'a Transaction),
+}
+
+/// An in-memory chain. Blocks are mined instantly, ten minutes apart.
+pub struct Chain {
+    network: Network,
+    /// Every block we know about, including the ones reorged out
+    blocks: HashMap<BlockHash, (Block, u32)>,
+    /// The main chain, by height
+    active: Vec<BlockHash>,
+    /// The outputs spent by each block of the main chain, to disconnect it
+    undo: HashMap<BlockHash, Vec<(OutPoint, TxOut)>>,
+    utxos: HashMap<OutPoint, TxOut>,
+    /// The block of each transaction in the main chain
+    tx_index: HashMap<Txid, BlockHash>,
+    mempool: Vec<Transaction>,
+    /// Makes each coinbase, and hence each block, unique
+    extra_nonce: u64,
+}
+
+impl Chain {
+    /// Creates a chain with only the genesis block of `network`.
+    pub fn new(network: Network) -> Chain {
+        let genesis = genesis_block(network);
+        let hash = genesis.block_hash();
+        let mut chain = Chain {
+            network,
+            blocks: HashMap::new(),
+            active: vec![hash],
+            undo: HashMap::new(),
+            utxos: HashMap::new(),
+            tx_index: HashMap::new(),
+            mempool: Vec::new(),
+            extra_nonce: 0,
+        };
+        // Like in Bitcoin, the genesis coinbase can't be spent
+        chain
+            .tx_index
+            .insert(genesis.txdata[0].compute_txid(), hash);
+        chain.blocks.insert(hash, (genesis, 0));
+        chain
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn height(&self) -> u32 {
+        self.active.len() as u32 - 1
+    }
+
+    pub fn tip(&self) -> BlockHash {
+        self.active[self.active.len() - 1]
+    }
+
+    /// Returns the hash of the block at `height` in the main chain.
+    pub fn block_hash(&self, height: u32) -> Option<BlockHash> {
+        self.active.get(height as usize).copied()
+    }
+
+    /// Returns a block and its height, even if it's not in the main chain anymore.
+    pub fn block(&self, hash: &BlockHash) -> Option<(&Block, u32)> {
+        self.blocks
+            .get(hash)
+            .map(|(block, height)| (block, *height))
+    }
+
+    pub fn is_active(&self, hash: &BlockHash) -> bool {
+        match self.blocks.get(hash) {
+            Some((_, height)) => self.active[*height as usize] == *hash,
+            None => false,
+        }
+    }
+
+    /// Returns how many blocks of the main chain were built on top of the block, including
+    /// itself, or -1 if it was reorged out.
+    pub fn confirmations(&self, hash: &BlockHash) -> i64 {
+        match self.blocks.get(hash) {
+            Some((_, height)) if self.is_active(hash) => (self.height() - height + 1) as i64,
+            _ => -1,
+        }
+    }
+
+    pub fn mempool(&self) -> &[Transaction] {
+        &self.mempool
+    }
+
+    /// Looks a transaction up in the main chain and in the mempool.
+    pub fn transaction(&self, txid: &Txid) -> Option<TxLocation<'_>> {
+        if let Some(hash) = self.tx_index.get(txid) {
+            let (block, height) = &self.blocks[hash];
+            let tx = block.txdata.iter().find(|tx| tx.compute_txid() == *txid)?;
+            return Some(TxLocation::Block {
+                tx,
+                block,
+                height: *height,
+            });
+        }
+        self.mempool
+            .iter()
+            .find(|tx| tx.compute_txid() == *txid)
+            .map(TxLocation::Mempool)
+    }
+
+    /// Returns an output of the main chain that isn't spent yet, nor by the mempool.
+    pub fn unspent(&self) -> impl Iterator<Item = (&OutPoint, &TxOut)> {
+        self.utxos.iter().filter(move |(prevout, _)| {
+            !self.mempool.iter().any(|tx| {
+                tx.input
+                    .iter()
+                    .any(|input| input.previous_output == **prevout)
+            })
+        })
+    }
+
+    /// Adds a transaction to the mempool, checking that it spends existing outputs, which
+    /// aren't spent yet. Scripts aren't checked.
+    pub fn add_transaction(&mut self, tx: Transaction) -> Result<Txid, RejectReason> {
+        let txid = tx.compute_txid();
+        if self.transaction(&txid).is_some() {
+            return Err(RejectReason::AlreadyHave(txid));
+        }
+        if tx.is_coinbase() {
+            return Err(RejectReason::Coinbase);
+        }
+        let mut value_in = Amount::ZERO;
+        for input in &tx.input {
+            let prevout = input.previous_output;
+            if self
+                .mempool
+                .iter()
+                .any(|tx| tx.input.iter().any(|i| i.previous_output == prevout))
+            {
+                return Err(RejectReason::DoubleSpend(prevout));
+            }
+            let spent = self.utxos.get(&prevout).or_else(|| {
+                self.mempool
+                    .iter()
+                    .find(|tx| tx.compute_txid() == prevout.txid)
+                    .and_then(|tx| tx.output.get(prevout.vout as usize))
+            });
+            match spent {
+                Some(output) => value_in += output.value,
+                None => return Err(RejectReason::MissingInput(prevout)),
+            }
+        }
+        let value_out = tx.output.iter().map(|o| o.value).sum::<Amount>();
+        if value_out > value_in {
+            return Err(RejectReason::Overspend);
+        }
+        self.mempool.push(tx);
+        Ok(txid)
+    }
+
+    /// Mines a block with all transactions of the mempool, paying the coinbase to `script`.
+    pub fn mine(&mut self, script: ScriptBuf) -> BlockHash {
+        let height = self.height() + 1;
+        let txdata: Vec<Transaction> = self.mempool.drain(..).collect();
+
+        let fees = txdata
+            .iter()
+            .map(|tx| {
+                let value_in: Amount = tx
+                    .input
+                    .iter()
+                    .map(|i| self.spent_value(&i.previous_output, &txdata))
+                    .sum();
+                value_in - tx.output.iter().map(|o| o.value).sum::<Amount>()
+            })
+            .sum::<Amount>();
+        let coinbase = self.coinbase(height, self.subsidy(height) + fees, script);
+
+        let prev = &self.blocks[&self.tip()].0.header;
+        let mut block = Block {
+            header: Header {
+                version: BlockVersion::from_consensus(0x2000_0000),
+                prev_blockhash: self.tip(),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: prev.time + 600,
+                bits: prev.bits,
+                nonce: 0,
+            },
+            txdata: Some(coinbase).into_iter().chain(txdata).collect(),
+        };
+        block.header.merkle_root = block.compute_merkle_root().expect("a block has a coinbase");
+        self.connect(block)
+    }
+
+    /// Removes the last `depth` blocks from the main chain, putting their transactions back in
+    /// the mempool, then mines `depth + 1` new blocks on top, so that the new chain is longer.
+    pub fn reorg(&mut self, depth: u32, script: ScriptBuf) -> Vec<BlockHash> {
+        let depth = depth.min(self.height());
+        let mut orphaned = Vec::new();
+        for _ in 0..depth {
+            orphaned.splice(0..0, self.disconnect());
+        }
+        let mempool = std::mem::take(&mut self.mempool);
+        for tx in orphaned.into_iter().chain(mempool) {
+            let _ = self.add_transaction(tx);
+        }
+        (0..=depth).map(|_| self.mine(script.clone())).collect()
+    }
+
+    fn subsidy(&self, height: u32) -> Amount {
+        let interval = match self.network {
+            Network::Regtest => 150,
+            _ => 210_000,
+        };
+        let halvings = height / interval;
+        if halvings >= 64 {
+            return Amount::ZERO;
+        }
+        Amount::from_sat((50 * 100_000_000) >> halvings)
+    }
+
+    fn coinbase(&mut self, height: u32, value: Amount, script: ScriptBuf) -> Transaction {
+        self.extra_nonce += 1;
+        // BIP 34 height, then an extra nonce
+        let mut script_sig = ScriptBuf::builder().push_int(height as i64);
+        let extra_nonce = PushBytesBuf::try_from(self.extra_nonce.to_le_bytes().to_vec())
+            .expect("8 bytes fit in a push");
+        script_sig = script_sig.push_slice(extra_nonce);
+        Transaction {
+            version: Version::ONE,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: script_sig.into_script(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey: script,
+            }],
+        }
+    }
+
+    /// Returns the value of an output spent by a transaction of `block`.
+    fn spent_value(&self, prevout: &OutPoint, block: &[Transaction]) -> Amount {
+        self.utxos
+            .get(prevout)
+            .or_else(|| {
+                block
+                    .iter()
+                    .find(|tx| tx.compute_txid() == prevout.txid)
+                    .and_then(|tx| tx.output.get(prevout.vout as usize))
+            })
+            .map_or(Amount::ZERO, |o| o.value)
+    }
+
+    fn connect(&mut self, block: Block) -> BlockHash {
+        let hash = block.block_hash();
+        let mut spent = Vec::new();
+        for tx in &block.txdata {
+            let txid = tx.compute_txid();
+            if !tx.is_coinbase() {
+                for input in &tx.input {
+                    if let Some(output) = self.utxos.remove(&input.previous_output) {
+                        spent.push((input.previous_output, output));
+                    }
+                }
+            }
+            for (vout, output) in tx.output.iter().enumerate() {
+                self.utxos
+                    .insert(OutPoint::new(txid, vout as u32), output.clone());
+            }
+            self.tx_index.insert(txid, hash);
+        }
+        self.undo.insert(hash, spent);
+        self.active.push(hash);
+        self.blocks.insert(hash, (block, self.height()));
+        hash
+    }
+
+    /// Removes the tip from the main chain, returning its transactions but the coinbase.
+    fn disconnect(&mut self) -> Vec<Transaction> {
+        let hash = self
+            .active
+            .pop()
+            .expect("the genesis block is never disconnected");
+        // Some outputs may have been created and spent in this block, restore them first
+        for (prevout, output) in self.undo.remove(&hash).unwrap_or_default() {
+            self.utxos.insert(prevout, output);
+        }
+        let block = &self.blocks[&hash].0;
+        for tx in &block.txdata {
+            let txid = tx.compute_txid();
+            for vout in 0..tx.output.len() {
+                self.utxos.remove(&OutPoint::new(txid, vout as u32));
+            }
+            self.tx_index.remove(&txid);
+        }
+        block.txdata[1..].to_vec()
+    }
+}
@@ -0,0 +1,569 @@
+//! A fake btcd, serving JSON-RPC over HTTP from an in-memory chain, to test code using
+//! [btcd-rpc] without running a node.
+//!
+//! The chain starts at the genesis block of the chosen network, and only moves when told to:
+//! blocks are mined with [FakeBtcd::mine], transactions enter the mempool through
+//! `sendrawtransaction` or [FakeBtcd::add_transaction], and [FakeBtcd::reorg] replaces the
+//! last blocks. Proof of work and scripts aren't checked, and coinbases pay to `OP_TRUE`, so
+//! [FakeBtcd::spend] can make valid transactions without any key.
+//!
+//! ```
+//! use fake_btcd::FakeBtcd;
+//!
+//! let node = FakeBtcd::start().unwrap();
+//! node.mine(10);
+//! let tx = node.spend();
+//! node.add_transaction(tx).unwrap();
+//! node.mine(1);
+//! // Point a client to `node.port()` on localhost
+//! ```
+//!
+//! [btcd-rpc]: https://github.com/Davidson-Souza/rust-btcd-rpc
+pub use bitcoin;
+
+mod chain;
+mod rpc;
+mod server;
+
+pub use chain::{Chain, RejectReason, TxLocation};
+
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Amount, BlockHash, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
+    Witness,
+};
+
+/// Everything the RPCs can see and change.
+pub(crate) struct State {
+    chain: Chain,
+    /// Peers added with `addnode`
+    peers: Vec<String>,
+    /// What `estimatefee` returns, in BTC/kB
+    fee_rate: f64,
+}
+
+/// A fake btcd, listening on localhost until dropped.
+pub struct FakeBtcd {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl FakeBtcd {
+    /// Starts a regtest node without authentication, on a random port.
+    pub fn start() -> io::Result<FakeBtcd> {
+        Builder::new().start()
+    }
+
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    /// Returns the address the node listens on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// Runs `f` on the chain, to look at it or change it directly.
+    pub fn with_chain<R, F: FnOnce(&mut Chain) -> R>(&self, f: F) -> R {
+        f(&mut self.lock().chain)
+    }
+
+    /// Mines `count` blocks with the transactions of the mempool, returning their hashes.
+    pub fn mine(&self, count: u32) -> Vec<BlockHash> {
+        let mut state = self.lock();
+        (0..count).map(|_| state.chain.mine(op_true())).collect()
+    }
+
+    /// Adds a transaction to the mempool, like `sendrawtransaction` does.
+    pub fn add_transaction(&self, tx: Transaction) -> Result<Txid, RejectReason> {
+        self.lock().chain.add_transaction(tx)
+    }
+
+    /// Replaces the last `depth` blocks by `depth + 1` new ones. The transactions of the
+    /// replaced blocks go back to the mempool and get mined again.
+    pub fn reorg(&self, depth: u32) -> Vec<BlockHash> {
+        self.lock().chain.reorg(depth, op_true())
+    }
+
+    /// Returns a new transaction spending an unspent output to `OP_TRUE`, with a 1000 sat
+    /// fee. It isn't added to the mempool.
+    ///
+    /// # Panics
+    ///
+    /// If there is no output to spend: mine a block first.
+    pub fn spend(&self) -> Transaction {
+        let state = self.lock();
+        let (prevout, output) = state
+            .chain
+            .unspent()
+            .filter(|(_, output)| output.value > Amount::from_sat(1000))
+            .map(|(prevout, output)| (*prevout, output.clone()))
+            .min_by_key(|(prevout, _)| *prevout)
+            .expect("no output to spend, mine a block first");
+        spending(prevout, output.value - Amount::from_sat(1000))
+    }
+
+    /// Sets what `estimatefee` returns, in BTC/kB.
+    pub fn set_fee_rate(&self, fee_rate: f64) {
+        self.lock().fee_rate = fee_rate;
+    }
+
+    /// Returns the peers added with `addnode`.
+    pub fn peers(&self) -> Vec<String> {
+        self.lock().peers.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        lock(&self.state)
+    }
+}
+
+/// Locks the state of the node. A poisoned lock is used anyway, so that a call that panicked
+/// doesn't make the node fail all the following ones.
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Drop for FakeBtcd {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wakes the server up, so that it sees it has to stop
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+fn op_true() -> ScriptBuf {
+    ScriptBuf::from(vec![0x51])
+}
+
+fn spending(prevout: OutPoint, value: Amount) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: prevout,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value,
+            script_pubkey: op_true(),
+        }],
+    }
+}
+
+/// Builder for a [FakeBtcd].
+pub struct Builder {
+    network: Network,
+    auth: Option<(String, String)>,
+    fee_rate: f64,
+}
+
+impl Builder {
+    /// Starts with a regtest node without authentication.
+    pub fn new() -> Builder {
+        Builder {
+            network: Network::Regtest,
+            auth: None,
+            fee_rate: 0.0001,
+        }
+    }
+
+    /// Sets the network, whose genesis block starts the chain.
+    pub fn network(mut self, network: Network) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Requires clients to authenticate with `user` and `pass`, answering 401 otherwise.
+    pub fn auth<S: Into<String>>(mut self, user: S, pass: S) -> Self {
+        self.auth = Some((user.into(), pass.into()));
+        self
+    }
+
+    /// Sets what `estimatefee` returns, in BTC/kB.
+    pub fn fee_rate(mut self, fee_rate: f64) -> Self {
+        self.fee_rate = fee_rate;
+        self
+    }
+
+    /// Starts the node on a random port of localhost.
+    pub fn start(self) -> io::Result<FakeBtcd> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State {
+            chain: Chain::new(self.network),
+            peers: Vec::new(),
+            fee_rate: self.fee_rate,
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let auth = self
+            .auth
+            .map(|(user, pass)| format!("Basic {}", base64::encode(format!("{}:{}", user, pass))));
+
+        let server_state = state.clone();
+        let server_stop = stop.clone();
+        thread::spawn(move || server::serve(listener, server_state, auth, server_stop));
+        Ok(FakeBtcd { addr, state, stop })
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::consensus::encode::serialize_hex;
+    use bitcoin::hashes::Hash;
+    use btcd_rpc::client::{BTCDClient, BTCDConfigs, BtcdRpc, Options};
+    use btcd_rpc::error::{ErrorKind, RpcErrorCode, UtreexodError};
+    use serde_json::{json, Value};
+
+    fn client(node: &FakeBtcd) -> BTCDClient {
+        let host = Some("127.0.0.1".to_owned());
+        let cfg = BTCDConfigs::new(false, None, None, host, Some(node.port() as usize));
+        BTCDClient::new(cfg).unwrap()
+    }
+
+    fn call(node: &FakeBtcd, method: &str, params: Value) -> jsonrpc::Response {
+        let url = format!("127.0.0.1:{}", node.port());
+        let client = jsonrpc::Client::simple_http(&url, None, None).unwrap();
+        let params = serde_json::value::to_raw_value(&params).unwrap();
+        let params: Vec<_> = serde_json::from_str(params.get()).unwrap();
+        client
+            .send_request(client.build_request(method, &params))
+            .unwrap()
+    }
+
+    fn error_code(node: &FakeBtcd, method: &str, params: Value) -> i32 {
+        call(node, method, params)
+            .error
+            .expect("expected an error")
+            .code
+    }
+
+    #[test]
+    fn mines_blocks() {
+        let node = FakeBtcd::start().unwrap();
+        let client = client(&node);
+        assert_eq!(client.getblockcount().unwrap(), 0);
+
+        let hashes = node.mine(3);
+        assert_eq!(client.getblockcount().unwrap(), 3);
+        assert_eq!(client.getblockhash(2).unwrap(), hashes[1].to_string());
+        assert_eq!(client.getbestblockhash().unwrap(), hashes[2].to_string());
+        let best = client.getbestblock().unwrap();
+        assert_eq!((best.height, best.hash), (3, hashes[2].to_string()));
+
+        let block = client
+            .getblock(hashes[0].to_string(), true)
+            .unwrap()
+            .get_verbose();
+        let block = serde_json::to_value(block).unwrap();
+        assert_eq!(block["confirmations"], 3);
+        assert_eq!(block["nextblockhash"], hashes[1].to_string());
+        let header = client
+            .getblockheader(hashes[1].to_string(), true)
+            .unwrap()
+            .get_verbose();
+        let header = serde_json::to_value(header).unwrap();
+        assert_eq!(header["height"], 2);
+        assert_eq!(header["previousblockhash"], hashes[0].to_string());
+
+        match client.getblock(hashes[2].to_string(), false).unwrap() {
+            btcd_rpc::json_types::VerbosityOutput::Simple(hex) => {
+                assert_eq!(
+                    hex,
+                    serialize_hex(&node.with_chain(|c| c.block(&hashes[2]).unwrap().0.clone()))
+                )
+            }
+            _ => panic!("expected a hex block"),
+        }
+    }
+
+    #[test]
+    fn relays_transactions() {
+        let node = FakeBtcd::start().unwrap();
+        let client = client(&node);
+        node.mine(1);
+
+        let tx = node.spend();
+        let txid = client.sendrawtransaction(serialize_hex(&tx)).unwrap();
+        assert_eq!(txid, tx.compute_txid().to_string());
+        let mempool_tx = client
+            .getrawtransaction(txid.clone(), true)
+            .unwrap()
+            .get_verbose();
+        assert_eq!(mempool_tx.confirmations, None);
+
+        let hash = node.mine(1)[0];
+        let mined = client
+            .getrawtransaction(txid.clone(), true)
+            .unwrap()
+            .get_verbose();
+        assert_eq!(mined.blockhash, Some(hash.to_string()));
+        assert_eq!(mined.confirmations, Some(1));
+        assert_eq!(mined.vout[0].value, 49.99999);
+
+        // Spent already
+        match client.sendrawtransaction(serialize_hex(&tx)) {
+            Err(UtreexodError::JsonRpcError(jsonrpc::Error::Rpc(e))) => assert_eq!(e.code, -25),
+            res => panic!("expected a rejection, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn reorgs() {
+        let node = FakeBtcd::start().unwrap();
+        node.mine(2);
+        let tx = node.spend();
+        node.add_transaction(tx.clone()).unwrap();
+        let stale = node.mine(2);
+
+        let new = node.reorg(2);
+        assert_eq!(new.len(), 3);
+        assert_eq!(
+            call(&node, "getblockcount", json!([]))
+                .result::<u32>()
+                .unwrap(),
+            5
+        );
+        let header = call(&node, "getblockheader", json!([stale[0].to_string()]));
+        assert_eq!(header.result::<Value>().unwrap()["confirmations"], -1);
+        // The transaction was mined again
+        let raw = call(
+            &node,
+            "getrawtransaction",
+            json!([tx.compute_txid().to_string(), 1]),
+        );
+        assert_eq!(
+            raw.result::<Value>().unwrap()["blockhash"],
+            new[0].to_string()
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let node = FakeBtcd::start().unwrap();
+        assert_eq!(error_code(&node, "getblockhash", json!([1])), -1);
+        assert_eq!(error_code(&node, "getblock", json!(["00"])), -22);
+        assert_eq!(
+            error_code(
+                &node,
+                "getblock",
+                json!([BlockHash::all_zeros().to_string()])
+            ),
+            -5
+        );
+        assert_eq!(error_code(&node, "sendrawtransaction", json!(["zz"])), -22);
+        assert_eq!(error_code(&node, "getblockcount", json!([1])), -32602);
+        assert_eq!(error_code(&node, "nosuchmethod", json!([])), -32601);
+    }
+
+    #[test]
+    fn classified_errors() {
+        use std::error::Error;
+
+        let node = FakeBtcd::start().unwrap();
+        let client = client(&node);
+        let zeros = BlockHash::all_zeros().to_string();
+        let code = |res: Result<_, UtreexodError>| {
+            let e = res.unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::Rpc);
+            assert!(!e.is_retryable());
+            e.rpc_code().unwrap()
+        };
+        assert_eq!(
+            code(client.getblockhash(1).map(drop)),
+            RpcErrorCode::OutOfRange
+        );
+        assert_eq!(
+            code(client.getblock(zeros.clone(), true).map(drop)),
+            RpcErrorCode::BlockNotFound
+        );
+        assert_eq!(
+            code(client.getrawtransaction(zeros, true).map(drop)),
+            RpcErrorCode::NoTxInfo
+        );
+
+        let node = FakeBtcd::builder().auth("user", "pass").start().unwrap();
+        let cfg = BTCDConfigs::new(
+            false,
+            Some("user".into()),
+            Some("wrong".into()),
+            Some("127.0.0.1".into()),
+            Some(node.port() as usize),
+        );
+        let e = BTCDClient::new(cfg).unwrap().getblockcount().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Auth);
+        assert!(!e.is_retryable());
+        // The chain goes down to the HTTP error
+        let http = e.source().and_then(Error::source).unwrap();
+        assert!(http.to_string().contains("401"), "{}", http);
+
+        // Nothing listens on the port of a stopped node
+        let port = node.port();
+        drop(node);
+        let host = Some("127.0.0.1".to_owned());
+        let cfg = BTCDConfigs::new(false, None, None, host, Some(port as usize));
+        let e = BTCDClient::new(cfg).unwrap().getblockcount().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Connection);
+        assert!(e.is_retryable());
+    }
+
+    #[test]
+    fn authenticates() {
+        let node = FakeBtcd::builder().auth("user", "pass").start().unwrap();
+        let cfg = |pass: &str| {
+            BTCDConfigs::new(
+                false,
+                Some("user".into()),
+                Some(pass.into()),
+                Some("127.0.0.1".into()),
+                Some(node.port() as usize),
+            )
+        };
+
+        let client = BTCDClient::new(cfg("pass")).unwrap();
+        assert_eq!(client.getblockcount().unwrap(), 0);
+        let client = BTCDClient::new(cfg("wrong")).unwrap();
+        match client.getblockcount() {
+            Err(UtreexodError::JsonRpcError(jsonrpc::Error::Transport(e))) => {
+                assert!(e.to_string().contains("401"), "{}", e)
+            }
+            res => panic!("expected a 401, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn rejects_bad_content_lengths() {
+        use std::io::{Read, Write};
+
+        let node = FakeBtcd::start().unwrap();
+        let status = |length: &str| {
+            let mut stream = TcpStream::connect(node.addr()).unwrap();
+            write!(
+                stream,
+                "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+                length
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response.lines().next().unwrap_or_default().to_owned()
+        };
+        assert_eq!(status("many"), "HTTP/1.1 400 Bad Request");
+        assert_eq!(status("-1"), "HTTP/1.1 400 Bad Request");
+        assert_eq!(
+            status("18446744073709551615"),
+            "HTTP/1.1 413 Payload Too Large"
+        );
+        // The node still answers
+        assert_eq!(client(&node).getblockcount().unwrap(), 0);
+    }
+
+    #[test]
+    fn batches() {
+        let node = FakeBtcd::start().unwrap();
+        let client = client(&node);
+        let hashes = node.mine(10);
+
+        let mut batch = client.batch().chunk_size(4);
+        let queued: Vec<_> = (1..=11).map(|h| batch.getblockhash(h).unwrap()).collect();
+        let block = batch.getblock(hashes[2].to_string(), true).unwrap();
+        let count = batch.getblockcount().unwrap();
+        let results = batch.send().unwrap();
+
+        for (hash, &pending) in hashes.iter().zip(&queued) {
+            assert_eq!(results.get(pending).unwrap(), hash.to_string());
+        }
+        match results.get(queued[10]) {
+            Err(UtreexodError::JsonRpcError(jsonrpc::Error::Rpc(e))) => assert_eq!(e.code, -1),
+            res => panic!("expected an error, got {:?}", res),
+        }
+        let block = serde_json::to_value(results.get(block).unwrap().get_verbose()).unwrap();
+        assert_eq!(block["height"], 3);
+        assert_eq!(results.get(count).unwrap(), 10);
+    }
+
+    #[test]
+    fn jsonrpc_1() {
+        let node = FakeBtcd::start().unwrap();
+        let host = Some("127.0.0.1".to_owned());
+        let cfg = BTCDConfigs::new(false, None, None, host, Some(node.port() as usize))
+            .set(Options::JsonRpc1(true));
+        let client = BTCDClient::new(cfg).unwrap();
+        let hashes = node.mine(1);
+        assert_eq!(client.getblockhash(1).unwrap(), hashes[0].to_string());
+        match client.getblockhash(2) {
+            Err(UtreexodError::JsonRpcError(jsonrpc::Error::Rpc(e))) => assert_eq!(e.code, -1),
+            res => panic!("expected an error, got {:?}", res),
+        }
+
+        // The node answers 1.0 requests in kind, without a `jsonrpc` field
+        let url = format!("127.0.0.1:{}", node.port());
+        let client = jsonrpc::Client::simple_http(&url, None, None)
+            .unwrap()
+            .with_version(jsonrpc::Version::V1);
+        let params = [jsonrpc::arg(1)];
+        let resp = client
+            .send_request(client.build_request("getblockhash", &params))
+            .unwrap();
+        assert_eq!(resp.jsonrpc, None);
+    }
+
+    #[test]
+    fn deadlines() {
+        use btcd_rpc::QueryBlock;
+        use jsonrpc::{CallOptions, CancelToken};
+        use std::time::{Duration, Instant};
+
+        let node = FakeBtcd::start().unwrap();
+        let host = Some("127.0.0.1".to_owned());
+        let cfg = BTCDConfigs::new(false, None, None, host, Some(node.port() as usize)).set(
+            Options::MethodTimeout("getblock".to_owned(), Duration::from_secs(30)),
+        );
+        let client = BTCDClient::new(cfg).unwrap();
+        let hashes = node.mine(3);
+
+        // Both round trips of the helper fit in the deadline
+        let options = CallOptions::new().timeout(Duration::from_secs(10));
+        let block = client
+            .with_options(&options, |c| c.getblock_by(QueryBlock::ByHeight(2), true))
+            .unwrap();
+        let block = serde_json::to_value(block.get_verbose()).unwrap();
+        assert_eq!(block["hash"], hashes[1].to_string());
+
+        let options = CallOptions::new().deadline(Instant::now());
+        match client.with_options(&options, |c| c.getblock_by(QueryBlock::ByHeight(2), true)) {
+            Err(UtreexodError::JsonRpcError(jsonrpc::Error::DeadlineExceeded)) => {}
+            res => panic!("expected the deadline to pass, got {:?}", res),
+        }
+
+        let token = CancelToken::new();
+        token.cancel();
+        let options = CallOptions::new().cancel_token(token);
+        match client.with_options(&options, |c| c.getblockcount()) {
+            Err(UtreexodError::JsonRpcError(jsonrpc::Error::Cancelled)) => {}
+            res => panic!("expected the call to be cancelled, got {:?}", res),
+        }
+        assert_eq!(client.getblockcount().unwrap(), 3);
+    }
+}
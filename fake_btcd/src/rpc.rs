@@ -0,0 +1,672 @@
+//! The RPCs answered by the fake node, with the same results and errors as btcd.
+use std::str::FromStr;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::blockdata::opcodes::all::{OP_CLTV, OP_CSV};
+use bitcoin::blockdata::opcodes::Opcode;
+use bitcoin::consensus::encode::{deserialize, serialize, serialize_hex};
+use bitcoin::hashes::Hash;
+use bitcoin::script::Instruction;
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Address, Amount, Block, BlockHash, CompactTarget, Network, OutPoint, Script, ScriptBuf,
+    Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::chain::{Chain, TxLocation};
+use crate::State;
+
+/// btcd's version, as reported by `getinfo`.
+const VERSION: u32 = 230300;
+const PROTOCOL_VERSION: u32 = 70016;
+/// `getheaders` returns at most this many headers.
+const MAX_HEADERS: usize = 2000;
+
+// btcd's error codes, from btcjson/jsonrpcerr.go
+const ERR_MISC: i32 = -1;
+const ERR_TYPE: i32 = -3;
+const ERR_INVALID_ADDRESS_OR_KEY: i32 = -5;
+const ERR_INVALID_PARAMETER: i32 = -8;
+const ERR_DESERIALIZATION: i32 = -22;
+const ERR_VERIFY: i32 = -25;
+pub(crate) const ERR_PARSE: i32 = -32700;
+pub(crate) const ERR_INVALID_REQUEST: i32 = -32600;
+const ERR_METHOD_NOT_FOUND: i32 = -32601;
+const ERR_INVALID_PARAMS: i32 = -32602;
+
+/// An error returned to the client, in the `error` field of the response.
+#[derive(Debug, Serialize)]
+pub(crate) struct RpcError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new<M: Into<String>>(code: i32, message: M) -> RpcError {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, RpcError>;
+
+/// Runs the RPC `method`, given its positional params.
+pub(crate) fn handle(state: &mut State, method: &str, params: &[Value]) -> Result<Value> {
+    let args = Args(params);
+    match method {
+        "getinfo" => {
+            args.count(0, 0)?;
+            getinfo(state)
+        }
+        "getblockcount" => {
+            args.count(0, 0)?;
+            Ok(json!(state.chain.height()))
+        }
+        "getbestblockhash" => {
+            args.count(0, 0)?;
+            Ok(json!(state.chain.tip().to_string()))
+        }
+        "getbestblock" => {
+            args.count(0, 0)?;
+            Ok(json!({"hash": state.chain.tip().to_string(), "height": state.chain.height()}))
+        }
+        "getblockhash" => {
+            args.count(1, 1)?;
+            let index: i64 = args.get(0, "index", "int64")?;
+            if index < 0 || index > state.chain.height() as i64 {
+                return Err(RpcError::new(ERR_MISC, "Block number out of range"));
+            }
+            Ok(json!(state
+                .chain
+                .block_hash(index as u32)
+                .unwrap()
+                .to_string()))
+        }
+        "getblock" => {
+            args.count(1, 2)?;
+            let hash = args.hash(0, "hash")?;
+            let verbosity: i64 = args.get_or(1, "verbosity", "int", 1)?;
+            getblock(&state.chain, &hash, verbosity)
+        }
+        "getblockheader" => {
+            args.count(1, 2)?;
+            let hash = args.hash(0, "hash")?;
+            let verbose: bool = args.get_or(1, "verbose", "bool", true)?;
+            getblockheader(&state.chain, &hash, verbose)
+        }
+        "getheaders" => {
+            args.count(2, 2)?;
+            let locators: Vec<String> = args.get(0, "blocklocators", "[]string")?;
+            let locators = locators
+                .iter()
+                .map(|l| parse_hash(l))
+                .collect::<Result<Vec<BlockHash>>>()?;
+            let stop: String = args.get(1, "hashstop", "string")?;
+            let stop = if stop.is_empty() {
+                BlockHash::all_zeros()
+            } else {
+                parse_hash(&stop)?
+            };
+            Ok(getheaders(&state.chain, &locators, &stop))
+        }
+        "getrawtransaction" => {
+            args.count(1, 2)?;
+            let txid = args.hash(0, "txid")?;
+            let verbose: i64 = args.get_or(1, "verbose", "int", 0)?;
+            getrawtransaction(&state.chain, &txid, verbose != 0)
+        }
+        "decoderawtransaction" => {
+            args.count(1, 1)?;
+            let tx = args.transaction(0)?;
+            let mut result = Map::new();
+            result.insert("txid".into(), json!(tx.compute_txid().to_string()));
+            result.insert("version".into(), json!(tx.version.0));
+            result.insert("locktime".into(), json!(tx.lock_time.to_consensus_u32()));
+            result.insert("vin".into(), vin(&tx));
+            result.insert("vout".into(), vout(&tx, state.chain.network()));
+            Ok(Value::Object(result))
+        }
+        "sendrawtransaction" => {
+            args.count(1, 2)?;
+            let tx = args.transaction(0)?;
+            match state.chain.add_transaction(tx) {
+                Ok(txid) => Ok(json!(txid.to_string())),
+                Err(reason) => Err(RpcError::new(
+                    ERR_VERIFY,
+                    format!("TX rejected: {}", reason),
+                )),
+            }
+        }
+        "createrawtransaction" => {
+            args.count(2, 3)?;
+            createrawtransaction(state.chain.network(), &args)
+        }
+        "estimatefee" => {
+            args.count(1, 1)?;
+            let blocks: i64 = args.get(0, "numblocks", "int64")?;
+            if blocks <= 0 {
+                return Err(RpcError::new(
+                    ERR_INVALID_PARAMETER,
+                    "Parameter NumBlocks must be positive.",
+                ));
+            }
+            Ok(json!(state.fee_rate))
+        }
+        "addnode" => {
+            args.count(2, 2)?;
+            let addr: String = args.get(0, "addr", "string")?;
+            let subcmd: String = args.get(1, "subcmd", "string")?;
+            addnode(state, addr, &subcmd)?;
+            Ok(Value::Null)
+        }
+        "debuglevel" => {
+            args.count(1, 1)?;
+            let spec: String = args.get(0, "levelspec", "string")?;
+            if spec == "show" {
+                return Ok(json!(
+                    "Supported subsystems [ADXR AMGR BCDB BMGR BTCD CHAN CMGR DISC INDX MINR PEER \
+                     RPCS SCRP SRVR SYNC TXMP]"
+                ));
+            }
+            Ok(json!("Done."))
+        }
+        _ => Err(RpcError::new(ERR_METHOD_NOT_FOUND, "Method not found")),
+    }
+}
+
+/// The positional params of a request, parsed the way btcd does.
+struct Args<'a>(&'a [Value]);
+
+impl<'a> Args<'a> {
+    /// Checks the number of params.
+    fn count(&self, min: usize, max: usize) -> Result<()> {
+        let received = self.0.len();
+        if received >= min && received <= max {
+            return Ok(());
+        }
+        let message = if min == max {
+            format!(
+                "wrong number of params (expected {}, received {})",
+                min, received
+            )
+        } else {
+            format!(
+                "wrong number of params (expected between {} and {}, received {})",
+                min, max, received
+            )
+        };
+        Err(RpcError::new(ERR_INVALID_PARAMS, message))
+    }
+
+    /// Returns the param at `index`, named `name` and of Go type `kind` for error messages.
+    fn get<T: DeserializeOwned>(&self, index: usize, name: &str, kind: &str) -> Result<T> {
+        serde_json::from_value(self.0[index].clone()).map_err(|_| {
+            RpcError::new(
+                ERR_INVALID_PARAMS,
+                format!(
+                    "parameter #{} '{}' must be type {} (got {})",
+                    index + 1,
+                    name,
+                    kind,
+                    json_type(&self.0[index])
+                ),
+            )
+        })
+    }
+
+    /// Returns an optional param, or `default` if it's missing or null.
+    fn get_or<T: DeserializeOwned>(
+        &self,
+        index: usize,
+        name: &str,
+        kind: &str,
+        default: T,
+    ) -> Result<T> {
+        match self.0.get(index) {
+            None | Some(Value::Null) => Ok(default),
+            Some(_) => self.get(index, name, kind),
+        }
+    }
+
+    fn hash<T: FromStr>(&self, index: usize, name: &str) -> Result<T> {
+        let hash: String = self.get(index, name, "string")?;
+        parse_hash(&hash)
+    }
+
+    /// Returns the hex-encoded transaction at `index`.
+    fn transaction(&self, index: usize) -> Result<Transaction> {
+        let hex: String = self.get(index, "hextx", "string")?;
+        let bytes = decode_hex(&hex)?;
+        deserialize(&bytes)
+            .map_err(|e| RpcError::new(ERR_DESERIALIZATION, format!("TX decode failed: {}", e)))
+    }
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn decode_hex_error(hex: &str) -> RpcError {
+    RpcError::new(
+        ERR_DESERIALIZATION,
+        format!("Argument must be hexadecimal string (not {:?})", hex),
+    )
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    // btcd accepts an odd number of digits, as if there was a leading zero
+    let padded;
+    let hex = if hex.len() % 2 == 1 {
+        padded = format!("0{}", hex);
+        &padded
+    } else {
+        hex
+    };
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| decode_hex_error(hex))
+}
+
+fn parse_hash<T: FromStr>(hash: &str) -> Result<T> {
+    T::from_str(hash).map_err(|_| decode_hex_error(hash))
+}
+
+fn block_not_found() -> RpcError {
+    RpcError::new(ERR_INVALID_ADDRESS_OR_KEY, "Block not found")
+}
+
+fn getinfo(state: &State) -> Result<Value> {
+    let chain = &state.chain;
+    let bits = chain.block(&chain.tip()).unwrap().0.header.bits;
+    Ok(json!({
+        "version": VERSION,
+        "protocolversion": PROTOCOL_VERSION,
+        "blocks": chain.height(),
+        "timeoffset": 0,
+        "connections": state.peers.len(),
+        "proxy": "",
+        "difficulty": difficulty(chain.network(), bits),
+        "testnet": chain.network() == Network::Testnet,
+        "relayfee": 0.00001,
+        "errors": "",
+    }))
+}
+
+/// Returns how much harder it is to find a block with `bits` than with the easiest target
+/// allowed on `network`.
+fn difficulty(network: Network, bits: CompactTarget) -> f64 {
+    let pow_limit = match network {
+        Network::Regtest => 0x207f_ffff,
+        Network::Signet => 0x1e03_77ae,
+        _ => 0x1d00_ffff,
+    };
+    compact_to_f64(pow_limit) / compact_to_f64(bits.to_consensus())
+}
+
+fn compact_to_f64(bits: u32) -> f64 {
+    let exponent = (bits >> 24) as i32;
+    let mantissa = (bits & 0x007f_ffff) as f64;
+    mantissa * 256f64.powi(exponent - 3)
+}
+
+/// The fields shared by `getblock` and `getblockheader`.
+fn header_fields(chain: &Chain, block: &Block, height: u32) -> Map<String, Value> {
+    let hash = block.block_hash();
+    let header = &block.header;
+    let mut fields = Map::new();
+    fields.insert("hash".into(), json!(hash.to_string()));
+    fields.insert("confirmations".into(), json!(chain.confirmations(&hash)));
+    fields.insert("height".into(), json!(height));
+    fields.insert("version".into(), json!(header.version.to_consensus()));
+    fields.insert(
+        "versionHex".into(),
+        json!(format!("{:08x}", header.version.to_consensus())),
+    );
+    fields.insert("merkleroot".into(), json!(header.merkle_root.to_string()));
+    fields.insert("time".into(), json!(header.time));
+    fields.insert("nonce".into(), json!(header.nonce));
+    fields.insert(
+        "bits".into(),
+        json!(format!("{:08x}", header.bits.to_consensus())),
+    );
+    fields.insert(
+        "difficulty".into(),
+        json!(difficulty(chain.network(), header.bits)),
+    );
+    if height > 0 {
+        fields.insert(
+            "previousblockhash".into(),
+            json!(header.prev_blockhash.to_string()),
+        );
+    }
+    if chain.is_active(&hash) {
+        if let Some(next) = chain.block_hash(height + 1) {
+            fields.insert("nextblockhash".into(), json!(next.to_string()));
+        }
+    }
+    fields
+}
+
+fn getblock(chain: &Chain, hash: &BlockHash, verbosity: i64) -> Result<Value> {
+    let (block, height) = chain.block(hash).ok_or_else(block_not_found)?;
+    if verbosity == 0 {
+        return Ok(json!(serialize_hex(block)));
+    }
+
+    let header = header_fields(chain, block, height);
+    let total_size = block.total_size();
+    let weight = block.weight().to_wu() as usize;
+    let mut result = Map::new();
+    for (key, value) in header {
+        if key == "height" {
+            result.insert("strippedsize".into(), json!((weight - total_size) / 3));
+            result.insert("size".into(), json!(total_size));
+            result.insert("weight".into(), json!(weight));
+        }
+        if key == "time" {
+            if verbosity == 1 {
+                let txids: Vec<_> = block
+                    .txdata
+                    .iter()
+                    .map(|tx| tx.compute_txid().to_string())
+                    .collect();
+                result.insert("tx".into(), json!(txids));
+            } else {
+                let txs: Vec<_> = block
+                    .txdata
+                    .iter()
+                    .map(|tx| verbose_transaction(chain, tx, Some((block, height))))
+                    .collect();
+                result.insert("rawtx".into(), json!(txs));
+            }
+        }
+        result.insert(key, value);
+    }
+    Ok(Value::Object(result))
+}
+
+fn getblockheader(chain: &Chain, hash: &BlockHash, verbose: bool) -> Result<Value> {
+    let (block, height) = chain.block(hash).ok_or_else(block_not_found)?;
+    if !verbose {
+        return Ok(json!(serialize_hex(&block.header)));
+    }
+    Ok(Value::Object(header_fields(chain, block, height)))
+}
+
+/// Returns the headers of the main chain following the first locator we know, up to `stop`.
+fn getheaders(chain: &Chain, locators: &[BlockHash], stop: &BlockHash) -> Value {
+    let start = locators
+        .iter()
+        .filter(|hash| chain.is_active(hash))
+        .filter_map(|hash| chain.block(hash))
+        .map(|(_, height)| height + 1)
+        .next()
+        .unwrap_or(1);
+    let mut headers = Vec::new();
+    for height in start..=chain.height() {
+        let hash = chain.block_hash(height).unwrap();
+        let (block, _) = chain.block(&hash).unwrap();
+        headers.push(serialize_hex(&block.header));
+        if hash == *stop || headers.len() == MAX_HEADERS {
+            break;
+        }
+    }
+    json!(headers)
+}
+
+fn getrawtransaction(chain: &Chain, txid: &Txid, verbose: bool) -> Result<Value> {
+    let (tx, block) = match chain.transaction(txid) {
+        Some(TxLocation::Block { tx, block, height }) => (tx, Some((block, height))),
+        Some(TxLocation::Mempool(tx)) => (tx, None),
+        None => {
+            return Err(RpcError::new(
+                ERR_INVALID_ADDRESS_OR_KEY,
+                format!("No information available about transaction {}", txid),
+            ))
+        }
+    };
+    if !verbose {
+        return Ok(json!(serialize_hex(tx)));
+    }
+    Ok(verbose_transaction(chain, tx, block))
+}
+
+fn verbose_transaction(chain: &Chain, tx: &Transaction, block: Option<(&Block, u32)>) -> Value {
+    let mut result = Map::new();
+    result.insert("hex".into(), json!(serialize_hex(tx)));
+    result.insert("txid".into(), json!(tx.compute_txid().to_string()));
+    result.insert("hash".into(), json!(tx.compute_wtxid().to_string()));
+    result.insert("size".into(), json!(tx.total_size()));
+    result.insert("vsize".into(), json!(tx.vsize()));
+    result.insert("weight".into(), json!(tx.weight().to_wu()));
+    result.insert("version".into(), json!(tx.version.0));
+    result.insert("locktime".into(), json!(tx.lock_time.to_consensus_u32()));
+    result.insert("vin".into(), vin(tx));
+    result.insert("vout".into(), vout(tx, chain.network()));
+    if let Some((block, _)) = block {
+        let hash = block.block_hash();
+        result.insert("blockhash".into(), json!(hash.to_string()));
+        result.insert("confirmations".into(), json!(chain.confirmations(&hash)));
+        result.insert("time".into(), json!(block.header.time));
+        result.insert("blocktime".into(), json!(block.header.time));
+    }
+    Value::Object(result)
+}
+
+fn vin(tx: &Transaction) -> Value {
+    let inputs = tx.input.iter().map(|input| {
+        let mut result = Map::new();
+        if tx.is_coinbase() {
+            result.insert("coinbase".into(), json!(hex(input.script_sig.as_bytes())));
+        } else {
+            result.insert("txid".into(), json!(input.previous_output.txid.to_string()));
+            result.insert("vout".into(), json!(input.previous_output.vout));
+            result.insert(
+                "scriptSig".into(),
+                json!({
+                    "asm": disassemble(&input.script_sig),
+                    "hex": hex(input.script_sig.as_bytes()),
+                }),
+            );
+        }
+        if !input.witness.is_empty() {
+            let witness: Vec<_> = input.witness.iter().map(hex).collect();
+            result.insert("txinwitness".into(), json!(witness));
+        }
+        result.insert("sequence".into(), json!(input.sequence.0));
+        Value::Object(result)
+    });
+    Value::Array(inputs.collect())
+}
+
+fn vout(tx: &Transaction, network: Network) -> Value {
+    let outputs = tx.output.iter().enumerate().map(|(n, output)| {
+        json!({
+            "value": output.value.to_btc(),
+            "n": n,
+            "scriptPubKey": script_pubkey(&output.script_pubkey, network),
+        })
+    });
+    Value::Array(outputs.collect())
+}
+
+/// Describes an output script like btcd does.
+fn script_pubkey(script: &Script, network: Network) -> Value {
+    let (kind, req_sigs) = if script.is_p2pk() {
+        ("pubkey", Some(1))
+    } else if script.is_p2pkh() {
+        ("pubkeyhash", Some(1))
+    } else if script.is_p2sh() {
+        ("scripthash", Some(1))
+    } else if script.is_p2wpkh() {
+        ("witness_v0_keyhash", Some(1))
+    } else if script.is_p2wsh() {
+        ("witness_v0_scripthash", Some(1))
+    } else if script.is_p2tr() {
+        ("witness_v1_taproot", Some(1))
+    } else if script.is_multisig() {
+        let required = match script.instructions().next() {
+            Some(Ok(Instruction::Op(op))) => pushnum(op).map(u32::from),
+            _ => None,
+        };
+        ("multisig", required)
+    } else if script.is_op_return() {
+        ("nulldata", None)
+    } else {
+        ("nonstandard", None)
+    };
+
+    let mut result = Map::new();
+    result.insert("asm".into(), json!(disassemble(script)));
+    result.insert("hex".into(), json!(hex(script.as_bytes())));
+    if let Some(req_sigs) = req_sigs {
+        result.insert("reqSigs".into(), json!(req_sigs));
+    }
+    result.insert("type".into(), json!(kind));
+    if let Ok(address) = Address::from_script(script, network) {
+        result.insert("addresses".into(), json!([address.to_string()]));
+    }
+    Value::Object(result)
+}
+
+/// Disassembles a script the way btcd does: data pushes as hex, small integers as numbers and
+/// other opcodes by name.
+fn disassemble(script: &Script) -> String {
+    let mut words = Vec::new();
+    for instruction in script.instructions() {
+        let word = match instruction {
+            Ok(Instruction::PushBytes(bytes)) if bytes.is_empty() => "0".to_owned(),
+            Ok(Instruction::PushBytes(bytes)) => hex(bytes.as_bytes()),
+            Ok(Instruction::Op(op)) => match pushnum(op) {
+                Some(n) => n.to_string(),
+                None if op == OP_CLTV => "OP_CHECKLOCKTIMEVERIFY".to_owned(),
+                None if op == OP_CSV => "OP_CHECKSEQUENCEVERIFY".to_owned(),
+                None if op.to_u8() == 0x4f => "-1".to_owned(),
+                None => op.to_string(),
+            },
+            Err(_) => {
+                words.push("[error]".to_owned());
+                break;
+            }
+        };
+        words.push(word);
+    }
+    words.join(" ")
+}
+
+/// Returns the number pushed by `OP_1` to `OP_16`.
+fn pushnum(op: Opcode) -> Option<u8> {
+    match op.to_u8() {
+        code @ 0x51..=0x60 => Some(code - 0x50),
+        _ => None,
+    }
+}
+
+fn hex<B: AsRef<[u8]>>(bytes: B) -> String {
+    bytes
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn createrawtransaction(network: Network, args: &Args) -> Result<Value> {
+    let inputs: Vec<Value> = args.get(0, "inputs", "[]btcjson.TransactionInput")?;
+    let amounts: Map<String, Value> = args.get(1, "amounts", "map[string]float64")?;
+    let locktime: i64 = args.get_or(2, "locktime", "int64", 0)?;
+
+    let mut tx = Transaction {
+        version: Version::ONE,
+        lock_time: LockTime::from_consensus(locktime as u32),
+        input: Vec::new(),
+        output: Vec::new(),
+    };
+    for input in &inputs {
+        let (txid, vout) = match (input["txid"].as_str(), input["vout"].as_u64()) {
+            (Some(txid), Some(vout)) => (txid, vout as u32),
+            _ => {
+                return Err(RpcError::new(
+                    ERR_INVALID_PARAMS,
+                    "parameter #1 'inputs' must be type []btcjson.TransactionInput",
+                ))
+            }
+        };
+        tx.input.push(TxIn {
+            previous_output: OutPoint::new(parse_hash(txid)?, vout),
+            script_sig: ScriptBuf::new(),
+            sequence: if locktime != 0 {
+                Sequence::ENABLE_LOCKTIME_NO_RBF
+            } else {
+                Sequence::MAX
+            },
+            witness: Witness::new(),
+        });
+    }
+    for (address, amount) in &amounts {
+        let amount = amount
+            .as_f64()
+            .and_then(|btc| Amount::from_btc(btc).ok())
+            .filter(|amount| *amount <= Amount::MAX_MONEY)
+            .ok_or_else(|| RpcError::new(ERR_TYPE, "Invalid amount"))?;
+        let address = Address::from_str(address)
+            .ok()
+            .and_then(|a| a.require_network(network).ok())
+            .ok_or_else(|| {
+                RpcError::new(
+                    ERR_INVALID_ADDRESS_OR_KEY,
+                    format!("Invalid address or key: {}", address),
+                )
+            })?;
+        tx.output.push(TxOut {
+            value: amount,
+            script_pubkey: address.script_pubkey(),
+        });
+    }
+    Ok(json!(hex(serialize(&tx))))
+}
+
+fn addnode(state: &mut State, addr: String, subcmd: &str) -> Result<()> {
+    match subcmd {
+        "add" | "onetry" => {
+            if state.peers.contains(&addr) {
+                return Err(RpcError::new(
+                    ERR_INVALID_PARAMETER,
+                    "peer already connected",
+                ));
+            }
+            if subcmd == "add" {
+                state.peers.push(addr);
+            }
+        }
+        "remove" => match state.peers.iter().position(|p| *p == addr) {
+            Some(i) => {
+                state.peers.remove(i);
+            }
+            None => return Err(RpcError::new(ERR_INVALID_PARAMETER, "peer not found")),
+        },
+        _ => {
+            return Err(RpcError::new(
+                ERR_INVALID_PARAMETER,
+                "invalid subcommand for addnode",
+            ))
+        }
+    }
+    Ok(())
+}
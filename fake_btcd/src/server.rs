@@ -0,0 +1,178 @@
+//! A small HTTP/1.1 server speaking JSON-RPC like btcd's RPC server.
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::rpc::{self, RpcError, ERR_INVALID_REQUEST, ERR_PARSE};
+use crate::{lock, State};
+
+/// The largest request body accepted, like the limit of jsonrpc's server.
+const MAX_REQUEST_SIZE: usize = 32 * 1024 * 1024;
+
+/// Accepts connections until `stop` is set, serving each on its own thread.
+pub(crate) fn serve(
+    listener: TcpListener,
+    state: Arc<Mutex<State>>,
+    auth: Option<String>,
+    stop: Arc<AtomicBool>,
+) {
+    for stream in listener.incoming() {
+        if stop.load(Ordering::SeqCst) {
+            return;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let state = state.clone();
+        let auth = auth.clone();
+        thread::spawn(move || {
+            let _ = serve_connection(stream, &state, auth.as_deref());
+        });
+    }
+}
+
+/// Answers requests on a connection until the client closes it.
+fn serve_connection(stream: TcpStream, state: &Mutex<State>, auth: Option<&str>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line)? == 0 {
+            return Ok(());
+        }
+
+        let mut content_length = Some(0);
+        let mut authorization = None;
+        let mut close = false;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let mut header = line.splitn(2, ':');
+            let name = header.next().unwrap_or_default().to_ascii_lowercase();
+            let value = header.next().unwrap_or_default().trim();
+            match name.as_str() {
+                "content-length" => content_length = value.parse().ok(),
+                "authorization" => authorization = Some(value.to_owned()),
+                "connection" => close = value.eq_ignore_ascii_case("close"),
+                _ => {}
+            }
+        }
+        let mut body = match content_length {
+            Some(length) if length <= MAX_REQUEST_SIZE => vec![0; length],
+            Some(_) => {
+                let stream = reader.get_mut();
+                stream.write_all(b"HTTP/1.1 413 Payload Too Large\r\nContent-Length: 0\r\n\r\n")?;
+                return Ok(());
+            }
+            None => {
+                let stream = reader.get_mut();
+                stream.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")?;
+                return Ok(());
+            }
+        };
+        reader.read_exact(&mut body)?;
+
+        let stream = reader.get_mut();
+        if !request_line.starts_with("POST ") {
+            stream.write_all(b"HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\n\r\n")?;
+            return Ok(());
+        }
+        if auth.is_some() && authorization.as_deref() != auth {
+            let body = "401 Unauthorized.\n";
+            write!(
+                stream,
+                "HTTP/1.1 401 Unauthorized\r\nWww-Authenticate: Basic realm=\"btcd RPC\"\r\n\
+                 Content-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )?;
+            return Ok(());
+        }
+
+        let response = match respond(state, &body) {
+            Some(response) => response.to_string(),
+            None => String::new(),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            response.len(),
+            response
+        )?;
+        if close {
+            return Ok(());
+        }
+    }
+}
+
+/// Answers a request or a batch of requests. Returns nothing if they were all notifications.
+fn respond(state: &Mutex<State>, body: &[u8]) -> Option<Value> {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(_) => return Some(response(None, Err(RpcError::new(ERR_PARSE, "Parse error")))),
+    };
+    match request {
+        Value::Array(requests) if requests.is_empty() => Some(response(
+            None,
+            Err(RpcError::new(ERR_INVALID_REQUEST, "Invalid request")),
+        )),
+        Value::Array(requests) => {
+            let responses: Vec<_> = requests.iter().filter_map(|r| answer(state, r)).collect();
+            if responses.is_empty() {
+                None
+            } else {
+                Some(Value::Array(responses))
+            }
+        }
+        request => answer(state, &request),
+    }
+}
+
+/// Answers a single request, unless it's a JSON-RPC 2.0 notification.
+fn answer(state: &Mutex<State>, request: &Value) -> Option<Value> {
+    let version = request.get("jsonrpc").and_then(Value::as_str);
+    let id = request.get("id").cloned();
+    if version == Some("2.0") && id.is_none() {
+        // Still run it, but nobody's waiting for the result
+        if let Some(method) = request["method"].as_str() {
+            let params = request["params"].as_array().map_or(&[][..], |p| &p[..]);
+            let _ = rpc::handle(&mut lock(state), method, params);
+        }
+        return None;
+    }
+
+    let result = match (request["method"].as_str(), &request["params"]) {
+        (Some(method), Value::Array(params)) => rpc::handle(&mut lock(state), method, params),
+        (Some(method), Value::Null) => rpc::handle(&mut lock(state), method, &[]),
+        _ => Err(RpcError::new(ERR_INVALID_REQUEST, "Invalid request")),
+    };
+    let mut response = response(id, result);
+    if version == Some("2.0") {
+        let response = response.as_object_mut().unwrap();
+        response.insert("jsonrpc".into(), json!("2.0"));
+        // JSON-RPC 2.0 only has one of result and error
+        if response["error"].is_null() {
+            response.remove("error");
+        } else {
+            response.remove("result");
+        }
+    }
+    Some(response)
+}
+
+fn response(id: Option<Value>, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({"result": result, "error": null, "id": id}),
+        Err(error) => json!({"result": null, "error": error, "id": id}),
+    }
+}
@@ -3,6 +3,7 @@
 /// License: MIT
 pub mod blockchain;
 pub mod general;
+pub mod notifications;
 pub mod transaction;
 
 #[derive(Debug)]
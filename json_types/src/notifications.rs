@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::transaction::VerboseGetRawTransactionResult;
+
+/// An outpoint watched with `notifyspent` or `loadtxfilter`. Unlike [Outpoint], this is
+/// serialized as an object of the form {"hash":txid,"index":vout}, as btcd expects for these RPCs.
+///
+/// [Outpoint]: crate::transaction::Outpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchedOutpoint {
+    /// The id of the transaction that created this output
+    pub hash: String,
+    /// The index of this output in that transaction
+    pub index: u32,
+}
+
+/// A block scanned by `rescanblocks`, with the transactions matching the loaded filter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RescannedBlock {
+    /// The hash of the block
+    pub hash: String,
+    /// Hex-encoded transactions that matched the filter
+    #[serde(default)]
+    pub transactions: Vec<String>,
+}
+
+/// Where a transaction sent by `recvtx` and `redeemingtx` got mined.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockDetails {
+    pub height: i32,
+    pub hash: String,
+    /// The position of the transaction in the block
+    pub index: i32,
+    pub time: i64,
+}
+
+/// A notification sent by btcd over a websocket, after subscribing to it.
+#[derive(Debug)]
+pub enum Notification {
+    /// A block was added to the main chain. Sent after `notifyblocks`.
+    BlockConnected {
+        hash: String,
+        height: i32,
+        time: i64,
+    },
+    /// A block was removed from the main chain. Sent after `notifyblocks`.
+    BlockDisconnected {
+        hash: String,
+        height: i32,
+        time: i64,
+    },
+    /// A block was added to the main chain. `transactions` are the hex-encoded transactions of
+    /// this block matching the filter loaded with `loadtxfilter`. Sent after `notifyblocks`.
+    FilteredBlockConnected {
+        height: i32,
+        header: String,
+        transactions: Vec<String>,
+    },
+    /// A block was removed from the main chain. Sent after `notifyblocks`.
+    FilteredBlockDisconnected { height: i32, header: String },
+    /// A transaction was accepted to the mempool. Sent after `notifynewtransactions(false)`.
+    TxAccepted { txid: String, amount: f64 },
+    /// A transaction was accepted to the mempool. Sent after `notifynewtransactions(true)`.
+    TxAcceptedVerbose(Box<VerboseGetRawTransactionResult>),
+    /// A hex-encoded transaction matching the loaded filter was accepted to the mempool.
+    RelevantTxAccepted { transaction: String },
+    /// A hex-encoded transaction paying to an address watched with `notifyreceived`. `block`
+    /// is set if it got mined.
+    RecvTx {
+        transaction: String,
+        block: Option<BlockDetails>,
+    },
+    /// A hex-encoded transaction spending an outpoint watched with `notifyspent`. `block` is
+    /// set if it got mined.
+    RedeemingTx {
+        transaction: String,
+        block: Option<BlockDetails>,
+    },
+    /// Any other notification, left as is
+    Other { method: String, params: Value },
+}
+
+impl Notification {
+    /// Parses a notification given its method name and its positional parameters.
+    /// Unknown notifications are returned as [Notification::Other].
+    pub fn parse(method: &str, params: Value) -> Result<Notification, serde_json::Error> {
+        use serde_json::from_value;
+
+        let notification = match method {
+            "blockconnected" => {
+                let (hash, height, time) = from_value(params)?;
+                Notification::BlockConnected { hash, height, time }
+            }
+            "blockdisconnected" => {
+                let (hash, height, time) = from_value(params)?;
+                Notification::BlockDisconnected { hash, height, time }
+            }
+            "filteredblockconnected" => {
+                let (height, header, transactions): (_, _, Option<_>) = from_value(params)?;
+                Notification::FilteredBlockConnected {
+                    height,
+                    header,
+                    transactions: transactions.unwrap_or_default(),
+                }
+            }
+            "filteredblockdisconnected" => {
+                let (height, header) = from_value(params)?;
+                Notification::FilteredBlockDisconnected { height, header }
+            }
+            "txaccepted" => {
+                let (txid, amount) = from_value(params)?;
+                Notification::TxAccepted { txid, amount }
+            }
+            "txacceptedverbose" => {
+                let (tx,) = from_value(params)?;
+                Notification::TxAcceptedVerbose(Box::new(tx))
+            }
+            "relevanttxaccepted" => {
+                let (transaction,) = from_value(params)?;
+                Notification::RelevantTxAccepted { transaction }
+            }
+            "recvtx" => {
+                let (transaction, block) = from_value(with_optional_last(params, 2))?;
+                Notification::RecvTx { transaction, block }
+            }
+            "redeemingtx" => {
+                let (transaction, block) = from_value(with_optional_last(params, 2))?;
+                Notification::RedeemingTx { transaction, block }
+            }
+            _ => Notification::Other {
+                method: method.to_owned(),
+                params,
+            },
+        };
+        Ok(notification)
+    }
+}
+
+/// btcd omits trailing optional parameters, but tuples need all their fields. This pads
+/// `params` with nulls up to `len` elements.
+fn with_optional_last(params: Value, len: usize) -> Value {
+    match params {
+        Value::Array(mut params) => {
+            while params.len() < len {
+                params.push(Value::Null);
+            }
+            Value::Array(params)
+        }
+        params => params,
+    }
+}
@@ -102,33 +102,33 @@ pub struct DecodeRawTransactionResult {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VerboseGetRawTransactionResult {
     /// Hex-encoded transaction
-    hex: String,
+    pub hex: String,
     /// The serialized transaction hash without witness
-    txid: String,
+    pub txid: String,
     /// The serialized transaction hash with witness (wtxid)
-    hash: String,
+    pub hash: String,
     /// The serialized size, in bytes
-    size: u64,
+    pub size: u64,
     /// The serialized size in vBytes
-    vsize: u64,
+    pub vsize: u64,
     /// The transaction weight (between vsize*4-3 and vsize*4)
-    weight: u64,
+    pub weight: u64,
     /// The transaction version
-    version: u32,
+    pub version: u32,
     /// The transaction lock time
-    locktime: u32,
+    pub locktime: u32,
     /// The transaction inputs
-    vin: Vec<RawTxIn>,
+    pub vin: Vec<RawTxIn>,
     /// The transaction outputs
-    vout: Vec<RawTxOut>,
+    pub vout: Vec<RawTxOut>,
     /// The hash of the block this transaction have been confirmed in
-    blockhash: Option<String>,
+    pub blockhash: Option<String>,
     /// The number of blocks after this transaction was included in the chain tip
-    confirmations: Option<u32>,
+    pub confirmations: Option<u32>,
     /// The transaction time
-    time: Option<u32>,
+    pub time: Option<u32>,
     /// The block time
-    blocktime: Option<u32>,
+    pub blocktime: Option<u32>,
 }
 /// A pair of values referencing the best known block. It contains both a hash and
 /// height.
@@ -1,6 +1,6 @@
 #!/bin/sh -ex
 
-FEATURES="simple_http simple_tcp simple_uds proxy"
+FEATURES="simple_http simple_tcp simple_uds simple_ws tls async async_http proxy"
 
 cargo --version
 rustc --version
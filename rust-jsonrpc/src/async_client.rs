@@ -0,0 +1,189 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Async client support
+//!
+//! The async counterparts of [Transport](crate::Transport) and [Client](crate::Client), for
+//! use from async code without blocking the executor.
+//!
+
+use std::fmt;
+use std::sync::atomic;
+
+use async_trait::async_trait;
+use serde_json::value::RawValue;
+
+use super::{Params, Request, Response, Version};
+use crate::client::{check_response, match_batch};
+use crate::error::Error;
+
+/// An interface for an async transport over which to use the JSONRPC protocol.
+#[async_trait]
+pub trait AsyncTransport: Send + Sync + 'static {
+    /// Send an RPC request over the transport.
+    async fn send_request(&self, _: Request<'_>) -> Result<Response, Error>;
+    /// Send a batch of RPC requests over the transport. The batch may hold notifications,
+    /// which get no response: when it holds only notifications, there may be no response at
+    /// all.
+    async fn send_batch(&self, _: &[Request<'_>]) -> Result<Vec<Response>, Error>;
+    /// Send a notification, a request without id, over the transport. No response is waited
+    /// for. By default, it's sent as a batch of one.
+    async fn send_notification(&self, notification: Request<'_>) -> Result<(), Error> {
+        self.send_batch(&[notification]).await.map(|_| ())
+    }
+    /// Format the target of this transport.
+    /// I.e. the URL/socket/...
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result;
+}
+
+/// An async JSON-RPC client.
+///
+/// Create a new AsyncClient using one of the transport-specific constructors:
+/// - [AsyncClient::async_http] for the built-in HTTP transport
+pub struct AsyncClient {
+    pub(crate) transport: Box<dyn AsyncTransport>,
+    nonce: atomic::AtomicUsize,
+    version: Version,
+}
+
+impl AsyncClient {
+    /// Creates a new client with the given transport.
+    pub fn with_transport<T: AsyncTransport>(transport: T) -> AsyncClient {
+        AsyncClient {
+            transport: Box::new(transport),
+            nonce: atomic::AtomicUsize::new(1),
+            version: Version::default(),
+        }
+    }
+
+    /// Sets the version of the JSON-RPC protocol this client speaks, 2.0 by default. This
+    /// changes how its requests are built and which responses [`AsyncClient::call`] accepts.
+    pub fn with_version(mut self, version: Version) -> AsyncClient {
+        self.version = version;
+        self
+    }
+
+    /// Returns the version of the JSON-RPC protocol this client speaks.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Builds a request.
+    ///
+    /// To construct the arguments, one can use one of the shorthand methods
+    /// [`crate::arg`] or [`crate::try_arg`].
+    pub fn build_request<'a>(&self, method: &'a str, params: &'a [Box<RawValue>]) -> Request<'a> {
+        self.request_with_id(method, Params::ByPosition(params))
+    }
+
+    /// Builds a request with parameters by name.
+    pub fn build_request_by_name<'a>(
+        &self,
+        method: &'a str,
+        params: &'a [(&'a str, Box<RawValue>)],
+    ) -> Request<'a> {
+        self.request_with_id(method, Params::ByName(params))
+    }
+
+    /// Builds a notification, a request without id that the server doesn't answer. With
+    /// JSON-RPC 1.0, its id is null instead.
+    pub fn build_notification<'a>(&self, method: &'a str, params: Params<'a>) -> Request<'a> {
+        Request {
+            method,
+            params,
+            id: match self.version {
+                Version::V1 => Some(serde_json::Value::Null),
+                Version::V2 => None,
+            },
+            jsonrpc: Some(self.version.as_str()),
+        }
+    }
+
+    fn request_with_id<'a>(&self, method: &'a str, params: Params<'a>) -> Request<'a> {
+        let nonce = self.nonce.fetch_add(1, atomic::Ordering::Relaxed);
+        Request {
+            method,
+            params,
+            id: Some(serde_json::Value::from(nonce)),
+            jsonrpc: Some(self.version.as_str()),
+        }
+    }
+
+    /// Sends a request to a client
+    pub async fn send_request(&self, request: Request<'_>) -> Result<Response, Error> {
+        self.transport.send_request(request).await
+    }
+
+    /// Sends a notification, built with [`AsyncClient::build_notification`]. This returns as
+    /// soon as it is sent, since the server doesn't answer it.
+    pub async fn send_notification(&self, notification: Request<'_>) -> Result<(), Error> {
+        self.transport.send_notification(notification).await
+    }
+
+    /// Sends a batch of requests to the client.  The return vector holds the response
+    /// for the request at the corresponding index.  If no response was provided, it's [None].
+    /// That's always the case for notifications.
+    ///
+    /// Note that the requests need to have valid IDs, so it is advised to create the requests
+    /// with [`AsyncClient::build_request`].
+    pub async fn send_batch(
+        &self,
+        requests: &[Request<'_>],
+    ) -> Result<Vec<Option<Response>>, Error> {
+        if requests.is_empty() {
+            return Err(Error::EmptyBatch);
+        }
+
+        let responses = self.transport.send_batch(requests).await?;
+        match_batch(requests, responses)
+    }
+
+    /// Make a request and deserialize the response.
+    ///
+    /// To construct the arguments, one can use one of the shorthand methods
+    /// [`crate::arg`] or [`crate::try_arg`].
+    pub async fn call<R: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        method: &str,
+        args: &[Box<RawValue>],
+    ) -> Result<R, Error> {
+        self.call_with(self.build_request(method, args)).await
+    }
+
+    /// Make a request with parameters by name and deserialize the response.
+    pub async fn call_by_name<R: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        method: &str,
+        args: &[(&str, Box<RawValue>)],
+    ) -> Result<R, Error> {
+        self.call_with(self.build_request_by_name(method, args)).await
+    }
+
+    async fn call_with<R: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        request: Request<'_>,
+    ) -> Result<R, Error> {
+        let id = request.id.clone();
+        let response = self.send_request(request).await?;
+        check_response(response, id.as_ref(), self.version)
+    }
+}
+
+impl fmt::Debug for AsyncClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "jsonrpc::AsyncClient(")?;
+        self.transport.fmt_target(f)?;
+        write!(f, ")")
+    }
+}
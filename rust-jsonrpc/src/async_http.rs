@@ -0,0 +1,754 @@
+//! This module implements an async HTTP transport running on tokio. It speaks the same subset
+//! of HTTP as [simple_http](crate::simple_http), and is configured the same way, but never
+//! blocks the executor.
+//!
+//! Connecting through a SOCKS5 proxy isn't supported by this transport.
+
+use std::fmt;
+use std::io;
+#[cfg(feature = "tls")]
+use std::io::{Read, Write};
+#[cfg(feature = "tls")]
+use std::path::Path;
+use std::slice;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::async_client::AsyncTransport;
+use crate::auth::CredentialProvider;
+use crate::http::{self, BodyLength, ParseError, ResponseHead};
+use crate::retry::is_idempotent;
+use crate::simple_http::{self, is_closed, Error, SimpleHttpTransport};
+use crate::{Request, Response};
+
+/// The size of the buffer responses are read into.
+const READ_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Async HTTP transport, the async counterpart of [SimpleHttpTransport].
+#[derive(Clone)]
+pub struct AsyncHttpTransport {
+    /// Where and how to connect: address, host, path, credentials, TLS and keep-alive.
+    tp: SimpleHttpTransport,
+    /// Idle keep-alive connections, shared by all clones of the transport.
+    pool: Arc<Mutex<Vec<(Connection, Instant)>>>,
+}
+
+impl AsyncHttpTransport {
+    /// Returns a builder for `AsyncHttpTransport`
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    /// Opens a new connection to the server, wrapping it in TLS if configured to do so.
+    async fn connect(&self) -> Result<Connection, Error> {
+        let addr = (self.tp.hostname.as_str(), self.tp.port);
+        let sock = match tokio::time::timeout(self.tp.timeout, TcpStream::connect(addr)).await {
+            Ok(sock) => sock?,
+            Err(_) => return Err(Error::Timeout),
+        };
+        sock.set_nodelay(true)?;
+
+        #[cfg(feature = "tls")]
+        if let Some(conn) = self.tp.tls_connection()? {
+            let mut tls = TlsStream {
+                sock,
+                conn,
+            };
+            tls.handshake().await?;
+            return Ok(Connection::new(Socket::Tls(Box::new(tls))));
+        }
+
+        Ok(Connection::new(Socket::Plain(sock)))
+    }
+
+    /// Takes an idle connection from the pool if there is one, or opens a new one.
+    /// The returned flag tells whether the connection is being reused.
+    async fn get_connection(&self) -> Result<(Connection, bool), Error> {
+        if self.tp.pool_size > 0 {
+            let mut idle = self.pool.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            idle.retain(|(_, since)| now.duration_since(*since) < self.tp.idle_timeout);
+            if let Some((conn, _)) = idle.pop() {
+                return Ok((conn, true));
+            }
+        }
+        Ok((self.connect().await?, false))
+    }
+
+    /// Gives a connection back to the pool, unless it is full.
+    fn put_connection(&self, conn: Connection) {
+        let mut idle = self.pool.lock().unwrap_or_else(|e| e.into_inner());
+        if idle.len() < self.tp.pool_size {
+            idle.push((conn, Instant::now()));
+        }
+    }
+
+    /// Sends a request and deserializes the response. A request that gets no answer, like a
+    /// notification, gets an empty body back: `if_empty` then gives the result. If the
+    /// server closes a reused connection after the request was written, it's only sent again
+    /// if it's `idempotent`, since the server may have run it.
+    async fn request<R>(
+        &self,
+        req: impl serde::Serialize,
+        if_empty: Option<fn() -> R>,
+        idempotent: bool,
+    ) -> Result<R, Error>
+    where
+        R: for<'a> serde::de::Deserialize<'a>,
+    {
+        // Serialize the body first so we can set the Content-Length header.
+        let body = serde_json::to_vec(&req)?;
+
+        let exchange = async {
+            let mut refreshed_auth = false;
+            loop {
+                let (mut conn, reused) = self.get_connection().await?;
+                let written = self.write_request(&mut conn, &body).await;
+                let sent = written.is_ok();
+                let res = match written {
+                    Ok(()) => self.read_response(&mut conn).await,
+                    Err(e) => Err(e),
+                };
+                match res {
+                    Ok((code, resp_body, keep_alive)) => {
+                        if keep_alive && self.tp.pool_size > 0 {
+                            self.put_connection(conn);
+                        }
+                        return Ok((code, resp_body));
+                    }
+                    // The server closed an idle connection under our feet, try a fresh one.
+                    Err(Error::SocketError(ref e))
+                        if reused && is_closed(e) && (!sent || idempotent) =>
+                    {
+                        continue
+                    }
+                    // The node may have rotated its cookie, try once more if it did.
+                    Err(Error::HttpErrorCode(401))
+                        if !refreshed_auth && self.tp.refresh_auth()? =>
+                    {
+                        refreshed_auth = true;
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+        let (response_code, resp_body) = match tokio::time::timeout(self.tp.timeout, exchange).await
+        {
+            Ok(res) => res?,
+            Err(_) => return Err(Error::Timeout),
+        };
+        if let Some(empty) = if_empty {
+            if resp_body.is_empty() && (200..300).contains(&response_code) {
+                return Ok(empty());
+            }
+        }
+        parse_response(response_code, &resp_body)
+    }
+
+    /// Writes a request to the given connection.
+    async fn write_request(&self, conn: &mut Connection, body: &[u8]) -> Result<(), Error> {
+        let mut request = self.tp.request_head(body.len())?;
+        request.extend_from_slice(body);
+        conn.sock.write_all(&request).await?;
+        Ok(())
+    }
+
+    /// Reads the response to a request from the given connection. Returns the HTTP status
+    /// code, the body and whether the connection can be used for another request.
+    async fn read_response(&self, conn: &mut Connection) -> Result<(u16, String, bool), Error> {
+        let head = conn.read_head().await?;
+        if head.status == 401 {
+            // There is no body in a 401 response, so don't try to read it
+            return Err(Error::HttpErrorCode(head.status));
+        }
+        let resp_body = conn.read_body(&head, self.tp.max_response_size).await?;
+        let resp_body = String::from_utf8(resp_body).map_err(|_| Error::HttpParseError)?;
+        Ok((head.status, resp_body, head.keep_alive()))
+    }
+}
+
+/// Deserializes the body of a response. Even if the status is != 200, we parse the response
+/// as we may get a JSONRPC error instead of the less meaningful HTTP error code.
+fn parse_response<R>(response_code: u16, resp_body: &str) -> Result<R, Error>
+where
+    R: for<'a> serde::de::Deserialize<'a>,
+{
+    match serde_json::from_str(resp_body) {
+        Ok(s) => Ok(s),
+        Err(e) => {
+            if response_code != 200 {
+                Err(Error::HttpErrorCode(response_code))
+            } else {
+                // If it was 200 then probably it was legitimately a parse error
+                Err(e.into())
+            }
+        }
+    }
+}
+
+impl fmt::Debug for AsyncHttpTransport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AsyncHttpTransport(")?;
+        self.fmt_target(f)?;
+        write!(f, ")")
+    }
+}
+
+#[async_trait]
+impl AsyncTransport for AsyncHttpTransport {
+    async fn send_request(&self, req: Request<'_>) -> Result<Response, crate::Error> {
+        let idempotent = is_idempotent(slice::from_ref(&req));
+        Ok(self.request(req, None, idempotent).await?)
+    }
+
+    async fn send_batch(&self, reqs: &[Request<'_>]) -> Result<Vec<Response>, crate::Error> {
+        let if_empty = if crate::expects_response(reqs) {
+            None
+        } else {
+            Some(Vec::new as fn() -> Vec<Response>)
+        };
+        Ok(self.request(reqs, if_empty, is_idempotent(reqs)).await?)
+    }
+
+    async fn send_notification(&self, notification: Request<'_>) -> Result<(), crate::Error> {
+        let idempotent = is_idempotent(slice::from_ref(&notification));
+        self.request(notification, Some(|| serde::de::IgnoredAny), idempotent).await?;
+        Ok(())
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        crate::Transport::fmt_target(&self.tp, f)
+    }
+}
+
+/// A connection to the server, with the bytes received but not read yet.
+struct Connection {
+    sock: Socket,
+    buf: Box<[u8]>,
+    /// The unread bytes are `buf[pos..end]`
+    pos: usize,
+    end: usize,
+}
+
+impl Connection {
+    fn new(sock: Socket) -> Connection {
+        Connection {
+            sock,
+            buf: vec![0; READ_BUFFER_SIZE].into_boxed_slice(),
+            pos: 0,
+            end: 0,
+        }
+    }
+
+    /// Returns the unread bytes, reading more from the socket if there are none. An empty
+    /// result means the server closed the connection.
+    async fn fill_buf(&mut self) -> Result<&[u8], ParseError> {
+        if self.pos == self.end {
+            self.pos = 0;
+            self.end = self.sock.read(&mut self.buf).await?;
+        }
+        Ok(&self.buf[self.pos..self.end])
+    }
+
+    /// Reads a line terminated by CRLF (or a bare LF) and returns it without the terminator.
+    async fn read_line(&mut self) -> Result<String, ParseError> {
+        let mut line = Vec::new();
+        loop {
+            let buf = self.fill_buf().await?;
+            if buf.is_empty() {
+                return Err(ParseError::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+            let (used, done) = match buf.iter().position(|&b| b == b'\n') {
+                Some(pos) => (pos + 1, true),
+                None => (buf.len(), false),
+            };
+            line.extend_from_slice(&buf[..used]);
+            self.pos += used;
+            if line.len() > http::MAX_LINE_LENGTH {
+                return Err(ParseError::Malformed);
+            }
+            if done {
+                break;
+            }
+        }
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        String::from_utf8(line).map_err(|_| ParseError::Malformed)
+    }
+
+    /// Reads exactly `buf.len()` bytes.
+    async fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<(), ParseError> {
+        while !buf.is_empty() {
+            let available = self.fill_buf().await?;
+            if available.is_empty() {
+                return Err(ParseError::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+            let n = available.len().min(buf.len());
+            buf[..n].copy_from_slice(&available[..n]);
+            self.pos += n;
+            buf = &mut buf[n..];
+        }
+        Ok(())
+    }
+
+    /// Reads the status line and the headers of a response, skipping interim (1xx) responses.
+    async fn read_head(&mut self) -> Result<ResponseHead, ParseError> {
+        loop {
+            let (http10, status) = http::parse_status_line(&self.read_line().await?)?;
+
+            let mut headers = Vec::new();
+            loop {
+                let line = self.read_line().await?;
+                if line.is_empty() {
+                    break;
+                }
+                if headers.len() == http::MAX_HEADERS {
+                    return Err(ParseError::Malformed);
+                }
+                headers.push(http::parse_header(&line)?);
+            }
+
+            if http::is_final(status) {
+                return Ok(ResponseHead {
+                    http10,
+                    status,
+                    headers,
+                });
+            }
+        }
+    }
+
+    /// Reads the body of a response whose head has already been read. Bodies larger than
+    /// `max_size` are rejected.
+    async fn read_body(
+        &mut self,
+        head: &ResponseHead,
+        max_size: usize,
+    ) -> Result<Vec<u8>, ParseError> {
+        match head.body_length()? {
+            BodyLength::Empty => Ok(Vec::new()),
+            BodyLength::Fixed(len) if len > max_size => Err(ParseError::TooLarge),
+            BodyLength::Fixed(len) => {
+                let mut body = vec![0; len];
+                self.read_exact(&mut body).await?;
+                Ok(body)
+            }
+            BodyLength::Chunked => {
+                let mut body = Vec::new();
+                loop {
+                    let size = http::parse_chunk_size(&self.read_line().await?)?;
+                    if size == 0 {
+                        break;
+                    }
+                    let start = body.len();
+                    let end = start.checked_add(size).ok_or(ParseError::Malformed)?;
+                    if end > max_size {
+                        return Err(ParseError::TooLarge);
+                    }
+                    body.resize(end, 0);
+                    self.read_exact(&mut body[start..]).await?;
+                    if !self.read_line().await?.is_empty() {
+                        return Err(ParseError::Malformed);
+                    }
+                }
+                // Skip the trailer fields
+                while !self.read_line().await?.is_empty() {}
+                Ok(body)
+            }
+            BodyLength::UntilClose => {
+                let mut body = Vec::new();
+                loop {
+                    let buf = match self.fill_buf().await {
+                        Ok(buf) => buf,
+                        // TLS peers may close the connection without a close_notify
+                        Err(ParseError::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                            break
+                        }
+                        Err(e) => return Err(e),
+                    };
+                    if buf.is_empty() {
+                        break;
+                    }
+                    if body.len() + buf.len() > max_size {
+                        return Err(ParseError::TooLarge);
+                    }
+                    body.extend_from_slice(buf);
+                    self.pos = self.end;
+                }
+                Ok(body)
+            }
+        }
+    }
+}
+
+/// A connection to the server, either plain text or wrapped in TLS.
+enum Socket {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<TlsStream>),
+}
+
+impl Socket {
+    async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Socket::Plain(ref mut sock) => sock.read(buf).await,
+            #[cfg(feature = "tls")]
+            Socket::Tls(ref mut tls) => tls.read(buf).await,
+        }
+    }
+
+    async fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match *self {
+            Socket::Plain(ref mut sock) => sock.write_all(buf).await,
+            #[cfg(feature = "tls")]
+            Socket::Tls(ref mut tls) => tls.write_all(buf).await,
+        }
+    }
+}
+
+/// A TLS session driven over a tokio socket.
+#[cfg(feature = "tls")]
+struct TlsStream {
+    sock: TcpStream,
+    conn: rustls::ClientConnection,
+}
+
+#[cfg(feature = "tls")]
+impl TlsStream {
+    /// Sends the TLS records waiting to be written.
+    async fn flush(&mut self) -> io::Result<()> {
+        while self.conn.wants_write() {
+            let mut records = Vec::new();
+            self.conn.write_tls(&mut records)?;
+            self.sock.write_all(&records).await?;
+        }
+        Ok(())
+    }
+
+    /// Reads TLS records from the socket and processes them. Returns false on end of file.
+    async fn receive(&mut self) -> io::Result<bool> {
+        let mut records = [0u8; READ_BUFFER_SIZE];
+        let n = self.sock.read(&mut records).await?;
+        let mut received = &records[..n];
+        if n == 0 {
+            // Lets rustls know about the end of the stream
+            self.conn.read_tls(&mut received)?;
+        }
+        while !received.is_empty() {
+            self.conn.read_tls(&mut received)?;
+            self.process()?;
+        }
+        self.process()?;
+        Ok(n != 0)
+    }
+
+    fn process(&mut self) -> io::Result<()> {
+        self.conn
+            .process_new_packets()
+            .map(|_| ())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    async fn handshake(&mut self) -> io::Result<()> {
+        while self.conn.is_handshaking() {
+            self.flush().await?;
+            if self.conn.is_handshaking() && !self.receive().await? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        self.flush().await
+    }
+
+    async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.conn.reader().read(buf) {
+                Ok(n) => return Ok(n),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+            self.flush().await?;
+            self.receive().await?;
+        }
+    }
+
+    async fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let n = self.conn.writer().write(buf)?;
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            buf = &buf[n..];
+            self.flush().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Builder for `AsyncHttpTransport`s
+#[derive(Clone, Debug)]
+pub struct Builder {
+    http: simple_http::Builder,
+}
+
+impl Builder {
+    /// Construct new `Builder` with default configuration
+    pub fn new() -> Builder {
+        Builder {
+            http: simple_http::Builder::new(),
+        }
+    }
+
+    /// Sets the timeout after which requests will abort if they aren't finished
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.http = self.http.timeout(timeout);
+        self
+    }
+
+    /// Set the URL of the server to the transport, see [simple_http::Builder::url].
+    pub fn url(mut self, url: &str) -> Result<Self, Error> {
+        self.http = self.http.url(url)?;
+        Ok(self)
+    }
+
+    /// Add authentication information to the transport.
+    pub fn auth<S: AsRef<str>>(mut self, user: S, pass: Option<S>) -> Self {
+        self.http = self.http.auth(user, pass);
+        self
+    }
+
+    /// Add authentication information to the transport using a cookie string ('user:pass'),
+    /// see [simple_http::Builder::cookie_auth].
+    pub fn cookie_auth<S: AsRef<str>>(mut self, cookie: S) -> Self {
+        self.http = self.http.cookie_auth(cookie);
+        self
+    }
+
+    /// Take the credentials from `provider`, see [simple_http::Builder::credentials].
+    pub fn credentials<P: CredentialProvider + 'static>(mut self, provider: P) -> Self {
+        self.http = self.http.credentials(provider);
+        self
+    }
+
+    #[cfg(feature = "tls")]
+    /// Use TLS and only accept the certificate(s) found in the given PEM file, see
+    /// [simple_http::Builder::tls_pinned_cert].
+    pub fn tls_pinned_cert<P: AsRef<Path>>(mut self, cert: P) -> Result<Self, Error> {
+        self.http = self.http.tls_pinned_cert(cert)?;
+        Ok(self)
+    }
+
+    #[cfg(feature = "tls")]
+    /// Use TLS and verify the server's certificate against the CA certificates found in
+    /// the given PEM file.
+    pub fn tls_ca_bundle<P: AsRef<Path>>(mut self, bundle: P) -> Result<Self, Error> {
+        self.http = self.http.tls_ca_bundle(bundle)?;
+        Ok(self)
+    }
+
+    #[cfg(feature = "tls")]
+    /// Use TLS with a custom `rustls` client configuration.
+    pub fn tls_config(mut self, config: Arc<rustls::ClientConfig>) -> Self {
+        self.http = self.http.tls_config(config);
+        self
+    }
+
+    /// Keep up to `size` idle connections alive and reuse them for later requests. A size
+    /// of 0, the default, opens a new connection for every request.
+    pub fn pool_size(mut self, size: usize) -> Self {
+        self.http = self.http.pool_size(size);
+        self
+    }
+
+    /// Sets how long an idle pooled connection is kept before it's closed.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.http = self.http.idle_timeout(timeout);
+        self
+    }
+
+    /// Sets the largest response body accepted, in bytes.
+    pub fn max_response_size(mut self, size: usize) -> Self {
+        self.http = self.http.max_response_size(size);
+        self
+    }
+
+    /// Builds the final `AsyncHttpTransport`
+    pub fn build(self) -> AsyncHttpTransport {
+        AsyncHttpTransport {
+            tp: self.http.build(),
+            pool: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder::new()
+    }
+}
+
+impl crate::AsyncClient {
+    /// Create a new async JSON-RPC client using an HTTP transport.
+    pub fn async_http(
+        url: &str,
+        user: Option<String>,
+        pass: Option<String>,
+    ) -> Result<crate::AsyncClient, Error> {
+        let mut builder = Builder::new().url(url)?;
+        if let Some(user) = user {
+            builder = builder.auth(user, pass);
+        }
+        Ok(crate::AsyncClient::with_transport(builder.build()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    use super::*;
+    use crate::AsyncClient;
+
+    /// Answers every request with its method name as the result, keeping connections alive.
+    /// Returns the address of the server and the number of connections it accepted.
+    fn echo_server() -> (SocketAddr, Arc<AtomicUsize>) {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        thread::spawn(move || {
+            for stream in server.incoming() {
+                let stream = stream.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                thread::spawn(move || serve(stream));
+            }
+        });
+        (addr, accepted)
+    }
+
+    fn serve<S: Read + Write>(stream: S) {
+        let mut reader = BufReader::new(stream);
+        loop {
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                match reader.read_line(&mut line) {
+                    Ok(0) | Err(_) => return,
+                    Ok(_) => {}
+                }
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(len) = line.strip_prefix("Content-Length: ") {
+                    content_length = len.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let req: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let resp = serde_json::json!({"result": req["method"], "error": null, "id": req["id"]});
+            let resp = serde_json::to_vec(&resp).unwrap();
+            let stream = reader.get_mut();
+            write!(stream, "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n").unwrap();
+            write!(stream, "{:x}\r\n", resp.len()).unwrap();
+            stream.write_all(&resp).unwrap();
+            stream.write_all(b"\r\n0\r\n\r\n").unwrap();
+            stream.flush().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn async_requests() {
+        let (addr, accepted) = echo_server();
+        let tp = Builder::new().url(&addr.to_string()).unwrap().pool_size(1).build();
+        assert_eq!(format!("{:?}", tp), format!("AsyncHttpTransport(http://{}/)", addr));
+        let client = AsyncClient::with_transport(tp);
+
+        for _ in 0..3 {
+            let res: String = client.call("getblockcount", &[]).await.unwrap();
+            assert_eq!(res, "getblockcount");
+        }
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+        let client = AsyncClient::async_http(&addr.to_string(), None, None).unwrap();
+        let res: String = client.call("getinfo", &[]).await.unwrap();
+        assert_eq!(res, "getinfo");
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn async_timeout() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || {
+            // Accept, but never answer
+            let (_stream, _) = server.accept().unwrap();
+            thread::sleep(Duration::from_secs(2));
+        });
+
+        let tp = Builder::new()
+            .url(&addr.to_string())
+            .unwrap()
+            .timeout(Duration::from_millis(100))
+            .build();
+        let client = AsyncClient::with_transport(tp);
+        match client.call::<String>("getinfo", &[]).await {
+            Err(crate::Error::Transport(e)) => {
+                assert!(matches!(e.downcast_ref::<Error>(), Some(Error::Timeout)))
+            }
+            res => panic!("expected a timeout, got {:?}", res),
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn async_tls() {
+        let key = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+        let params = rcgen::CertificateParams::new(vec!["localhost".to_owned()]).unwrap();
+        let cert = params.self_signed(&key).unwrap();
+        let cert_path =
+            std::env::temp_dir().join(format!("async_tls_cert_{}.pem", std::process::id()));
+        std::fs::write(&cert_path, cert.pem()).unwrap();
+
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        let config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![cert.der().clone()],
+                rustls::pki_types::PrivateKeyDer::Pkcs8(key.serialize_der().into()),
+            )
+            .unwrap();
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || {
+            let (sock, _) = server.accept().unwrap();
+            let conn = rustls::ServerConnection::new(Arc::new(config)).unwrap();
+            serve(rustls::StreamOwned::new(conn, sock));
+        });
+
+        let tp = Builder::new()
+            .url(&format!("https://localhost:{}", addr.port()))
+            .unwrap()
+            .tls_pinned_cert(&cert_path)
+            .unwrap()
+            .pool_size(1)
+            .build();
+        std::fs::remove_file(&cert_path).unwrap();
+        let client = AsyncClient::with_transport(tp);
+        for _ in 0..2 {
+            let res: String = client.call("getbestblockhash", &[]).await.unwrap();
+            assert_eq!(res, "getbestblockhash");
+        }
+    }
+}
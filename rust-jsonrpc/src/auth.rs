@@ -0,0 +1,346 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Credential providers
+//!
+//! Where the HTTP based transports get the credentials they authenticate with. Besides fixed
+//! credentials, they can be read from the node's cookie file, from environment variables or
+//! from the output of a command.
+//!
+//! Credentials are fetched for the first request and cached. When the server answers a
+//! request with a 401, they are fetched again and, if they changed, the request is sent once
+//! more: this is how a client follows a node that rotated its cookie on restart.
+//!
+//! Passwords, and the `Authorization` headers built from them, are kept in [Secret]s: they
+//! are wiped from memory when dropped, and never show up in `Debug` output.
+//!
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::{env, fmt, fs, io};
+
+pub use zeroize::Zeroizing;
+
+use crate::util::lock;
+
+/// A password, or anything else that shouldn't leak. It's zeroized when dropped, and
+/// formatted as `[redacted]` by `Debug`. It doesn't implement `Display`, the secret has to be
+/// asked for with [Secret::expose].
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret(Zeroizing<String>);
+
+impl Secret {
+    /// Wraps `secret`.
+    pub fn new<S: Into<String>>(secret: S) -> Secret {
+        Secret(Zeroizing::new(secret.into()))
+    }
+
+    /// The secret itself.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(secret: String) -> Secret {
+        Secret::new(secret)
+    }
+}
+
+impl<'a> From<&'a str> for Secret {
+    fn from(secret: &'a str) -> Secret {
+        Secret::new(secret)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+/// A user name and password for HTTP basic authentication.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Credentials {
+    user: String,
+    password: Secret,
+}
+
+impl Credentials {
+    /// Creates credentials from a user name and password.
+    pub fn new<U: Into<String>, P: Into<Secret>>(user: U, password: P) -> Credentials {
+        Credentials {
+            user: user.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Parses a cookie, as written by bitcoind and btcd: `user:password`, with an optional
+    /// trailing newline.
+    pub fn from_cookie(cookie: &str) -> io::Result<Credentials> {
+        let cookie = cookie.trim_end_matches(|c| c == '\r' || c == '\n');
+        let mut parts = cookie.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some(user), Some(password)) => Ok(Credentials::new(user, password)),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "cookie is not user:password")),
+        }
+    }
+
+    /// The user name.
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    /// The password.
+    pub fn password(&self) -> &Secret {
+        &self.password
+    }
+
+    /// The value of the `Authorization` header for these credentials.
+    pub(crate) fn header(&self) -> Secret {
+        let cookie = Zeroizing::new(format!("{}:{}", self.user, self.password.expose()));
+        Secret::new(format!("Basic {}", base64::encode(cookie.as_bytes())))
+    }
+}
+
+/// Something the credentials can be fetched from.
+pub trait CredentialProvider: Send + Sync + fmt::Debug {
+    /// Returns the current credentials. This is called before the first request, and again
+    /// every time the server rejects the credentials.
+    fn credentials(&self) -> io::Result<Credentials>;
+}
+
+impl<P: CredentialProvider + ?Sized> CredentialProvider for Arc<P> {
+    fn credentials(&self) -> io::Result<Credentials> {
+        (**self).credentials()
+    }
+}
+
+impl<P: CredentialProvider + ?Sized> CredentialProvider for Box<P> {
+    fn credentials(&self) -> io::Result<Credentials> {
+        (**self).credentials()
+    }
+}
+
+/// Credentials that never change.
+#[derive(Clone, Debug)]
+pub struct StaticCredentials(pub Credentials);
+
+impl CredentialProvider for StaticCredentials {
+    fn credentials(&self) -> io::Result<Credentials> {
+        Ok(self.0.clone())
+    }
+}
+
+/// Credentials given as a cookie string. It's parsed when the credentials are needed, so an
+/// invalid cookie fails the requests.
+#[derive(Clone, Debug)]
+pub(crate) struct CookieString(pub(crate) Secret);
+
+impl CredentialProvider for CookieString {
+    fn credentials(&self) -> io::Result<Credentials> {
+        Credentials::from_cookie(self.0.expose())
+    }
+}
+
+/// Reads the credentials from a cookie file, like the `.cookie` file bitcoind writes to its
+/// data directory. The file is read again whenever the server rejects the credentials, so
+/// a new cookie is picked up after the node restarts.
+#[derive(Clone, Debug)]
+pub struct CookieFile {
+    path: PathBuf,
+}
+
+impl CookieFile {
+    /// Reads the cookie from the file at `path`.
+    pub fn new<P: AsRef<Path>>(path: P) -> CookieFile {
+        CookieFile {
+            path: path.as_ref().to_owned(),
+        }
+    }
+}
+
+impl CredentialProvider for CookieFile {
+    fn credentials(&self) -> io::Result<Credentials> {
+        let cookie = Zeroizing::new(fs::read_to_string(&self.path)?);
+        Credentials::from_cookie(&cookie)
+    }
+}
+
+/// Reads the user name and password from two environment variables.
+#[derive(Clone, Debug)]
+pub struct EnvCredentials {
+    user_var: String,
+    password_var: String,
+}
+
+impl EnvCredentials {
+    /// Reads the user name from `user_var` and the password from `password_var`.
+    pub fn new<U: Into<String>, P: Into<String>>(user_var: U, password_var: P) -> EnvCredentials {
+        EnvCredentials {
+            user_var: user_var.into(),
+            password_var: password_var.into(),
+        }
+    }
+}
+
+impl CredentialProvider for EnvCredentials {
+    fn credentials(&self) -> io::Result<Credentials> {
+        let var = |name: &str| {
+            env::var(name)
+                .map_err(|e| io::Error::new(io::ErrorKind::NotFound, format!("${}: {}", name, e)))
+        };
+        let password = Zeroizing::new(var(&self.password_var)?);
+        Ok(Credentials::new(var(&self.user_var)?, password.as_str()))
+    }
+}
+
+/// Runs a command and reads the credentials from its output, written as a cookie
+/// (`user:password`). This is how credentials are taken from a password manager or a
+/// secrets store.
+#[derive(Clone, Debug)]
+pub struct CommandCredentials {
+    program: OsString,
+    args: Vec<OsString>,
+}
+
+impl CommandCredentials {
+    /// Runs `program`, without arguments so far.
+    pub fn new<S: Into<OsString>>(program: S) -> CommandCredentials {
+        CommandCredentials {
+            program: program.into(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Adds an argument to the command.
+    pub fn arg<S: Into<OsString>>(mut self, arg: S) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+}
+
+impl CredentialProvider for CommandCredentials {
+    fn credentials(&self) -> io::Result<Credentials> {
+        let output = Command::new(&self.program).args(&self.args).output()?;
+        let stdout = Zeroizing::new(output.stdout);
+        if !output.status.success() {
+            let msg = format!("{:?} failed: {}", self.program, output.status);
+            return Err(io::Error::new(io::ErrorKind::Other, msg));
+        }
+        let cookie = std::str::from_utf8(&stdout)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "output is not UTF-8"))?;
+        Credentials::from_cookie(cookie.lines().next().unwrap_or_default())
+    }
+}
+
+/// A credential provider and the `Authorization` header made from its last credentials.
+pub(crate) struct Auth {
+    provider: Box<dyn CredentialProvider>,
+    header: Mutex<Option<Secret>>,
+}
+
+impl Auth {
+    pub(crate) fn new(provider: Box<dyn CredentialProvider>) -> Auth {
+        Auth {
+            provider,
+            header: Mutex::new(None),
+        }
+    }
+
+    /// The value of the `Authorization` header, asking the provider for credentials if we
+    /// don't have any yet.
+    pub(crate) fn header(&self) -> io::Result<Secret> {
+        let mut header = lock(&self.header);
+        if let Some(ref header) = *header {
+            return Ok(header.clone());
+        }
+        let new = self.provider.credentials()?.header();
+        *header = Some(new.clone());
+        Ok(new)
+    }
+
+    /// Asks the provider for credentials again, after the server rejected ours. Returns
+    /// whether they changed, that is whether it's worth sending the request again.
+    pub(crate) fn refresh(&self) -> io::Result<bool> {
+        let new = self.provider.credentials()?.header();
+        let mut header = lock(&self.header);
+        let changed = header.as_ref() != Some(&new);
+        *header = Some(new);
+        Ok(changed)
+    }
+}
+
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Auth").field(&self.provider).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cookie() {
+        let creds = Credentials::from_cookie("__cookie__:abc:def\n").unwrap();
+        assert_eq!((creds.user(), creds.password().expose()), ("__cookie__", "abc:def"));
+        assert!(Credentials::from_cookie("nocolon").is_err());
+        assert_eq!(
+            format!("{:?}", creds),
+            r#"Credentials { user: "__cookie__", password: [redacted] }"#
+        );
+
+        let path = env::temp_dir().join(format!("jsonrpc_cookie_{}", std::process::id()));
+        fs::write(&path, "user:first").unwrap();
+        let auth = Auth::new(Box::new(CookieFile::new(&path)));
+        assert_eq!(
+            auth.header().unwrap().expose(),
+            format!("Basic {}", base64::encode("user:first"))
+        );
+        // Cached until the server rejects it
+        fs::write(&path, "user:second").unwrap();
+        assert_eq!(
+            auth.header().unwrap().expose(),
+            format!("Basic {}", base64::encode("user:first"))
+        );
+        assert!(auth.refresh().unwrap());
+        assert!(!auth.refresh().unwrap());
+        assert_eq!(
+            auth.header().unwrap().expose(),
+            format!("Basic {}", base64::encode("user:second"))
+        );
+        fs::remove_file(&path).unwrap();
+        assert!(auth.refresh().is_err());
+    }
+
+    #[test]
+    fn env_and_command() {
+        env::set_var("JSONRPC_TEST_USER", "alice");
+        env::set_var("JSONRPC_TEST_PASS", "hunter2");
+        let creds = EnvCredentials::new("JSONRPC_TEST_USER", "JSONRPC_TEST_PASS");
+        assert_eq!(creds.credentials().unwrap(), Credentials::new("alice", "hunter2"));
+        let missing = EnvCredentials::new("JSONRPC_TEST_USER", "JSONRPC_TEST_MISSING");
+        assert_eq!(missing.credentials().unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        #[cfg(unix)]
+        {
+            let cmd = CommandCredentials::new("sh").arg("-c").arg("echo bob:s3cret; echo more");
+            assert_eq!(cmd.credentials().unwrap(), Credentials::new("bob", "s3cret"));
+            assert!(CommandCredentials::new("false").credentials().is_err());
+        }
+    }
+}
@@ -22,26 +22,54 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt;
 use std::sync::atomic;
+use std::time::Duration;
 
 use serde;
 use serde_json;
 use serde_json::value::RawValue;
 
-use super::{Request, Response};
+use super::{Params, Request, Response, Version};
+use crate::context::{self, CallOptions};
 use crate::error::Error;
+use crate::interceptor::{Intercepted, Interceptor};
 use crate::util::HashableValue;
 
 /// An interface for a transport over which to use the JSONRPC protocol.
 pub trait Transport: Send + Sync + 'static {
     /// Send an RPC request over the transport.
     fn send_request(&self, _: Request) -> Result<Response, Error>;
-    /// Send a batch of RPC requests over the transport.
+    /// Send a batch of RPC requests over the transport. The batch may hold notifications,
+    /// which get no response: when it holds only notifications, there may be no response at
+    /// all.
     fn send_batch(&self, _: &[Request]) -> Result<Vec<Response>, Error>;
+    /// Send a notification, a request without id, over the transport. No response is waited
+    /// for. By default, it's sent as a batch of one.
+    fn send_notification(&self, notification: Request) -> Result<(), Error> {
+        self.send_batch(&[notification]).map(|_| ())
+    }
     /// Format the target of this transport.
     /// I.e. the URL/socket/...
     fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result;
 }
 
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn send_request(&self, req: Request) -> Result<Response, Error> {
+        (**self).send_request(req)
+    }
+
+    fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, Error> {
+        (**self).send_batch(reqs)
+    }
+
+    fn send_notification(&self, notification: Request) -> Result<(), Error> {
+        (**self).send_notification(notification)
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt_target(f)
+    }
+}
+
 /// A JSON-RPC client.
 ///
 /// Create a new Client using one of the transport-specific constructors:
@@ -49,6 +77,8 @@ pub trait Transport: Send + Sync + 'static {
 pub struct Client {
     pub(crate) transport: Box<dyn Transport>,
     nonce: atomic::AtomicUsize,
+    version: Version,
+    method_timeouts: HashMap<String, Duration>,
 }
 
 impl Client {
@@ -57,6 +87,39 @@ impl Client {
         Client {
             transport: Box::new(transport),
             nonce: atomic::AtomicUsize::new(1),
+            version: Version::default(),
+            method_timeouts: HashMap::new(),
+        }
+    }
+
+    /// Sets the version of the JSON-RPC protocol this client speaks, 2.0 by default. This
+    /// changes how its requests are built and which responses [`Client::call`] accepts.
+    pub fn with_version(mut self, version: Version) -> Client {
+        self.version = version;
+        self
+    }
+
+    /// Returns the version of the JSON-RPC protocol this client speaks.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Gives the requests for `method` `timeout` to complete by default, instead of the
+    /// timeout of the transport. A deadline set for the call, see [crate::context], still
+    /// applies if it's earlier.
+    pub fn with_method_timeout(mut self, method: &str, timeout: Duration) -> Client {
+        self.method_timeouts.insert(method.to_owned(), timeout);
+        self
+    }
+
+    /// Runs `interceptor` around every request sent by this client, see [crate::interceptor].
+    /// It runs before the interceptors added so far.
+    pub fn with_interceptor<I: Interceptor>(self, interceptor: I) -> Client {
+        Client {
+            transport: Box::new(Intercepted::new(interceptor, self.transport)),
+            nonce: self.nonce,
+            version: self.version,
+            method_timeouts: self.method_timeouts,
         }
     }
 
@@ -65,25 +128,64 @@ impl Client {
     /// To construct the arguments, one can use one of the shorthand methods
     /// [`crate::arg`] or [`crate::try_arg`].
     pub fn build_request<'a>(&self, method: &'a str, params: &'a [Box<RawValue>]) -> Request<'a> {
+        self.request_with_id(method, Params::ByPosition(params))
+    }
+
+    /// Builds a request with parameters by name.
+    pub fn build_request_by_name<'a>(
+        &self,
+        method: &'a str,
+        params: &'a [(&'a str, Box<RawValue>)],
+    ) -> Request<'a> {
+        self.request_with_id(method, Params::ByName(params))
+    }
+
+    /// Builds a notification, a request without id that the server doesn't answer. With
+    /// JSON-RPC 1.0, its id is null instead.
+    pub fn build_notification<'a>(&self, method: &'a str, params: Params<'a>) -> Request<'a> {
+        Request {
+            method,
+            params,
+            id: match self.version {
+                Version::V1 => Some(serde_json::Value::Null),
+                Version::V2 => None,
+            },
+            jsonrpc: Some(self.version.as_str()),
+        }
+    }
+
+    fn request_with_id<'a>(&self, method: &'a str, params: Params<'a>) -> Request<'a> {
         let nonce = self.nonce.fetch_add(1, atomic::Ordering::Relaxed);
         Request {
             method,
             params,
-            id: serde_json::Value::from(nonce),
-            jsonrpc: Some("2.0"),
+            id: Some(serde_json::Value::from(nonce)),
+            jsonrpc: Some(self.version.as_str()),
         }
     }
 
     /// Sends a request to a client
     pub fn send_request(&self, request: Request) -> Result<Response, Error> {
-        self.transport.send_request(request)
+        let mut options = CallOptions::new();
+        if let Some(&timeout) = self.method_timeouts.get(request.method) {
+            options = options.timeout(timeout);
+        }
+        context::scope(&options, || context::run(|| self.transport.send_request(request)))
+    }
+
+    /// Sends a notification, built with [`Client::build_notification`]. This returns as soon as
+    /// it is sent, since the server doesn't answer it.
+    pub fn send_notification(&self, notification: Request) -> Result<(), Error> {
+        context::run(|| self.transport.send_notification(notification))
     }
 
     /// Sends a batch of requests to the client.  The return vector holds the response
     /// for the request at the corresponding index.  If no response was provided, it's [None].
+    /// That's always the case for notifications.
     ///
     /// Note that the requests need to have valid IDs, so it is advised to create the requests
-    /// with [`Client::build_request`].
+    /// with [`Client::build_request`]. Default timeouts per method don't apply to batches,
+    /// only the deadline of the call does.
     pub fn send_batch(&self, requests: &[Request]) -> Result<Vec<Option<Response>>, Error> {
         if requests.is_empty() {
             return Err(Error::EmptyBatch);
@@ -91,32 +193,8 @@ impl Client {
 
         // If the request body is invalid JSON, the response is a single response object.
         // We ignore this case since we are confident we are producing valid JSON.
-        let responses = self.transport.send_batch(requests)?;
-        if responses.len() > requests.len() {
-            return Err(Error::WrongBatchResponseSize);
-        }
-
-        //TODO(stevenroose) check if the server preserved order to avoid doing the mapping
-
-        // First index responses by ID and catch duplicate IDs.
-        let mut by_id = HashMap::with_capacity(requests.len());
-        for resp in responses.into_iter() {
-            let id = HashableValue(Cow::Owned(resp.id.clone()));
-            if let Some(dup) = by_id.insert(id, resp) {
-                return Err(Error::BatchDuplicateResponseId(dup.id));
-            }
-        }
-        // Match responses to the requests.
-        let results =
-            requests.iter().map(|r| by_id.remove(&HashableValue(Cow::Borrowed(&r.id)))).collect();
-
-        // Since we're also just producing the first duplicate ID, we can also just produce the
-        // first incorrect ID in case there are multiple.
-        if let Some(id) = by_id.keys().next() {
-            return Err(Error::WrongBatchResponseId((*id.0).clone()));
-        }
-
-        Ok(results)
+        let responses = context::run(|| self.transport.send_batch(requests))?;
+        match_batch(requests, responses)
     }
 
     /// Make a request and deserialize the response.
@@ -128,19 +206,91 @@ impl Client {
         method: &str,
         args: &[Box<RawValue>],
     ) -> Result<R, Error> {
-        let request = self.build_request(method, args);
-        let id = request.id.clone();
+        self.call_with(self.build_request(method, args))
+    }
 
+    /// Make a request with a deadline or a cancellation token and deserialize the response.
+    pub fn call_with_options<R: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        method: &str,
+        args: &[Box<RawValue>],
+        options: &CallOptions,
+    ) -> Result<R, Error> {
+        context::scope(options, || self.call(method, args))
+    }
+
+    /// Make a request with parameters by name and deserialize the response.
+    pub fn call_by_name<R: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        method: &str,
+        args: &[(&str, Box<RawValue>)],
+    ) -> Result<R, Error> {
+        self.call_with(self.build_request_by_name(method, args))
+    }
+
+    fn call_with<R: for<'a> serde::de::Deserialize<'a>>(
+        &self,
+        request: Request,
+    ) -> Result<R, Error> {
+        let id = request.id.clone();
         let response = self.send_request(request)?;
-        if response.jsonrpc != None && response.jsonrpc != Some(From::from("2.0")) {
-            return Err(Error::VersionMismatch);
-        }
-        if response.id != id {
-            return Err(Error::NonceMismatch);
+        check_response(response, id.as_ref(), self.version)
+    }
+}
+
+/// Matches the responses of a batch to its requests. The returned vector holds the response
+/// for the request at the corresponding index, or [None] if there was no response for it.
+pub(crate) fn match_batch(
+    requests: &[Request],
+    responses: Vec<Response>,
+) -> Result<Vec<Option<Response>>, Error> {
+    if responses.len() > requests.len() {
+        return Err(Error::WrongBatchResponseSize);
+    }
+
+    //TODO(stevenroose) check if the server preserved order to avoid doing the mapping
+
+    // First index responses by ID and catch duplicate IDs.
+    let mut by_id = HashMap::with_capacity(requests.len());
+    for resp in responses.into_iter() {
+        let id = HashableValue(Cow::Owned(resp.id.clone()));
+        if let Some(dup) = by_id.insert(id, resp) {
+            return Err(Error::BatchDuplicateResponseId(dup.id));
         }
+    }
+    // Match responses to the requests.
+    let results = requests
+        .iter()
+        .map(|r| r.id.as_ref().and_then(|id| by_id.remove(&HashableValue(Cow::Borrowed(id)))))
+        .collect();
 
-        response.result()
+    // Since we're also just producing the first duplicate ID, we can also just produce the
+    // first incorrect ID in case there are multiple.
+    if let Some(id) = by_id.keys().next() {
+        return Err(Error::WrongBatchResponseId((*id.0).clone()));
     }
+
+    Ok(results)
+}
+
+/// Checks that a response answers the request of the given version with the given id and
+/// deserializes its result.
+pub(crate) fn check_response<R: for<'a> serde::de::Deserialize<'a>>(
+    response: Response,
+    id: Option<&serde_json::Value>,
+    version: Version,
+) -> Result<R, Error> {
+    if !version.accepts(response.jsonrpc.as_deref()) {
+        return Err(Error::VersionMismatch);
+    }
+    if version == Version::V1 && !(response.has_result && response.has_error) {
+        return Err(Error::IncompleteResponse);
+    }
+    if Some(&response.id) != id {
+        return Err(Error::NonceMismatch);
+    }
+
+    response.result()
 }
 
 impl fmt::Debug for crate::Client {
@@ -179,4 +329,92 @@ mod tests {
         assert_eq!(client.nonce.load(sync::atomic::Ordering::Relaxed), 3);
         assert!(req1.id != req2.id);
     }
+
+    /// Answers every request with `true` and the given `jsonrpc` field.
+    struct Answer(Option<&'static str>);
+    impl Transport for Answer {
+        fn send_request(&self, req: Request) -> Result<Response, Error> {
+            Ok(Response {
+                result: Some(crate::arg(true)),
+                error: None,
+                id: req.id.unwrap_or_default(),
+                jsonrpc: self.0.map(From::from),
+                has_result: true,
+                has_error: true,
+            })
+        }
+        fn send_batch(&self, _: &[Request]) -> Result<Vec<Response>, Error> {
+            Ok(vec![])
+        }
+        fn fmt_target(&self, _: &mut fmt::Formatter) -> fmt::Result {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn versions() {
+        for &(version, answer, ok) in &[
+            (Version::V2, Some("2.0"), true),
+            (Version::V2, None, true),
+            (Version::V2, Some("1.0"), false),
+            (Version::V1, Some("1.0"), true),
+            (Version::V1, None, true),
+            (Version::V1, Some("2.0"), false),
+        ] {
+            let client = Client::with_transport(Answer(answer)).with_version(version);
+            match client.call::<bool>("test", &[]) {
+                Ok(res) => assert!(ok && res),
+                Err(Error::VersionMismatch) => assert!(!ok),
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        }
+
+        let client = Client::with_transport(DummyTransport).with_version(Version::V1);
+        assert_eq!(client.version(), Version::V1);
+        let req = serde_json::to_string(&client.build_request("test", &[])).unwrap();
+        assert_eq!(req, r#"{"method":"test","params":[],"id":1,"jsonrpc":"1.0"}"#);
+        let notification = client.build_notification("test", Params::ByPosition(&[]));
+        assert!(notification.is_notification());
+        let notification = serde_json::to_string(&notification).unwrap();
+        assert_eq!(notification, r#"{"method":"test","params":[],"id":null,"jsonrpc":"1.0"}"#);
+    }
+
+    /// Answers every request with the given JSON, its id replaced with the request's.
+    struct Raw(&'static str);
+    impl Transport for Raw {
+        fn send_request(&self, req: Request) -> Result<Response, Error> {
+            let mut resp: Response = serde_json::from_str(self.0)?;
+            resp.id = req.id.unwrap_or_default();
+            Ok(resp)
+        }
+        fn send_batch(&self, _: &[Request]) -> Result<Vec<Response>, Error> {
+            Ok(vec![])
+        }
+        fn fmt_target(&self, _: &mut fmt::Formatter) -> fmt::Result {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn v1_responses_have_result_and_error() {
+        for &(answer, v1_ok) in &[
+            (r#"{"result":true,"error":null,"id":0,"jsonrpc":"1.0"}"#, true),
+            (r#"{"result":true,"error":null,"id":0}"#, true),
+            (r#"{"result":true,"id":0,"jsonrpc":"1.0"}"#, false),
+            (r#"{"result":true,"id":0}"#, false),
+            (r#"{"error":null,"id":0,"jsonrpc":"1.0"}"#, false),
+        ] {
+            let client = Client::with_transport(Raw(answer)).with_version(Version::V1);
+            match client.call::<Option<bool>>("test", &[]) {
+                Ok(_) => assert!(v1_ok, "{}", answer),
+                Err(Error::IncompleteResponse) => assert!(!v1_ok, "{}", answer),
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+            // 2.0 doesn't ask for both members
+            if !answer.contains("jsonrpc") {
+                let client = Client::with_transport(Raw(answer));
+                client.call::<Option<bool>>("test", &[]).unwrap();
+            }
+        }
+    }
 }
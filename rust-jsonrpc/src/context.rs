@@ -0,0 +1,378 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Call deadlines and cancellation
+//!
+//! [CallOptions] give a call a deadline, after which it fails with [Error::DeadlineExceeded],
+//! and a [CancelToken], which aborts it with [Error::Cancelled] once cancelled from another
+//! thread. [Client::call_with_options](crate::Client::call_with_options) makes a single call
+//! with them, and [scope] applies them to all the calls a closure makes on the current thread.
+//!
+//! Scopes nest: a call gets the earliest deadline of all the scopes it runs in, and any of
+//! their tokens cancels it. A helper making several round trips in a scope thus spends one
+//! deadline on all of them. Default timeouts per method, set with
+//! [Client::with_method_timeout](crate::Client::with_method_timeout), never push it back.
+//!
+//! A timeout given with [CallOptions::timeout], or by a method timeout, replaces the timeout
+//! of the built-in transports, so it may be longer. A deadline given with
+//! [CallOptions::deadline] only caps it, like the transport's timeout.
+//!
+//! The [Client](crate::Client) doesn't send calls whose deadline passed or that were
+//! cancelled. The built-in transports don't wait for a response past the deadline, and drop
+//! the call once it's cancelled. Other transports can do the same with [request_deadline]
+//! and [on_cancel]. The options are kept per thread, so they don't apply to the
+//! [AsyncClient](crate::AsyncClient).
+//!
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{fmt, mem};
+
+use crate::error::Error;
+use crate::util::lock;
+
+thread_local! {
+    static CURRENT: RefCell<Context> = RefCell::new(Context::default());
+}
+
+/// The options applying to the calls made on a thread.
+#[derive(Clone, Default)]
+struct Context {
+    deadline: Option<Instant>,
+    /// The call has a timeout of its own, replacing the one of the transport.
+    own_timeout: bool,
+    tokens: Vec<CancelToken>,
+}
+
+impl Context {
+    fn is_cancelled(&self) -> bool {
+        self.tokens.iter().any(CancelToken::is_cancelled)
+    }
+
+    fn is_expired(&self) -> bool {
+        self.deadline.map_or(false, |deadline| Instant::now() >= deadline)
+    }
+}
+
+/// A deadline and a cancellation token for a call, both unset by default.
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    cancel: Option<CancelToken>,
+}
+
+impl CallOptions {
+    /// Returns options setting nothing.
+    pub fn new() -> CallOptions {
+        CallOptions::default()
+    }
+
+    /// Gives the call `timeout` to complete, counted from when it starts. This replaces the
+    /// timeout of the transport.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Gives the call until `deadline` to complete.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Aborts the call once `token` is cancelled.
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+}
+
+/// Runs `f` with `options` applying to all the calls it makes on the current thread, on top of
+/// the options of the scopes it runs in.
+pub fn scope<T, F: FnOnce() -> T>(options: &CallOptions, f: F) -> T {
+    /// Puts the previous options back, even if `f` panics.
+    struct Restore(Context);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = mem::take(&mut self.0);
+            let _ = CURRENT.try_with(|c| *c.borrow_mut() = previous);
+        }
+    }
+
+    let previous = CURRENT.with(|c| {
+        let mut c = c.borrow_mut();
+        let previous = c.clone();
+        let deadline = earliest(options.deadline, options.timeout.map(|t| Instant::now() + t));
+        c.deadline = earliest(c.deadline, deadline);
+        c.own_timeout |= options.timeout.is_some();
+        if let Some(ref token) = options.cancel {
+            c.tokens.push(token.clone());
+        }
+        previous
+    });
+    let _restore = Restore(previous);
+    f()
+}
+
+fn earliest(a: Option<Instant>, b: Option<Instant>) -> Option<Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Returns the deadline of the current call, if it has one.
+pub fn deadline() -> Option<Instant> {
+    CURRENT.with(|c| c.borrow().deadline)
+}
+
+/// Returns when a request sent now must be answered by, for a transport whose own timeout is
+/// `timeout`: the deadline of the current call, and `timeout` from now unless the call has a
+/// timeout of its own.
+pub fn request_deadline(timeout: Option<Duration>) -> Option<Instant> {
+    CURRENT.with(|c| {
+        let c = c.borrow();
+        let timeout = if c.own_timeout {
+            None
+        } else {
+            timeout
+        };
+        earliest(c.deadline, timeout.map(|t| Instant::now() + t))
+    })
+}
+
+/// Returns the time left until the deadline of the current call, if it has one.
+pub fn remaining() -> Option<Duration> {
+    deadline().map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// Returns whether the current call can be cancelled.
+pub fn is_cancellable() -> bool {
+    CURRENT.with(|c| !c.borrow().tokens.is_empty())
+}
+
+/// Returns whether the current call was cancelled.
+pub fn is_cancelled() -> bool {
+    CURRENT.with(|c| c.borrow().is_cancelled())
+}
+
+/// Runs `f` once the current call is cancelled, from the thread cancelling it, or right away
+/// if it already is. `f` isn't run anymore once the returned guard is dropped.
+pub fn on_cancel<F: Fn() + Send + Sync + 'static>(f: F) -> CancelGuard {
+    let tokens = CURRENT.with(|c| c.borrow().tokens.clone());
+    // Several tokens may cancel the call, `f` only runs for the first one
+    let done = AtomicBool::new(false);
+    let f: Arc<dyn Fn() + Send + Sync> = Arc::new(move || {
+        if !done.swap(true, Ordering::SeqCst) {
+            f()
+        }
+    });
+    let mut registered = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        match token.register(f.clone()) {
+            Some(id) => registered.push((token, id)),
+            None => {
+                f();
+                break;
+            }
+        }
+    }
+    CancelGuard {
+        registered,
+    }
+}
+
+/// Fails if the current call was cancelled or its deadline passed, before it's sent.
+pub(crate) fn check() -> Result<(), Error> {
+    CURRENT.with(|c| {
+        let c = c.borrow();
+        if c.is_cancelled() {
+            Err(Error::Cancelled)
+        } else if c.is_expired() {
+            Err(Error::DeadlineExceeded)
+        } else {
+            Ok(())
+        }
+    })
+}
+
+/// Runs the current call with `send`, reporting its errors as [Error::Cancelled] or
+/// [Error::DeadlineExceeded] if they're due to the options of the call.
+pub(crate) fn run<T, F: FnOnce() -> Result<T, Error>>(send: F) -> Result<T, Error> {
+    check()?;
+    send().map_err(|e| match check() {
+        Err(options_error) => options_error,
+        Ok(()) => e,
+    })
+}
+
+/// A token to cancel calls with, from any thread. Clones cancel the same calls.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<TokenState>);
+
+#[derive(Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    callbacks: Mutex<Callbacks>,
+}
+
+#[derive(Default)]
+struct Callbacks {
+    next_id: u64,
+    registered: HashMap<u64, Arc<dyn Fn() + Send + Sync>>,
+}
+
+impl CancelToken {
+    /// Returns a token that isn't cancelled.
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Cancels the calls using this token, now and later.
+    pub fn cancel(&self) {
+        let callbacks = {
+            let mut callbacks = lock(&self.0.callbacks);
+            if self.0.cancelled.swap(true, Ordering::SeqCst) {
+                return;
+            }
+            mem::take(&mut callbacks.registered)
+        };
+        for (_, f) in callbacks {
+            f();
+        }
+    }
+
+    /// Returns whether the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Registers `f` to run on cancellation, unless the token is already cancelled.
+    fn register(&self, f: Arc<dyn Fn() + Send + Sync>) -> Option<u64> {
+        let mut callbacks = lock(&self.0.callbacks);
+        if self.is_cancelled() {
+            return None;
+        }
+        let id = callbacks.next_id;
+        callbacks.next_id += 1;
+        callbacks.registered.insert(id, f);
+        Some(id)
+    }
+}
+
+impl fmt::Debug for CancelToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancelToken").field("cancelled", &self.is_cancelled()).finish()
+    }
+}
+
+/// Keeps a function registered with [on_cancel] until it's dropped.
+#[must_use]
+pub struct CancelGuard {
+    registered: Vec<(CancelToken, u64)>,
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        for (token, id) in self.registered.drain(..) {
+            lock(&token.0.callbacks).registered.remove(&id);
+        }
+    }
+}
+
+impl fmt::Debug for CancelGuard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancelGuard").field("tokens", &self.registered.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn nested_scopes() {
+        assert_eq!(deadline(), None);
+        let outer = Instant::now() + Duration::from_secs(10);
+        scope(&CallOptions::new().deadline(outer), || {
+            assert_eq!(deadline(), Some(outer));
+            // A later deadline doesn't extend the outer one, an earlier one shortens it
+            scope(&CallOptions::new().timeout(Duration::from_secs(60)), || {
+                assert_eq!(deadline(), Some(outer));
+            });
+            scope(&CallOptions::new().timeout(Duration::from_secs(1)), || {
+                assert!(deadline().unwrap() < outer);
+            });
+            assert_eq!(deadline(), Some(outer));
+        });
+        assert_eq!(deadline(), None);
+
+        scope(&CallOptions::new().deadline(Instant::now()), || {
+            assert!(matches!(check(), Err(Error::DeadlineExceeded)));
+            assert!(matches!(
+                run(|| Err::<(), _>(Error::EmptyBatch)),
+                Err(Error::DeadlineExceeded)
+            ));
+        });
+        assert!(matches!(run(|| Err::<(), _>(Error::EmptyBatch)), Err(Error::EmptyBatch)));
+    }
+
+    #[test]
+    fn cancellation() {
+        let outer = CancelToken::new();
+        let inner = CancelToken::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+        scope(&CallOptions::new().cancel_token(outer.clone()), || {
+            scope(&CallOptions::new().cancel_token(inner.clone()), || {
+                assert!(is_cancellable());
+                let counter = runs.clone();
+                let _guard = on_cancel(move || {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                });
+                // Cancelling the outer token cancels the inner calls, once
+                thread::spawn(move || outer.cancel()).join().unwrap();
+                assert!(is_cancelled());
+                assert!(matches!(check(), Err(Error::Cancelled)));
+                inner.cancel();
+            });
+        });
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert!(!is_cancellable());
+        assert!(!is_cancelled());
+
+        // Registering on a cancelled token runs right away, dropped guards don't run
+        let token = CancelToken::new();
+        scope(&CallOptions::new().cancel_token(token.clone()), || {
+            let counter = runs.clone();
+            drop(on_cancel(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }));
+            token.cancel();
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+            let counter = runs.clone();
+            let _guard = on_cancel(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+            assert_eq!(runs.load(Ordering::SeqCst), 2);
+        });
+    }
+}
@@ -14,14 +14,15 @@
 
 //! # Error handling
 //!
-//! Some useful methods for creating Error objects
+//! Some useful methods for creating Error objects, and the [ErrorKind] classifying them
 //!
 
-use std::{error, fmt};
+use std::{error, fmt, io};
 
 use serde::{Deserialize, Serialize};
 use serde_json;
 
+use crate::retry::DEFAULT_RETRY_CODES;
 use crate::Response;
 
 /// A library error
@@ -35,8 +36,10 @@ pub enum Error {
     Rpc(RpcError),
     /// Response to a request did not have the expected nonce
     NonceMismatch,
-    /// Response to a request had a jsonrpc field other than "2.0"
+    /// Response to a request had a jsonrpc field other than the version of the request
     VersionMismatch,
+    /// Response to a JSON-RPC 1.0 request lacked its `result` or `error` member
+    IncompleteResponse,
     /// Batches can't be empty
     EmptyBatch,
     /// Too many responses returned in batch
@@ -45,6 +48,168 @@ pub enum Error {
     BatchDuplicateResponseId(serde_json::Value),
     /// Batch response contained an ID that didn't correspond to any request ID
     WrongBatchResponseId(serde_json::Value),
+    /// The deadline of the call passed before it completed, see [crate::context]
+    DeadlineExceeded,
+    /// The call was cancelled, see [crate::context]
+    Cancelled,
+}
+
+impl Error {
+    /// Returns the kind of the error. Transport errors are classified by the first error of
+    /// their source chain that this crate knows about, so errors of other transports are
+    /// classified too if they wrap an [io::Error].
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::Transport(ref e) => classify_transport(&**e).0,
+            Error::Json(_) => ErrorKind::Deserialization,
+            Error::Rpc(_) => ErrorKind::Rpc,
+            Error::DeadlineExceeded => ErrorKind::Timeout,
+            Error::Cancelled => ErrorKind::Cancelled,
+            Error::NonceMismatch
+            | Error::VersionMismatch
+            | Error::IncompleteResponse
+            | Error::EmptyBatch
+            | Error::WrongBatchResponseSize
+            | Error::BatchDuplicateResponseId(_)
+            | Error::WrongBatchResponseId(_) => ErrorKind::Protocol,
+        }
+    }
+
+    /// Returns whether sending the request again as is may succeed: after connection errors,
+    /// timeouts of the transport, HTTP 5xx responses and the RPC errors of
+    /// [DEFAULT_RETRY_CODES]. A call whose own deadline passed isn't, it would fail right away.
+    ///
+    /// This doesn't tell whether the server ran the request, see [crate::retry].
+    pub fn is_retryable(&self) -> bool {
+        match *self {
+            Error::Transport(ref e) => classify_transport(&**e).1,
+            Error::Rpc(ref e) => DEFAULT_RETRY_CODES.contains(&e.code),
+            _ => false,
+        }
+    }
+}
+
+/// Returns the kind of a transport error and whether it's retryable, from the first error of
+/// its source chain we know about.
+fn classify_transport(err: &(dyn error::Error + 'static)) -> (ErrorKind, bool) {
+    let mut next = Some(err);
+    while let Some(err) = next {
+        if let Some(e) = err.downcast_ref::<io::Error>() {
+            let kind = ErrorKind::of_io(e);
+            return (kind, matches!(kind, ErrorKind::Connection | ErrorKind::Timeout));
+        }
+        if let Some(e) = err.downcast_ref::<Error>() {
+            return (e.kind(), e.is_retryable());
+        }
+        if let Some(e) = err.downcast_ref::<crate::failover::Error>() {
+            return (e.kind(), false);
+        }
+        #[cfg(feature = "simple_http")]
+        {
+            if let Some(e) = err.downcast_ref::<crate::simple_http::Error>() {
+                return (e.kind(), e.is_retryable());
+            }
+        }
+        #[cfg(feature = "simple_tcp")]
+        {
+            if let Some(e) = err.downcast_ref::<crate::simple_tcp::Error>() {
+                return (e.kind(), e.is_retryable());
+            }
+        }
+        #[cfg(all(feature = "simple_uds", not(windows)))]
+        {
+            if let Some(e) = err.downcast_ref::<crate::simple_uds::Error>() {
+                return (e.kind(), e.is_retryable());
+            }
+        }
+        #[cfg(feature = "simple_ws")]
+        {
+            if let Some(e) = err.downcast_ref::<crate::simple_ws::Error>() {
+                return (e.kind(), e.is_retryable());
+            }
+        }
+        next = err.source();
+    }
+    (ErrorKind::Other, false)
+}
+
+/// The broad kind of an [Error], see [Error::kind].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The server couldn't be reached, or the connection broke before the response arrived
+    Connection,
+    /// The response didn't arrive in time
+    Timeout,
+    /// The credentials couldn't be provided or were refused, or the certificate of the server
+    /// didn't verify
+    Auth,
+    /// The server answered something that isn't the expected JSON-RPC response
+    Protocol,
+    /// The server answered with an error response
+    Rpc,
+    /// The response couldn't be deserialized to the expected type
+    Deserialization,
+    /// The call was cancelled
+    Cancelled,
+    /// The transport isn't set up right, like an invalid URL or certificate file
+    Config,
+    /// A transport error we don't know about
+    Other,
+}
+
+impl ErrorKind {
+    /// Returns a short lowercase name for the kind, like `timeout`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Connection => "connection",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Auth => "auth",
+            ErrorKind::Protocol => "protocol",
+            ErrorKind::Rpc => "rpc",
+            ErrorKind::Deserialization => "deserialization",
+            ErrorKind::Cancelled => "cancelled",
+            ErrorKind::Config => "config",
+            ErrorKind::Other => "other",
+        }
+    }
+
+    /// Returns the kind of an I/O error. Besides timeouts and connection errors, a SOCKS proxy
+    /// refusing us, garbage from the peer and invalid addresses turn up as I/O errors.
+    pub(crate) fn of_io(err: &io::Error) -> ErrorKind {
+        // TLS errors of the handshake or of a record come wrapped in an I/O error
+        #[cfg(feature = "tls")]
+        {
+            if let Some(e) = err.get_ref().and_then(|e| e.downcast_ref::<rustls::Error>()) {
+                return ErrorKind::of_tls(e);
+            }
+        }
+        match err.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ErrorKind::Timeout,
+            io::ErrorKind::PermissionDenied => ErrorKind::Auth,
+            io::ErrorKind::InvalidData => ErrorKind::Protocol,
+            io::ErrorKind::InvalidInput => ErrorKind::Config,
+            _ => ErrorKind::Connection,
+        }
+    }
+
+    /// Returns the kind of a TLS error: an auth error if the certificate of the server didn't
+    /// verify, a protocol error otherwise.
+    #[cfg(feature = "tls")]
+    pub(crate) fn of_tls(err: &rustls::Error) -> ErrorKind {
+        match *err {
+            rustls::Error::InvalidCertificate(_)
+            | rustls::Error::NoCertificatesPresented
+            | rustls::Error::InvalidCertRevocationList(_) => ErrorKind::Auth,
+            _ => ErrorKind::Protocol,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 impl From<serde_json::Error> for Error {
@@ -70,9 +235,16 @@ impl fmt::Display for Error {
             }
             Error::WrongBatchResponseId(ref v) => write!(f, "wrong RPC batch response ID: {}", v),
             Error::NonceMismatch => write!(f, "Nonce of response did not match nonce of request"),
-            Error::VersionMismatch => write!(f, "`jsonrpc` field set to non-\"2.0\""),
+            Error::VersionMismatch => {
+                write!(f, "`jsonrpc` field doesn't match the version of the request")
+            }
+            Error::IncompleteResponse => {
+                write!(f, "JSON-RPC 1.0 response lacks its `result` or `error` member")
+            }
             Error::EmptyBatch => write!(f, "batches can't be empty"),
             Error::WrongBatchResponseSize => write!(f, "too many responses returned in batch"),
+            Error::DeadlineExceeded => write!(f, "deadline of the call exceeded"),
+            Error::Cancelled => write!(f, "call cancelled"),
         }
     }
 }
@@ -85,10 +257,13 @@ impl error::Error for Error {
             Rpc(_)
             | NonceMismatch
             | VersionMismatch
+            | IncompleteResponse
             | EmptyBatch
             | WrongBatchResponseSize
             | BatchDuplicateResponseId(_)
-            | WrongBatchResponseId(_) => None,
+            | WrongBatchResponseId(_)
+            | DeadlineExceeded
+            | Cancelled => None,
             Transport(ref e) => Some(&**e),
             Json(ref e) => Some(e),
         }
@@ -191,12 +366,16 @@ pub fn result_to_response(
             error: None,
             id,
             jsonrpc: Some(String::from("2.0")),
+            has_result: true,
+            has_error: true,
         },
         Err(err) => Response {
             result: None,
             error: Some(err),
             id,
             jsonrpc: Some(String::from("2.0")),
+            has_result: true,
+            has_error: true,
         },
     }
 }
@@ -206,8 +385,9 @@ mod tests {
     use super::StandardError::{
         InternalError, InvalidParams, InvalidRequest, MethodNotFound, ParseError,
     };
-    use super::{result_to_response, standard_error};
+    use super::{result_to_response, standard_error, Error, ErrorKind, RpcError};
     use serde_json;
+    use std::{error, fmt, io};
 
     #[test]
     fn test_parse_error() {
@@ -253,4 +433,56 @@ mod tests {
         assert_eq!(resp.id, serde_json::Value::from(-1));
         assert_eq!(resp.error.unwrap().code, -32603);
     }
+
+    #[test]
+    fn kinds() {
+        let rpc = |code| {
+            Error::Rpc(RpcError {
+                code,
+                message: "nope".to_owned(),
+                data: None,
+            })
+        };
+        assert_eq!(rpc(-8).kind(), ErrorKind::Rpc);
+        assert!(!rpc(-8).is_retryable());
+        assert!(rpc(-28).is_retryable());
+        assert_eq!(Error::NonceMismatch.kind(), ErrorKind::Protocol);
+        assert_eq!(Error::DeadlineExceeded.kind(), ErrorKind::Timeout);
+        assert!(!Error::DeadlineExceeded.is_retryable());
+        assert!(!Error::Cancelled.is_retryable());
+
+        let io = |kind| Error::Transport(Box::new(io::Error::from(kind)));
+        assert_eq!(io(io::ErrorKind::TimedOut).kind(), ErrorKind::Timeout);
+        assert_eq!(io(io::ErrorKind::ConnectionRefused).kind(), ErrorKind::Connection);
+        assert!(io(io::ErrorKind::ConnectionRefused).is_retryable());
+        assert_eq!(io(io::ErrorKind::PermissionDenied).kind(), ErrorKind::Auth);
+        assert!(!io(io::ErrorKind::PermissionDenied).is_retryable());
+        assert_eq!(io(io::ErrorKind::InvalidData).kind(), ErrorKind::Protocol);
+        assert!(!io(io::ErrorKind::InvalidData).is_retryable());
+        assert_eq!(io(io::ErrorKind::InvalidInput).kind(), ErrorKind::Config);
+        assert!(!io(io::ErrorKind::InvalidInput).is_retryable());
+
+        // Errors of other transports are classified by what they wrap
+        #[derive(Debug)]
+        struct Wrapper(Option<io::Error>);
+
+        impl fmt::Display for Wrapper {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("wrapper")
+            }
+        }
+
+        impl error::Error for Wrapper {
+            fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+                self.0.as_ref().map(|e| e as _)
+            }
+        }
+
+        let wrapped = Error::Transport(Box::new(Wrapper(Some(io::ErrorKind::TimedOut.into()))));
+        assert_eq!(wrapped.kind(), ErrorKind::Timeout);
+        assert!(wrapped.is_retryable());
+        let unknown = Error::Transport(Box::new(Wrapper(None)));
+        assert_eq!(unknown.kind(), ErrorKind::Other);
+        assert!(!unknown.is_retryable());
+    }
 }
@@ -0,0 +1,646 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Failover transport
+//!
+//! A [Transport] spreading requests over several nodes, and failing over to the next one when a
+//! node can't be reached.
+//!
+//! Nodes are checked every once in a while, in the background, by asking them all for their
+//! block height. Nodes which don't answer, or are more than a few blocks behind the others, are
+//! left out until the next check. Like with [RetryTransport](crate::retry::RetryTransport), methods with side effects
+//! never fail over, since the failing node may have run them anyway.
+//!
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{error, fmt};
+
+use crate::client::Transport;
+use crate::context;
+use crate::retry::DEFAULT_NON_IDEMPOTENT;
+use crate::util::lock;
+use crate::{ErrorKind, Params, Request, Response};
+
+/// Error that can occur while using the failover transport.
+#[derive(Debug)]
+pub enum Error {
+    /// The transport was built without any node.
+    NoNodes,
+}
+
+impl Error {
+    /// Returns the kind of the error, see [crate::Error::kind].
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::NoNodes => ErrorKind::Config,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::NoNodes => f.write_str("no node to send the request to"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<Error> for crate::Error {
+    fn from(e: Error) -> crate::Error {
+        crate::Error::Transport(Box::new(e))
+    }
+}
+
+/// How requests are spread over the nodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Each request goes to the next node.
+    RoundRobin,
+    /// Requests go to the node that answered the fastest lately.
+    LowestLatency,
+}
+
+/// A node and what we know about it.
+struct Node {
+    transport: Box<dyn Transport>,
+    state: Mutex<NodeState>,
+}
+
+#[derive(Default)]
+struct NodeState {
+    /// The last request or check failed.
+    failed: bool,
+    /// The node was behind the others at the last check.
+    lagging: bool,
+    /// The average response time.
+    latency: Option<Duration>,
+}
+
+impl NodeState {
+    fn record_latency(&mut self, elapsed: Duration) {
+        self.latency = Some(match self.latency {
+            Some(latency) => (latency * 7 + elapsed) / 8,
+            None => elapsed,
+        });
+    }
+}
+
+struct Shared {
+    nodes: Vec<Node>,
+    strategy: Strategy,
+    height_method: String,
+    max_lag: u64,
+    check_interval: Duration,
+    last_check: Mutex<Option<Instant>>,
+    next: AtomicUsize,
+    non_idempotent: HashSet<String>,
+}
+
+/// Asks all nodes for their block height at once, and updates what is known about them.
+///
+/// The requests are sent from their own threads, so they don't run under the deadline or
+/// cancel token of the call that started the check.
+fn check(shared: &Arc<Shared>) {
+    let checks: Vec<_> = (0..shared.nodes.len())
+        .map(|i| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let req = Request {
+                    method: &shared.height_method,
+                    params: Params::ByPosition(&[]),
+                    id: Some(serde_json::Value::from(0)),
+                    jsonrpc: Some("2.0"),
+                };
+                let start = Instant::now();
+                let height = shared.nodes[i].transport.send_request(req);
+                height.and_then(|resp| resp.result::<u64>()).ok().map(|h| (h, start.elapsed()))
+            })
+        })
+        .collect();
+    // A check that panicked counts as failed.
+    let heights: Vec<_> = checks.into_iter().map(|c| c.join().ok().and_then(|h| h)).collect();
+
+    let best = heights.iter().filter_map(|h| h.map(|(h, _)| h)).max().unwrap_or(0);
+    for (node, height) in shared.nodes.iter().zip(heights) {
+        let mut state = lock(&node.state);
+        state.failed = height.is_none();
+        state.lagging = height.map_or(false, |(h, _)| h + shared.max_lag < best);
+        if let Some((_, elapsed)) = height {
+            state.record_latency(elapsed);
+        }
+    }
+}
+
+/// A [Transport] sending requests to one of several nodes.
+///
+/// Clones share the nodes and what is known about them.
+#[derive(Clone)]
+pub struct FailoverTransport {
+    shared: Arc<Shared>,
+    /// The node all requests go to, once picked. Only set in pinned mode.
+    pin: Option<Arc<Mutex<Option<usize>>>>,
+}
+
+impl FailoverTransport {
+    /// Returns a builder for a [FailoverTransport].
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    /// Returns a handle sending all its requests to the same node, for a series of calls that
+    /// must see the same chain state. The node is picked on the first request.
+    ///
+    /// If that node fails, the request isn't sent to another one and the error is returned:
+    /// the whole operation should be started over with a new pinned handle.
+    pub fn pinned(&self) -> FailoverTransport {
+        FailoverTransport {
+            shared: self.shared.clone(),
+            pin: Some(Arc::new(Mutex::new(None))),
+        }
+    }
+
+    /// Checks all nodes now, instead of waiting for the next periodic check.
+    pub fn check_nodes(&self) {
+        *lock(&self.shared.last_check) = Some(Instant::now());
+        check(&self.shared);
+    }
+
+    /// Checks the nodes if it's been long enough since the last check. Only the first check
+    /// is waited for, since nothing is known about the nodes before; later ones run in the
+    /// background.
+    fn maybe_check_nodes(&self) {
+        // Only one caller gets to start the check.
+        let first = {
+            let mut last_check = lock(&self.shared.last_check);
+            match *last_check {
+                Some(last) if last.elapsed() < self.shared.check_interval => return,
+                last => {
+                    *last_check = Some(Instant::now());
+                    last.is_none()
+                }
+            }
+        };
+        if first {
+            check(&self.shared);
+        } else {
+            let shared = self.shared.clone();
+            thread::spawn(move || check(&shared));
+        }
+    }
+
+    /// Returns the order in which to try the nodes for the next request.
+    fn order(&self) -> Vec<usize> {
+        let nodes = &self.shared.nodes;
+        let mut order: Vec<usize> = (0..nodes.len())
+            .filter(|&i| {
+                let state = lock(&nodes[i].state);
+                !state.failed && !state.lagging
+            })
+            .collect();
+        // A node that failed may be back already, better try than give up.
+        if order.is_empty() {
+            order = (0..nodes.len()).collect();
+        }
+        match self.shared.strategy {
+            Strategy::RoundRobin => {
+                let start = self.shared.next.fetch_add(1, Ordering::Relaxed) % order.len().max(1);
+                order.rotate_left(start);
+            }
+            // Nodes we don't know about yet come first, so that we get to know them.
+            Strategy::LowestLatency => order.sort_by_key(|&i| lock(&nodes[i].state).latency),
+        }
+        order
+    }
+
+    /// Sends with `send` to the first node that answers, or only to the first one if
+    /// `failover` isn't set.
+    fn send<R, F>(&self, failover: bool, send: F) -> Result<R, crate::Error>
+    where
+        F: Fn(&dyn Transport) -> Result<R, crate::Error>,
+    {
+        self.maybe_check_nodes();
+
+        let mut pin = self.pin.as_ref().map(|pin| lock(pin));
+        let order = match pin.as_ref().and_then(|pin| **pin) {
+            Some(pinned) => vec![pinned],
+            None => self.order(),
+        };
+
+        let mut last_err = None;
+        for i in order {
+            let node = &self.shared.nodes[i];
+            let start = Instant::now();
+            match send(&*node.transport) {
+                Ok(res) => {
+                    lock(&node.state).record_latency(start.elapsed());
+                    if let Some(ref mut pin) = pin {
+                        **pin = Some(i);
+                    }
+                    return Ok(res);
+                }
+                Err(crate::Error::Transport(e)) => {
+                    lock(&node.state).failed = true;
+                    let pinned = pin.as_ref().map_or(false, |pin| pin.is_some());
+                    if !failover || pinned || context::check().is_err() {
+                        return Err(crate::Error::Transport(e));
+                    }
+                    last_err = Some(crate::Error::Transport(e));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| Error::NoNodes.into()))
+    }
+
+    fn is_idempotent(&self, method: &str) -> bool {
+        !self.shared.non_idempotent.contains(method)
+    }
+}
+
+impl Transport for FailoverTransport {
+    fn send_request(&self, req: Request) -> Result<Response, crate::Error> {
+        let failover = self.is_idempotent(req.method);
+        self.send(failover, |tp| tp.send_request(req.clone()))
+    }
+
+    fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, crate::Error> {
+        let failover = reqs.iter().all(|r| self.is_idempotent(r.method));
+        self.send(failover, |tp| tp.send_batch(reqs))
+    }
+
+    fn send_notification(&self, notification: Request) -> Result<(), crate::Error> {
+        let failover = self.is_idempotent(notification.method);
+        self.send(failover, |tp| tp.send_notification(notification.clone()))
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failover(")?;
+        for (i, node) in self.shared.nodes.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            node.transport.fmt_target(f)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl fmt::Debug for FailoverTransport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "jsonrpc::failover::FailoverTransport(")?;
+        self.fmt_target(f)?;
+        write!(f, ")")
+    }
+}
+
+/// Builder for a [FailoverTransport].
+pub struct Builder {
+    nodes: Vec<Box<dyn Transport>>,
+    strategy: Strategy,
+    height_method: String,
+    max_lag: u64,
+    check_interval: Duration,
+    non_idempotent: HashSet<String>,
+}
+
+impl Builder {
+    /// Constructs a new [Builder] without any node, sending requests round-robin, checking
+    /// nodes every 30 seconds with `getblockcount` and leaving out nodes more than 2 blocks
+    /// behind.
+    pub fn new() -> Builder {
+        Builder {
+            nodes: Vec::new(),
+            strategy: Strategy::RoundRobin,
+            height_method: "getblockcount".to_owned(),
+            max_lag: 2,
+            check_interval: Duration::from_secs(30),
+            non_idempotent: DEFAULT_NON_IDEMPOTENT.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    /// Adds a node reached over the given transport.
+    pub fn node<T: Transport>(mut self, transport: T) -> Self {
+        self.nodes.push(Box::new(transport));
+        self
+    }
+
+    /// Adds a node reached over HTTP, see [simple_http::Builder::url].
+    ///
+    /// [simple_http::Builder::url]: crate::simple_http::Builder::url
+    #[cfg(feature = "simple_http")]
+    pub fn http_node(
+        self,
+        url: &str,
+        user: Option<String>,
+        pass: Option<String>,
+    ) -> Result<Self, crate::simple_http::Error> {
+        let mut builder = crate::simple_http::Builder::new().url(url)?;
+        if let Some(user) = user {
+            builder = builder.auth(user, pass);
+        }
+        Ok(self.node(builder.build()))
+    }
+
+    /// Sets how requests are spread over the nodes.
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sets the method returning the block height of a node, `getblockcount` by default.
+    pub fn height_method(mut self, method: &str) -> Self {
+        self.height_method = method.to_owned();
+        self
+    }
+
+    /// Sets how many blocks a node may be behind the others before being left out.
+    pub fn max_lag(mut self, blocks: u64) -> Self {
+        self.max_lag = blocks;
+        self
+    }
+
+    /// Sets how often the nodes are checked.
+    pub fn check_interval(mut self, interval: Duration) -> Self {
+        self.check_interval = interval;
+        self
+    }
+
+    /// Never sends `method` to another node after a failure.
+    pub fn non_idempotent(mut self, method: &str) -> Self {
+        self.non_idempotent.insert(method.to_owned());
+        self
+    }
+
+    /// Allows failing over for `method`, even if it's in [DEFAULT_NON_IDEMPOTENT].
+    pub fn idempotent(mut self, method: &str) -> Self {
+        self.non_idempotent.remove(method);
+        self
+    }
+
+    /// Builds the final [FailoverTransport].
+    pub fn build(self) -> FailoverTransport {
+        let nodes = self
+            .nodes
+            .into_iter()
+            .map(|transport| Node {
+                transport,
+                state: Mutex::new(NodeState::default()),
+            })
+            .collect();
+        FailoverTransport {
+            shared: Arc::new(Shared {
+                nodes,
+                strategy: self.strategy,
+                height_method: self.height_method,
+                max_lag: self.max_lag,
+                check_interval: self.check_interval,
+                last_check: Mutex::new(None),
+                next: AtomicUsize::new(0),
+                non_idempotent: self.non_idempotent,
+            }),
+            pin: None,
+        }
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::atomic::AtomicBool;
+
+    use serde_json::value::RawValue;
+
+    use super::*;
+    use crate::Client;
+
+    /// A node answering requests with its name and `getblockcount` with its height.
+    #[derive(Clone)]
+    struct FakeNode {
+        name: &'static str,
+        height: Arc<AtomicUsize>,
+        up: Arc<AtomicBool>,
+        delay: Duration,
+        calls: Arc<AtomicUsize>,
+        checks: Arc<AtomicUsize>,
+    }
+
+    impl FakeNode {
+        fn new(name: &'static str, height: usize) -> FakeNode {
+            FakeNode {
+                name,
+                height: Arc::new(AtomicUsize::new(height)),
+                up: Arc::new(AtomicBool::new(true)),
+                delay: Duration::from_millis(0),
+                calls: Arc::new(AtomicUsize::new(0)),
+                checks: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Transport for FakeNode {
+        fn send_request(&self, req: Request) -> Result<Response, crate::Error> {
+            thread::sleep(self.delay);
+            if !self.up.load(Ordering::SeqCst) {
+                let err = io::Error::from(io::ErrorKind::ConnectionRefused);
+                return Err(crate::Error::Transport(Box::new(err)));
+            }
+            let result = if req.method == "getblockcount" {
+                self.checks.fetch_add(1, Ordering::SeqCst);
+                self.height.load(Ordering::SeqCst).to_string()
+            } else {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                format!("\"{}\"", self.name)
+            };
+            Ok(Response {
+                result: Some(RawValue::from_string(result).unwrap()),
+                error: None,
+                id: req.id.unwrap_or_default(),
+                jsonrpc: Some("2.0".to_owned()),
+                has_result: true,
+                has_error: true,
+            })
+        }
+
+        fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, crate::Error> {
+            reqs.iter().map(|r| self.send_request(r.clone())).collect()
+        }
+
+        fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}", self.name)
+        }
+    }
+
+    fn call(client: &Client, method: &str) -> Result<String, crate::Error> {
+        client.call(method, &[])
+    }
+
+    #[test]
+    fn round_robin() {
+        let (a, b) = (FakeNode::new("a", 100), FakeNode::new("b", 100));
+        let tp = FailoverTransport::builder().node(a.clone()).node(b.clone()).build();
+        assert_eq!(format!("{:?}", tp), "jsonrpc::failover::FailoverTransport(failover(a, b))");
+        let client = Client::with_transport(tp);
+
+        let answers: Vec<_> = (0..4).map(|_| call(&client, "getinfo").unwrap()).collect();
+        assert_eq!(answers, ["a", "b", "a", "b"]);
+
+        // b goes down: a takes over, and b isn't tried again until the next check.
+        b.up.store(false, Ordering::SeqCst);
+        for _ in 0..3 {
+            assert_eq!(call(&client, "getinfo").unwrap(), "a");
+        }
+        assert_eq!(a.calls(), 5);
+
+        // Nothing else to fail over to.
+        a.up.store(false, Ordering::SeqCst);
+        assert!(call(&client, "getinfo").is_err());
+    }
+
+    #[test]
+    fn writes_dont_fail_over() {
+        let (a, b) = (FakeNode::new("a", 100), FakeNode::new("b", 100));
+        let tp = FailoverTransport::builder().node(a.clone()).node(b.clone()).build();
+        tp.check_nodes();
+        let client = Client::with_transport(tp);
+
+        // a goes down after the check, so it gets the transaction first.
+        a.up.store(false, Ordering::SeqCst);
+        assert!(call(&client, "sendrawtransaction").is_err());
+        assert_eq!(call(&client, "sendrawtransaction").unwrap(), "b");
+        assert_eq!(b.calls(), 1);
+    }
+
+    #[test]
+    fn drops_lagging_and_unhealthy_nodes() {
+        let (a, b, c) = (FakeNode::new("a", 100), FakeNode::new("b", 97), FakeNode::new("c", 99));
+        let tp = FailoverTransport::builder()
+            .node(a.clone())
+            .node(b.clone())
+            .node(c.clone())
+            .check_interval(Duration::from_secs(3600))
+            .build();
+        let client = Client::with_transport(tp.clone());
+
+        for _ in 0..4 {
+            assert_ne!(call(&client, "getinfo").unwrap(), "b");
+        }
+
+        // b catches up while a goes down.
+        b.height.store(100, Ordering::SeqCst);
+        a.up.store(false, Ordering::SeqCst);
+        tp.check_nodes();
+        for _ in 0..4 {
+            assert_ne!(call(&client, "getinfo").unwrap(), "a");
+        }
+        assert_eq!(a.calls(), 2);
+        assert_eq!(b.calls(), 2);
+    }
+
+    #[test]
+    fn checks_once_in_background() {
+        let (a, b) = (FakeNode::new("a", 100), FakeNode::new("b", 100));
+        let tp = FailoverTransport::builder()
+            .node(a.clone())
+            .node(b.clone())
+            .check_interval(Duration::from_millis(50))
+            .build();
+        let client = Arc::new(Client::with_transport(tp));
+        call(&client, "getinfo").unwrap();
+        assert_eq!(a.checks.load(Ordering::SeqCst), 1);
+
+        // b falls behind, the next check is started once by the callers that see it due.
+        b.height.store(90, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(60));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let client = client.clone();
+                thread::spawn(move || call(&client, "getinfo").unwrap())
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(a.checks.load(Ordering::SeqCst), 2);
+        assert_eq!(b.checks.load(Ordering::SeqCst), 2);
+        for _ in 0..4 {
+            assert_eq!(call(&client, "getinfo").unwrap(), "a");
+        }
+    }
+
+    #[test]
+    fn lowest_latency() {
+        let mut slow = FakeNode::new("slow", 100);
+        slow.delay = Duration::from_millis(20);
+        let fast = FakeNode::new("fast", 100);
+        let tp = FailoverTransport::builder()
+            .node(slow.clone())
+            .node(fast)
+            .strategy(Strategy::LowestLatency)
+            .build();
+        let client = Client::with_transport(tp);
+
+        for _ in 0..3 {
+            assert_eq!(call(&client, "getinfo").unwrap(), "fast");
+        }
+        assert_eq!(slow.calls(), 0);
+    }
+
+    #[test]
+    fn pinned() {
+        let (a, b) = (FakeNode::new("a", 100), FakeNode::new("b", 100));
+        let tp = FailoverTransport::builder().node(a.clone()).node(b.clone()).build();
+
+        let op = Client::with_transport(tp.pinned());
+        let first = call(&op, "getblockhash").unwrap();
+        for _ in 0..3 {
+            assert_eq!(call(&op, "getblock").unwrap(), first);
+        }
+
+        // Another operation may use another node, and the node failing is an error.
+        let op = Client::with_transport(tp.pinned());
+        let node = if call(&op, "getblockhash").unwrap() == "a" {
+            &a
+        } else {
+            &b
+        };
+        node.up.store(false, Ordering::SeqCst);
+        assert!(call(&op, "getblock").is_err());
+    }
+
+    #[test]
+    fn no_nodes() {
+        let client = Client::with_transport(FailoverTransport::builder().build());
+        match call(&client, "getinfo") {
+            Err(crate::Error::Transport(e)) => assert!(e.is::<Error>()),
+            res => panic!("expected a transport error, got {:?}", res),
+        }
+    }
+}
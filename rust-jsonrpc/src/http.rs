@@ -0,0 +1,692 @@
+//! A small HTTP/1.x parser for the HTTP based transports, and for the HTTP listener of the
+//! server.
+//!
+//! It understands the status line of both HTTP/1.0 and HTTP/1.1, and reads bodies delimited by
+//! `Content-Length`, `Transfer-Encoding: chunked`, or the end of the connection. Every read is
+//! bounded by a single deadline for the whole response. Requests are read the same way, except
+//! that their bodies can't be delimited by the end of the connection.
+
+use std::io::{self, BufRead, BufReader, Read};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+/// The maximum length of the status line or of a single header line.
+pub(crate) const MAX_LINE_LENGTH: usize = 8 * 1024;
+
+/// The maximum number of header fields we accept in a response.
+pub(crate) const MAX_HEADERS: usize = 100;
+
+/// Error that can happen while reading a response.
+#[derive(Debug)]
+pub(crate) enum ParseError {
+    /// An error occurred on the socket layer
+    Io(io::Error),
+    /// The deadline was reached before the response was complete
+    Timeout,
+    /// The response isn't valid HTTP/1.x
+    Malformed,
+    /// The body is larger than allowed
+    TooLarge,
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => ParseError::Timeout,
+            _ => ParseError::Io(e),
+        }
+    }
+}
+
+/// A stream whose read timeout can be changed, so reads can be bounded by a deadline.
+pub(crate) trait ReadTimeout: Read {
+    /// Sets the timeout of the next reads, see [TcpStream::set_read_timeout].
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl ReadTimeout for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// How the end of a response body is determined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BodyLength {
+    /// There is no body, e.g. for 204 responses
+    Empty,
+    /// The body has exactly this many bytes
+    Fixed(usize),
+    /// The body is sent as a series of chunks
+    Chunked,
+    /// The body ends when the server closes the connection
+    UntilClose,
+}
+
+/// The status line and headers of a response.
+#[derive(Debug)]
+pub(crate) struct ResponseHead {
+    /// Whether the server answered with HTTP/1.0
+    pub http10: bool,
+    /// The HTTP status code
+    pub status: u16,
+    /// The header fields, in the order they were received
+    pub headers: Vec<(String, String)>,
+}
+
+impl ResponseHead {
+    /// Returns the value of the first header with the given (case-insensitive) name.
+    #[cfg_attr(not(feature = "simple_ws"), allow(dead_code))]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header(&self.headers, name)
+    }
+
+    /// Returns how the body of this response is delimited.
+    pub fn body_length(&self) -> Result<BodyLength, ParseError> {
+        if self.status / 100 == 1 || self.status == 204 || self.status == 304 {
+            return Ok(BodyLength::Empty);
+        }
+        if let Some(encoding) = transfer_encoding(&self.headers) {
+            // If chunked isn't the final encoding, the body runs until the connection closes.
+            return if encoding.eq_ignore_ascii_case("chunked") {
+                Ok(BodyLength::Chunked)
+            } else {
+                Ok(BodyLength::UntilClose)
+            };
+        }
+        Ok(content_length(&self.headers)?.map_or(BodyLength::UntilClose, BodyLength::Fixed))
+    }
+
+    /// Whether the connection can be used for another request once the body has been read.
+    pub fn keep_alive(&self) -> bool {
+        if self.body_length().ok() == Some(BodyLength::UntilClose) {
+            return false;
+        }
+        keep_alive(self.http10, &self.headers)
+    }
+}
+
+/// The request line and headers of a request.
+#[derive(Debug)]
+pub(crate) struct RequestHead {
+    /// The request method, like `POST`
+    pub method: String,
+    /// Whether the client sent an HTTP/1.0 request
+    pub http10: bool,
+    /// The header fields, in the order they were received
+    pub headers: Vec<(String, String)>,
+}
+
+impl RequestHead {
+    /// Returns the value of the first header with the given (case-insensitive) name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header(&self.headers, name)
+    }
+
+    /// Returns how the body of this request is delimited. Unlike responses, requests without
+    /// a length have no body, and only the chunked encoding is understood.
+    pub fn body_length(&self) -> Result<BodyLength, ParseError> {
+        match transfer_encoding(&self.headers) {
+            Some(encoding) if encoding.eq_ignore_ascii_case("chunked") => Ok(BodyLength::Chunked),
+            Some(_) => Err(ParseError::Malformed),
+            None => Ok(content_length(&self.headers)?.map_or(BodyLength::Empty, BodyLength::Fixed)),
+        }
+    }
+
+    /// Whether the client wants to send another request once it got the response.
+    pub fn keep_alive(&self) -> bool {
+        keep_alive(self.http10, &self.headers)
+    }
+}
+
+/// Returns the value of the first header with the given (case-insensitive) name.
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+}
+
+/// Returns the last transfer coding applied to the body, if any.
+fn transfer_encoding(headers: &[(String, String)]) -> Option<&str> {
+    headers
+        .iter()
+        .filter(|(n, _)| n.eq_ignore_ascii_case("Transfer-Encoding"))
+        .flat_map(|(_, v)| v.split(','))
+        .map(str::trim)
+        .rfind(|e| !e.is_empty())
+}
+
+/// Returns the value of the `Content-Length` headers, which must all agree.
+fn content_length(headers: &[(String, String)]) -> Result<Option<usize>, ParseError> {
+    let mut length = None;
+    for (_, value) in headers.iter().filter(|(n, _)| n.eq_ignore_ascii_case("Content-Length")) {
+        let value = value.trim().parse::<usize>().map_err(|_| ParseError::Malformed)?;
+        if length.map_or(false, |l| l != value) {
+            return Err(ParseError::Malformed);
+        }
+        length = Some(value);
+    }
+    Ok(length)
+}
+
+/// Whether the connection stays open after a message, given its version and headers.
+fn keep_alive(http10: bool, headers: &[(String, String)]) -> bool {
+    let connection_has = |option: &str| {
+        headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case("Connection"))
+            .flat_map(|(_, v)| v.split(','))
+            .any(|o| o.trim().eq_ignore_ascii_case(option))
+    };
+    if http10 {
+        connection_has("keep-alive")
+    } else {
+        !connection_has("close")
+    }
+}
+
+/// Fills the buffer of `reader`, waiting at most until `deadline`.
+fn fill_buf<S: ReadTimeout>(
+    reader: &mut BufReader<S>,
+    deadline: Instant,
+) -> Result<&[u8], ParseError> {
+    if reader.buffer().is_empty() {
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(ParseError::Timeout);
+        }
+        reader.get_ref().set_read_timeout(Some(deadline - now))?;
+    }
+    loop {
+        match reader.fill_buf() {
+            Ok(_) => break,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(reader.buffer())
+}
+
+/// Reads a line terminated by CRLF (or a bare LF) and returns it without the terminator.
+fn read_line<S: ReadTimeout>(
+    reader: &mut BufReader<S>,
+    deadline: Instant,
+) -> Result<String, ParseError> {
+    let mut line = Vec::new();
+    loop {
+        let buf = fill_buf(reader, deadline)?;
+        if buf.is_empty() {
+            return Err(ParseError::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+        let (used, done) = match buf.iter().position(|&b| b == b'\n') {
+            Some(pos) => (pos + 1, true),
+            None => (buf.len(), false),
+        };
+        line.extend_from_slice(&buf[..used]);
+        reader.consume(used);
+        if line.len() > MAX_LINE_LENGTH {
+            return Err(ParseError::Malformed);
+        }
+        if done {
+            break;
+        }
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|_| ParseError::Malformed)
+}
+
+/// Reads exactly `buf.len()` bytes.
+fn read_exact<S: ReadTimeout>(
+    reader: &mut BufReader<S>,
+    mut buf: &mut [u8],
+    deadline: Instant,
+) -> Result<(), ParseError> {
+    while !buf.is_empty() {
+        let available = fill_buf(reader, deadline)?;
+        if available.is_empty() {
+            return Err(ParseError::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        reader.consume(n);
+        buf = &mut buf[n..];
+    }
+    Ok(())
+}
+
+/// Reads the status line and the headers of a response, skipping interim (1xx) responses.
+pub(crate) fn read_head<S: ReadTimeout>(
+    reader: &mut BufReader<S>,
+    deadline: Instant,
+) -> Result<ResponseHead, ParseError> {
+    loop {
+        let (http10, status) = parse_status_line(&read_line(reader, deadline)?)?;
+        let headers = read_headers(reader, deadline)?;
+        if is_final(status) {
+            return Ok(ResponseHead {
+                http10,
+                status,
+                headers,
+            });
+        }
+    }
+}
+
+/// Reads the request line and the headers of a request.
+pub(crate) fn read_request_head<S: ReadTimeout>(
+    reader: &mut BufReader<S>,
+    deadline: Instant,
+) -> Result<RequestHead, ParseError> {
+    let (method, http10) = parse_request_line(&read_line(reader, deadline)?)?;
+    let headers = read_headers(reader, deadline)?;
+    Ok(RequestHead {
+        method,
+        http10,
+        headers,
+    })
+}
+
+/// Reads header lines up to the empty line ending them.
+fn read_headers<S: ReadTimeout>(
+    reader: &mut BufReader<S>,
+    deadline: Instant,
+) -> Result<Vec<(String, String)>, ParseError> {
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader, deadline)?;
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(ParseError::Malformed);
+        }
+        headers.push(parse_header(&line)?);
+    }
+}
+
+/// Parses a request line, returning the method and whether it is HTTP/1.0. The target is
+/// ignored.
+pub(crate) fn parse_request_line(line: &str) -> Result<(String, bool), ParseError> {
+    let mut parts = line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version), None) => (method, target, version),
+        _ => return Err(ParseError::Malformed),
+    };
+    if method.is_empty() || target.is_empty() {
+        return Err(ParseError::Malformed);
+    }
+    let http10 = match version {
+        "HTTP/1.0" => true,
+        "HTTP/1.1" => false,
+        _ => return Err(ParseError::Malformed),
+    };
+    Ok((method.to_owned(), http10))
+}
+
+/// Parses a status line, returning whether it is HTTP/1.0 and the status code.
+pub(crate) fn parse_status_line(line: &str) -> Result<(bool, u16), ParseError> {
+    let http10 = if line.starts_with("HTTP/1.0 ") {
+        true
+    } else if line.starts_with("HTTP/1.1 ") {
+        false
+    } else {
+        return Err(ParseError::Malformed);
+    };
+    let code = line.get(9..12).ok_or(ParseError::Malformed)?;
+    if !code.bytes().all(|b| b.is_ascii_digit()) || line.len() > 12 && !line[12..].starts_with(' ')
+    {
+        return Err(ParseError::Malformed);
+    }
+    let status = code.parse::<u16>().map_err(|_| ParseError::Malformed)?;
+    Ok((http10, status))
+}
+
+/// Parses a header line into its name and its trimmed value.
+pub(crate) fn parse_header(line: &str) -> Result<(String, String), ParseError> {
+    let mut split = line.splitn(2, ':');
+    let name = split.next().unwrap_or_default();
+    let value = split.next().ok_or(ParseError::Malformed)?;
+    if name.is_empty() || name.ends_with(char::is_whitespace) {
+        return Err(ParseError::Malformed);
+    }
+    Ok((name.to_owned(), value.trim().to_owned()))
+}
+
+/// Whether a response with this status is the final one. 101 Switching Protocols is final,
+/// other 1xx responses are followed by the real one.
+pub(crate) fn is_final(status: u16) -> bool {
+    status / 100 != 1 || status == 101
+}
+
+/// Parses the line starting a chunk and returns the chunk size.
+pub(crate) fn parse_chunk_size(line: &str) -> Result<usize, ParseError> {
+    // Ignore chunk extensions
+    let size = line.split(';').next().unwrap_or_default().trim();
+    usize::from_str_radix(size, 16).map_err(|_| ParseError::Malformed)
+}
+
+/// Where a [BodyReader] is in the body.
+enum BodyState {
+    /// This many bytes of a body with a `Content-Length` are left
+    Fixed(usize),
+    /// This many bytes of the current chunk are left, then comes its CRLF
+    Chunk(usize),
+    /// The size of the next chunk comes next
+    ChunkStart,
+    /// Everything up to the end of the connection is body
+    UntilClose,
+    /// The whole body was read
+    Done,
+}
+
+/// Reads the body of a response, whose head has already been read, as it arrives. This lets
+/// responses be deserialized straight from the connection instead of from a copy of the body.
+///
+/// Reads fail once the body gets larger than `max_size`, or when the deadline is reached. The
+/// error is then kept, see [BodyReader::take_error].
+pub(crate) struct BodyReader<'a, S> {
+    reader: &'a mut BufReader<S>,
+    state: BodyState,
+    remaining: usize,
+    deadline: Instant,
+    error: Option<ParseError>,
+}
+
+impl<'a, S: ReadTimeout> BodyReader<'a, S> {
+    pub(crate) fn new(
+        reader: &'a mut BufReader<S>,
+        head: &ResponseHead,
+        max_size: usize,
+        deadline: Instant,
+    ) -> Result<BodyReader<'a, S>, ParseError> {
+        let state = match head.body_length()? {
+            BodyLength::Empty => BodyState::Done,
+            BodyLength::Fixed(len) if len > max_size => return Err(ParseError::TooLarge),
+            BodyLength::Fixed(len) => BodyState::Fixed(len),
+            BodyLength::Chunked => BodyState::ChunkStart,
+            BodyLength::UntilClose => BodyState::UntilClose,
+        };
+        Ok(BodyReader {
+            reader,
+            state,
+            remaining: max_size,
+            deadline,
+            error: None,
+        })
+    }
+
+    /// Returns the error that made a read fail, if any. Deserializers wrap I/O errors in
+    /// their own, this gets the original back.
+    pub(crate) fn take_error(&mut self) -> Option<ParseError> {
+        self.error.take()
+    }
+
+    fn read_body(&mut self, buf: &mut [u8]) -> Result<usize, ParseError> {
+        loop {
+            let left = match self.state {
+                BodyState::Done => return Ok(0),
+                BodyState::Fixed(0) => {
+                    self.state = BodyState::Done;
+                    return Ok(0);
+                }
+                BodyState::Chunk(0) => {
+                    if !read_line(self.reader, self.deadline)?.is_empty() {
+                        return Err(ParseError::Malformed);
+                    }
+                    self.state = BodyState::ChunkStart;
+                    continue;
+                }
+                BodyState::ChunkStart => {
+                    let size = parse_chunk_size(&read_line(self.reader, self.deadline)?)?;
+                    if size == 0 {
+                        // Skip the trailer fields
+                        while !read_line(self.reader, self.deadline)?.is_empty() {}
+                        self.state = BodyState::Done;
+                    } else if size > self.remaining {
+                        return Err(ParseError::TooLarge);
+                    } else {
+                        self.state = BodyState::Chunk(size);
+                    }
+                    continue;
+                }
+                BodyState::Fixed(left) | BodyState::Chunk(left) => left,
+                BodyState::UntilClose => std::usize::MAX,
+            };
+
+            let available = match fill_buf(self.reader, self.deadline) {
+                Ok(available) => available,
+                // TLS peers may close the connection without a close_notify
+                Err(ParseError::Io(ref e))
+                    if e.kind() == io::ErrorKind::UnexpectedEof && left == std::usize::MAX =>
+                {
+                    &[]
+                }
+                Err(e) => return Err(e),
+            };
+            if available.is_empty() {
+                if let BodyState::UntilClose = self.state {
+                    self.state = BodyState::Done;
+                    return Ok(0);
+                }
+                return Err(ParseError::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+            let n = available.len().min(buf.len()).min(left);
+            if n > self.remaining {
+                return Err(ParseError::TooLarge);
+            }
+            buf[..n].copy_from_slice(&available[..n]);
+            self.reader.consume(n);
+            self.remaining -= n;
+            match self.state {
+                BodyState::Fixed(ref mut left) | BodyState::Chunk(ref mut left) => *left -= n,
+                _ => {}
+            }
+            return Ok(n);
+        }
+    }
+}
+
+impl<'a, S: ReadTimeout> Read for BodyReader<'a, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.read_body(buf).map_err(|e| {
+            let err = io::Error::new(io::ErrorKind::Other, format!("{:?}", e));
+            self.error = Some(e);
+            err
+        })
+    }
+}
+
+/// Reads the body of a request whose head has already been read. Bodies longer than
+/// `max_size` are refused.
+pub(crate) fn read_request_body<S: ReadTimeout>(
+    reader: &mut BufReader<S>,
+    head: &RequestHead,
+    max_size: usize,
+    deadline: Instant,
+) -> Result<Vec<u8>, ParseError> {
+    match head.body_length()? {
+        BodyLength::Fixed(len) if len > max_size => Err(ParseError::TooLarge),
+        BodyLength::Fixed(len) => {
+            let mut body = vec![0; len];
+            read_exact(reader, &mut body, deadline)?;
+            Ok(body)
+        }
+        BodyLength::Chunked => read_chunked(reader, max_size, deadline),
+        BodyLength::Empty | BodyLength::UntilClose => Ok(Vec::new()),
+    }
+}
+
+/// Reads a chunked body, refusing it if it gets longer than `max_size`.
+fn read_chunked<S: ReadTimeout>(
+    reader: &mut BufReader<S>,
+    max_size: usize,
+    deadline: Instant,
+) -> Result<Vec<u8>, ParseError> {
+    let mut body = Vec::new();
+    loop {
+        let size = parse_chunk_size(&read_line(reader, deadline)?)?;
+        if size == 0 {
+            break;
+        }
+        let start = body.len();
+        let end = start.checked_add(size).ok_or(ParseError::Malformed)?;
+        if end > max_size {
+            return Err(ParseError::TooLarge);
+        }
+        body.resize(end, 0);
+        read_exact(reader, &mut body[start..], deadline)?;
+        if !read_line(reader, deadline)?.is_empty() {
+            return Err(ParseError::Malformed);
+        }
+    }
+    // Skip the trailer fields
+    while !read_line(reader, deadline)?.is_empty() {}
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+
+    /// Serves `response` on a local socket and returns a reader connected to it.
+    fn serve(response: &'static [u8]) -> BufReader<TcpStream> {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = server.accept().unwrap();
+            stream.write_all(response).unwrap();
+            // Keep the connection open for a little while, like a keep-alive server would.
+            thread::sleep(Duration::from_millis(300));
+        });
+        BufReader::new(TcpStream::connect(addr).unwrap())
+    }
+
+    fn deadline() -> Instant {
+        Instant::now() + Duration::from_secs(5)
+    }
+
+    fn read_body<S: ReadTimeout>(
+        reader: &mut BufReader<S>,
+        head: &ResponseHead,
+        max_size: usize,
+        deadline: Instant,
+    ) -> Result<Vec<u8>, ParseError> {
+        let mut body = BodyReader::new(reader, head, max_size, deadline)?;
+        let mut buf = Vec::new();
+        match body.read_to_end(&mut buf) {
+            Ok(_) => Ok(buf),
+            Err(_) => Err(body.take_error().unwrap()),
+        }
+    }
+
+    #[test]
+    fn content_length() {
+        let mut reader =
+            serve(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\nX-Other: a:b\r\n\r\nhello trailing");
+        let head = read_head(&mut reader, deadline()).unwrap();
+        assert_eq!(head.status, 200);
+        assert!(!head.http10);
+        assert_eq!(head.header("X-OTHER"), Some("a:b"));
+        assert_eq!(head.body_length().unwrap(), BodyLength::Fixed(5));
+        assert!(head.keep_alive());
+        assert_eq!(read_body(&mut reader, &head, 100, deadline()).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn chunked() {
+        let mut reader = serve(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nTrailer: x\r\n\r\n",
+        );
+        let head = read_head(&mut reader, deadline()).unwrap();
+        assert_eq!(head.body_length().unwrap(), BodyLength::Chunked);
+        assert_eq!(read_body(&mut reader, &head, 100, deadline()).unwrap(), b"hello, world");
+    }
+
+    #[test]
+    fn http10_until_close() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = server.accept().unwrap();
+            stream.write_all(b"HTTP/1.0 500 Internal Server Error\r\n\r\n{\"a\": 1}").unwrap();
+        });
+        let mut reader = BufReader::new(TcpStream::connect(addr).unwrap());
+        let head = read_head(&mut reader, deadline()).unwrap();
+        assert!(head.http10);
+        assert_eq!(head.status, 500);
+        assert!(!head.keep_alive());
+        assert_eq!(read_body(&mut reader, &head, 100, deadline()).unwrap(), b"{\"a\": 1}");
+    }
+
+    #[test]
+    fn interim_response() {
+        let mut reader =
+            serve(b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.0 200 OK\r\nConnection: keep-alive\r\nContent-Length: 0\r\n\r\n");
+        let head = read_head(&mut reader, deadline()).unwrap();
+        assert_eq!(head.status, 200);
+        assert!(head.keep_alive());
+        assert!(read_body(&mut reader, &head, 100, deadline()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed() {
+        let responses: [&'static [u8]; 4] = [
+            b"HTTP/2 200 OK\r\n\r\n",
+            b"HTTP/1.1 2x0 OK\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nno colon\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+        ];
+        for response in responses.iter() {
+            let mut reader = serve(response);
+            let res = read_head(&mut reader, deadline()).and_then(|h| h.body_length());
+            assert!(matches!(res, Err(ParseError::Malformed)), "{:?}", response);
+        }
+    }
+
+    #[test]
+    fn deadline_covers_body() {
+        // The body never arrives, the deadline must still be honoured.
+        let mut reader = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort");
+        let head = read_head(&mut reader, deadline()).unwrap();
+        let start = Instant::now();
+        let res = read_body(&mut reader, &head, 100, Instant::now() + Duration::from_millis(100));
+        assert!(matches!(res, Err(ParseError::Timeout)));
+        assert!(start.elapsed() < Duration::from_millis(300));
+    }
+
+    #[test]
+    fn too_large() {
+        let mut reader = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 12\r\n\r\nhello, world");
+        let head = read_head(&mut reader, deadline()).unwrap();
+        let res = read_body(&mut reader, &head, 11, deadline());
+        assert!(matches!(res, Err(ParseError::TooLarge)));
+
+        let mut reader = serve(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5\r\nhello\r\n7\r\n, world\r\n0\r\n\r\n",
+        );
+        let head = read_head(&mut reader, deadline()).unwrap();
+        let res = read_body(&mut reader, &head, 11, deadline());
+        assert!(matches!(res, Err(ParseError::TooLarge)));
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = server.accept().unwrap();
+            stream.write_all(b"HTTP/1.0 200 OK\r\n\r\nhello, world").unwrap();
+        });
+        let mut reader = BufReader::new(TcpStream::connect(addr).unwrap());
+        let head = read_head(&mut reader, deadline()).unwrap();
+        let res = read_body(&mut reader, &head, 11, deadline());
+        assert!(matches!(res, Err(ParseError::TooLarge)));
+    }
+}
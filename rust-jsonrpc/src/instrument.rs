@@ -0,0 +1,394 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Instrumentation
+//!
+//! An [Interceptor] recording a `tracing` span for every request and batch, and metrics
+//! through the `metrics` facade. Nothing is recorded until the application installs a
+//! subscriber and a recorder, like `tracing-subscriber` and `metrics-exporter-prometheus`.
+//!
+//! Requests get a `jsonrpc_request` span with the `method` and `id` fields, notifications a
+//! `jsonrpc_notification` span with the `method` field and batches a `jsonrpc_batch` span
+//! with a `size` field. The parameters are only recorded, in a `params`
+//! field, if [Instrument::record_params] is set: they can hold addresses, transactions or
+//! passphrases.
+//!
+//! The metrics are labelled with the `method`, which is `batch` for batches:
+//!
+//! - `jsonrpc_request_duration_seconds`, a histogram of the time until the response arrived
+//! - `jsonrpc_sent_bytes_total` and `jsonrpc_received_bytes_total`, counters of the size of
+//!   the serialized requests and responses
+//! - `jsonrpc_rpc_errors_total`, a counter of the error responses, also labelled with their
+//!   `code`
+//! - `jsonrpc_transport_errors_total`, a counter of the requests that got no response, also
+//!   labelled with the [kind](crate::ErrorKind::as_str) of error, like `connection` or
+//!   `timeout`
+//! - `jsonrpc_batch_size`, a histogram of the number of requests in each batch
+//!
+
+use std::io;
+use std::time::Instant;
+
+use metrics::{counter, histogram};
+use serde::Serialize;
+use tracing::field;
+
+use crate::client::Transport;
+use crate::interceptor::Interceptor;
+use crate::{Error, Request, Response};
+
+/// An [Interceptor] recording spans and metrics, see the [module documentation](self).
+#[derive(Clone, Debug, Default)]
+pub struct Instrument {
+    record_params: bool,
+}
+
+impl Instrument {
+    /// Records spans and metrics, without the parameters.
+    pub fn new() -> Instrument {
+        Instrument::default()
+    }
+
+    /// Sets whether the parameters of the requests are recorded in their spans.
+    pub fn record_params(mut self, record: bool) -> Self {
+        self.record_params = record;
+        self
+    }
+
+    /// Records what happened to a request, or a batch, that took since `start`.
+    fn record<R>(&self, method: &str, start: Instant, result: &Result<R, Error>) {
+        histogram!("jsonrpc_request_duration_seconds", "method" => method.to_owned())
+            .record(start.elapsed().as_secs_f64());
+        match *result {
+            Ok(_) => tracing::debug!(elapsed = ?start.elapsed(), "response received"),
+            Err(ref e) => {
+                let kind = e.kind().as_str();
+                counter!(
+                    "jsonrpc_transport_errors_total",
+                    "method" => method.to_owned(),
+                    "kind" => kind
+                )
+                .increment(1);
+                tracing::debug!(kind, error = %e, "request failed");
+            }
+        }
+    }
+}
+
+impl Interceptor for Instrument {
+    fn send_request(&self, req: Request, next: &dyn Transport) -> Result<Response, Error> {
+        let span = tracing::debug_span!(
+            "jsonrpc_request",
+            method = req.method,
+            id = %req.id.as_ref().unwrap_or(&serde_json::Value::Null),
+            params = field::Empty,
+        );
+        if self.record_params {
+            span.record("params", field::display(serialized(&req.params)));
+        }
+        let _enter = span.enter();
+
+        let method = req.method;
+        add_bytes("jsonrpc_sent_bytes_total", method, &req);
+        let start = Instant::now();
+        let result = next.send_request(req);
+        self.record(method, start, &result);
+        if let Ok(ref resp) = result {
+            add_bytes("jsonrpc_received_bytes_total", method, resp);
+            count_rpc_error(method, resp);
+        }
+        result
+    }
+
+    fn send_batch(&self, reqs: &[Request], next: &dyn Transport) -> Result<Vec<Response>, Error> {
+        let span = tracing::debug_span!("jsonrpc_batch", size = reqs.len(), params = field::Empty);
+        if self.record_params {
+            let params: Vec<_> = reqs.iter().map(|r| (r.method, r.params)).collect();
+            span.record("params", field::display(serialized(&params)));
+        }
+        let _enter = span.enter();
+
+        histogram!("jsonrpc_batch_size").record(reqs.len() as f64);
+        add_bytes("jsonrpc_sent_bytes_total", "batch", &reqs);
+        let start = Instant::now();
+        let result = next.send_batch(reqs);
+        self.record("batch", start, &result);
+        if let Ok(ref resps) = result {
+            add_bytes("jsonrpc_received_bytes_total", "batch", resps);
+            for resp in resps {
+                // Errors are counted for the method of the request they answer
+                let method = reqs
+                    .iter()
+                    .find(|r| r.id.as_ref() == Some(&resp.id))
+                    .map_or("batch", |r| r.method);
+                count_rpc_error(method, resp);
+            }
+        }
+        result
+    }
+
+    fn send_notification(&self, notification: Request, next: &dyn Transport) -> Result<(), Error> {
+        let span = tracing::debug_span!(
+            "jsonrpc_notification",
+            method = notification.method,
+            params = field::Empty,
+        );
+        if self.record_params {
+            span.record("params", field::display(serialized(&notification.params)));
+        }
+        let _enter = span.enter();
+
+        let method = notification.method;
+        add_bytes("jsonrpc_sent_bytes_total", method, &notification);
+        let start = Instant::now();
+        let result = next.send_notification(notification);
+        self.record(method, start, &result);
+        result
+    }
+}
+
+/// Adds the size of `value`, once serialized, to the counter `name`.
+fn add_bytes<T: Serialize + ?Sized>(name: &'static str, method: &str, value: &T) {
+    let mut size = ByteCount(0);
+    if serde_json::to_writer(&mut size, value).is_ok() {
+        counter!(name, "method" => method.to_owned()).increment(size.0);
+    }
+}
+
+fn count_rpc_error(method: &str, resp: &Response) {
+    if let Some(ref e) = resp.error {
+        counter!(
+            "jsonrpc_rpc_errors_total",
+            "method" => method.to_owned(),
+            "code" => e.code.to_string()
+        )
+        .increment(1);
+        tracing::debug!(code = e.code, message = %e.message, "error response");
+    }
+}
+
+fn serialized<T: Serialize + ?Sized>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+/// Counts the bytes written to it.
+struct ByteCount(u64);
+
+impl io::Write for ByteCount {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+
+    use metrics::{
+        Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+        SharedString, Unit,
+    };
+
+    use super::*;
+    use crate::util::lock;
+    use crate::Client;
+
+    /// Answers `getinfo` and fails `getblock` with an RPC error, or with a timeout when
+    /// asked to time out.
+    struct Node;
+
+    impl Transport for Node {
+        fn send_request(&self, req: Request) -> Result<Response, Error> {
+            if req.method == "timeout" {
+                return Err(Error::Transport(Box::new(io::Error::from(io::ErrorKind::TimedOut))));
+            }
+            let (result, error) = match req.method {
+                "getblock" => (
+                    None,
+                    Some(crate::error::standard_error(
+                        crate::error::StandardError::InvalidParams,
+                        None,
+                    )),
+                ),
+                _ => (Some(crate::arg(req.method)), None),
+            };
+            Ok(Response {
+                result,
+                error,
+                id: req.id.unwrap_or_default(),
+                jsonrpc: Some("2.0".to_owned()),
+                has_result: true,
+                has_error: true,
+            })
+        }
+
+        fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, Error> {
+            reqs.iter().map(|r| self.send_request(r.clone())).collect()
+        }
+
+        fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("node")
+        }
+    }
+
+    /// Keeps the counters and the number of values recorded in histograms.
+    #[derive(Default)]
+    struct Collect(Arc<Mutex<Vec<(String, u64)>>>);
+
+    struct Metric(String, Arc<Mutex<Vec<(String, u64)>>>);
+
+    impl Metric {
+        fn add(&self, value: u64) {
+            let mut metrics = lock(&self.1);
+            match metrics.iter_mut().find(|(k, _)| *k == self.0) {
+                Some(m) => m.1 += value,
+                None => metrics.push((self.0.clone(), value)),
+            }
+        }
+    }
+
+    impl CounterFn for Metric {
+        fn increment(&self, value: u64) {
+            self.add(value)
+        }
+
+        fn absolute(&self, _: u64) {}
+    }
+
+    impl HistogramFn for Metric {
+        fn record(&self, _: f64) {
+            self.add(1)
+        }
+    }
+
+    impl Collect {
+        fn metric(&self, key: &Key) -> Arc<Metric> {
+            let labels: Vec<_> =
+                key.labels().map(|l| format!("{}={}", l.key(), l.value())).collect();
+            Arc::new(Metric(format!("{}{{{}}}", key.name(), labels.join(",")), self.0.clone()))
+        }
+
+        fn get(&self, name: &str) -> Option<u64> {
+            lock(&self.0).iter().find(|(k, _)| k == name).map(|m| m.1)
+        }
+    }
+
+    impl Recorder for Collect {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.metric(key))
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.metric(key))
+        }
+    }
+
+    #[test]
+    fn metrics() {
+        let recorder = Collect::default();
+        let client = Client::with_transport(Node).with_interceptor(Instrument::new());
+        metrics::with_local_recorder(&recorder, || {
+            assert_eq!(client.call::<String>("getinfo", &[]).unwrap(), "getinfo");
+            assert_eq!(client.call::<String>("getinfo", &[]).unwrap(), "getinfo");
+            assert!(client.call::<String>("getblock", &[]).is_err());
+            assert!(client.call::<String>("timeout", &[]).is_err());
+            let reqs =
+                [client.build_request("getinfo", &[]), client.build_request("getblock", &[])];
+            assert_eq!(client.send_batch(&reqs).unwrap().len(), 2);
+        });
+
+        let get = |name: &str| recorder.get(name);
+        assert_eq!(get("jsonrpc_request_duration_seconds{method=getinfo}"), Some(2));
+        assert_eq!(get("jsonrpc_request_duration_seconds{method=batch}"), Some(1));
+        assert_eq!(get("jsonrpc_rpc_errors_total{method=getblock,code=-32602}"), Some(2));
+        assert_eq!(get("jsonrpc_transport_errors_total{method=timeout,kind=timeout}"), Some(1));
+        assert_eq!(get("jsonrpc_batch_size{}"), Some(1));
+        // {"method":"getinfo","params":[],"id":1,"jsonrpc":"2.0"}
+        assert_eq!(get("jsonrpc_sent_bytes_total{method=getinfo}"), Some(2 * 55));
+        // {"result":"getinfo","error":null,"id":1,"jsonrpc":"2.0"}
+        assert_eq!(get("jsonrpc_received_bytes_total{method=getinfo}"), Some(2 * 56));
+        assert!(get("jsonrpc_received_bytes_total{method=timeout}").is_none());
+    }
+
+    /// Keeps the fields of the spans, as `name.field=value`.
+    #[derive(Clone, Default)]
+    struct Spans(Arc<Mutex<Vec<String>>>);
+
+    struct Visit<'a>(&'a Spans, &'static str);
+
+    impl<'a> field::Visit for Visit<'a> {
+        fn record_debug(&mut self, field: &field::Field, value: &dyn fmt::Debug) {
+            lock(&(self.0).0).push(format!("{}.{}={:?}", self.1, field.name(), value));
+        }
+    }
+
+    impl tracing::Subscriber for Spans {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            span.record(&mut Visit(self, span.metadata().name()));
+            tracing::span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+            values.record(&mut Visit(self, "record"));
+        }
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+        fn event(&self, _: &tracing::Event<'_>) {}
+        fn enter(&self, _: &tracing::span::Id) {}
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[test]
+    fn params_are_redacted() {
+        let params = [crate::arg("hunter2")];
+        let send = |instrument: Instrument| {
+            let spans = Spans::default();
+            let client = Client::with_transport(Node).with_interceptor(instrument);
+            tracing::subscriber::with_default(spans.clone(), || {
+                client.call::<String>("walletpassphrase", &params).unwrap();
+                let reqs = [client.build_request("walletpassphrase", &params)];
+                client.send_batch(&reqs).unwrap();
+            });
+            let spans = lock(&spans.0);
+            spans.clone()
+        };
+
+        let spans = send(Instrument::new());
+        assert!(spans.contains(&"jsonrpc_request.method=\"walletpassphrase\"".to_owned()));
+        assert!(spans.contains(&"jsonrpc_batch.size=1".to_owned()));
+        assert!(!spans.iter().any(|s| s.contains("hunter2")), "{:?}", spans);
+
+        let spans = send(Instrument::new().record_params(true));
+        assert!(spans.contains(&"record.params=[\"hunter2\"]".to_owned()), "{:?}", spans);
+        assert!(spans.contains(&"record.params=[[\"walletpassphrase\",[\"hunter2\"]]]".to_owned()));
+    }
+}
@@ -0,0 +1,242 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Interceptors
+//!
+//! Hooks around the requests a [Client](crate::Client) sends, to log or time them, or to
+//! rewrite them. An [Interceptor] gets each request, or batch of requests, together with the
+//! transport it would be sent on. It can look at or replace the request, send it on or answer
+//! it itself, and then look at or replace the response or the error.
+//!
+//! [Client::with_interceptor](crate::Client::with_interceptor) adds an interceptor around a
+//! client's transport. Interceptors added later run first, they see the requests before the
+//! ones added earlier, and the responses after them. Since an [Intercepted] transport is a
+//! [Transport] itself, interceptors also stack with transport wrappers like
+//! [RetryTransport](crate::retry::RetryTransport).
+//!
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::client::Transport;
+use crate::{Error, Request, Response};
+
+/// Something that runs around the requests sent over a transport. All methods send the
+/// request on `next` as is by default.
+pub trait Interceptor: Send + Sync + 'static {
+    /// Handles a single request, usually by sending it, or a request made from it, on `next`.
+    fn send_request(&self, req: Request, next: &dyn Transport) -> Result<Response, Error> {
+        next.send_request(req)
+    }
+
+    /// Handles a batch of requests, usually by sending it, or a batch made from it, on `next`.
+    fn send_batch(&self, reqs: &[Request], next: &dyn Transport) -> Result<Vec<Response>, Error> {
+        next.send_batch(reqs)
+    }
+
+    /// Handles a notification, usually by sending it, or a notification made from it, on
+    /// `next`.
+    fn send_notification(&self, notification: Request, next: &dyn Transport) -> Result<(), Error> {
+        next.send_notification(notification)
+    }
+}
+
+impl<I: Interceptor + ?Sized> Interceptor for Arc<I> {
+    fn send_request(&self, req: Request, next: &dyn Transport) -> Result<Response, Error> {
+        (**self).send_request(req, next)
+    }
+
+    fn send_batch(&self, reqs: &[Request], next: &dyn Transport) -> Result<Vec<Response>, Error> {
+        (**self).send_batch(reqs, next)
+    }
+
+    fn send_notification(&self, notification: Request, next: &dyn Transport) -> Result<(), Error> {
+        (**self).send_notification(notification, next)
+    }
+}
+
+impl<I: Interceptor + ?Sized> Interceptor for Box<I> {
+    fn send_request(&self, req: Request, next: &dyn Transport) -> Result<Response, Error> {
+        (**self).send_request(req, next)
+    }
+
+    fn send_batch(&self, reqs: &[Request], next: &dyn Transport) -> Result<Vec<Response>, Error> {
+        (**self).send_batch(reqs, next)
+    }
+
+    fn send_notification(&self, notification: Request, next: &dyn Transport) -> Result<(), Error> {
+        (**self).send_notification(notification, next)
+    }
+}
+
+/// A [Transport] passing every request through an [Interceptor] before it reaches another
+/// transport.
+pub struct Intercepted<I, T> {
+    interceptor: I,
+    inner: T,
+}
+
+impl<I: Interceptor, T: Transport> Intercepted<I, T> {
+    /// Runs `interceptor` around the requests sent on `inner`.
+    pub fn new(interceptor: I, inner: T) -> Self {
+        Intercepted {
+            interceptor,
+            inner,
+        }
+    }
+
+    /// Returns the interceptor.
+    pub fn interceptor(&self) -> &I {
+        &self.interceptor
+    }
+
+    /// Returns the wrapped transport.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<I: Interceptor, T: Transport> Transport for Intercepted<I, T> {
+    fn send_request(&self, req: Request) -> Result<Response, Error> {
+        self.interceptor.send_request(req, &self.inner)
+    }
+
+    fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, Error> {
+        self.interceptor.send_batch(reqs, &self.inner)
+    }
+
+    fn send_notification(&self, notification: Request) -> Result<(), Error> {
+        self.interceptor.send_notification(notification, &self.inner)
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt_target(f)
+    }
+}
+
+impl<I, T: Transport> fmt::Debug for Intercepted<I, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Intercepted(")?;
+        self.inner.fmt_target(f)?;
+        write!(f, ")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::util::lock;
+    use crate::Client;
+
+    /// Answers every request with its method name.
+    struct Echo;
+
+    impl Transport for Echo {
+        fn send_request(&self, req: Request) -> Result<Response, Error> {
+            if req.method == "fail" {
+                return Err(Error::NonceMismatch);
+            }
+            Ok(Response {
+                result: Some(crate::arg(req.method)),
+                error: None,
+                id: req.id.unwrap_or_default(),
+                jsonrpc: Some("2.0".to_owned()),
+                has_result: true,
+                has_error: true,
+            })
+        }
+
+        fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, Error> {
+            reqs.iter().map(|r| self.send_request(r.clone())).collect()
+        }
+
+        fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("echo")
+        }
+    }
+
+    /// Writes down what goes through it.
+    struct Log(&'static str, Arc<Mutex<Vec<String>>>);
+
+    impl Interceptor for Log {
+        fn send_request(&self, req: Request, next: &dyn Transport) -> Result<Response, Error> {
+            lock(&self.1).push(format!("{} > {}", self.0, req.method));
+            let res = next.send_request(req);
+            let outcome = match res {
+                Ok(ref resp) => resp.result.as_ref().unwrap().get().to_owned(),
+                Err(ref e) => e.to_string(),
+            };
+            lock(&self.1).push(format!("{} < {}", self.0, outcome));
+            res
+        }
+
+        fn send_batch(
+            &self,
+            reqs: &[Request],
+            next: &dyn Transport,
+        ) -> Result<Vec<Response>, Error> {
+            lock(&self.1).push(format!("{} > batch of {}", self.0, reqs.len()));
+            next.send_batch(reqs)
+        }
+    }
+
+    /// Sends `getblockcount` instead of `getinfo`.
+    struct Rename;
+
+    impl Interceptor for Rename {
+        fn send_request(&self, req: Request, next: &dyn Transport) -> Result<Response, Error> {
+            if req.method == "getinfo" {
+                return next.send_request(Request {
+                    method: "getblockcount",
+                    ..req
+                });
+            }
+            next.send_request(req)
+        }
+    }
+
+    #[test]
+    fn chain() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let client = Client::with_transport(Echo)
+            .with_interceptor(Log("inner", log.clone()))
+            .with_interceptor(Rename)
+            .with_interceptor(Log("outer", log.clone()));
+        assert_eq!(format!("{:?}", client), "jsonrpc::Client(echo)");
+
+        assert_eq!(client.call::<String>("getinfo", &[]).unwrap(), "getblockcount");
+        assert!(client.call::<String>("fail", &[]).is_err());
+        let reqs = [client.build_request("a", &[]), client.build_request("b", &[])];
+        assert_eq!(client.send_batch(&reqs).unwrap().len(), 2);
+
+        let log = lock(&log);
+        assert_eq!(
+            *log,
+            [
+                "outer > getinfo",
+                "inner > getblockcount",
+                "inner < \"getblockcount\"",
+                "outer < \"getblockcount\"",
+                "outer > fail",
+                "inner > fail",
+                "inner < Nonce of response did not match nonce of request",
+                "outer < Nonce of response did not match nonce of request",
+                "outer > batch of 2",
+                "inner > batch of 2",
+            ]
+        );
+    }
+}
@@ -14,7 +14,8 @@
 
 //! # Rust JSON-RPC Library
 //!
-//! Rust support for the JSON-RPC 2.0 protocol.
+//! Rust support for the JSON-RPC 2.0 protocol, and for the JSON-RPC 1.0 protocol still
+//! spoken by btcd, see [Version].
 //!
 
 // Coding conventions
@@ -24,20 +25,40 @@
 #![deny(unused_mut)]
 #![warn(missing_docs)]
 
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
 
 extern crate serde;
 pub extern crate serde_json;
 
-#[cfg(feature = "base64-compat")]
+#[cfg(feature = "base64")]
 pub extern crate base64;
 
+#[cfg(feature = "async")]
+pub mod async_client;
+#[cfg(feature = "simple_http")]
+pub mod auth;
 pub mod client;
+pub mod context;
 pub mod error;
+pub mod failover;
+#[cfg(feature = "simple_http")]
+mod http;
+#[cfg(feature = "instrument")]
+pub mod instrument;
+pub mod interceptor;
+#[cfg(any(feature = "simple_tcp", all(feature = "simple_uds", not(windows))))]
+mod multiplex;
+pub mod replay;
+pub mod retry;
+pub mod server;
 mod util;
 
 #[cfg(feature = "simple_http")]
 pub mod simple_http;
+#[cfg(feature = "simple_http")]
+mod socks;
 
 #[cfg(feature = "simple_tcp")]
 pub mod simple_tcp;
@@ -45,12 +66,25 @@ pub mod simple_tcp;
 #[cfg(all(feature = "simple_uds", not(windows)))]
 pub mod simple_uds;
 
+#[cfg(feature = "simple_ws")]
+pub mod simple_ws;
+
+#[cfg(feature = "async_http")]
+pub mod async_http;
+
 // Re-export error type
+#[cfg(feature = "async")]
+pub use crate::async_client::{AsyncClient, AsyncTransport};
 pub use crate::client::{Client, Transport};
-pub use crate::error::Error;
+pub use crate::context::{CallOptions, CancelToken};
+pub use crate::error::{Error, ErrorKind};
 
 use serde_json::value::RawValue;
 
+/// The largest response the transports accept by default, in bytes. Verbose `getblock`
+/// responses for full blocks take tens of megabytes.
+pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 256 * 1024 * 1024;
+
 /// Shorthand method to convert an argument into a [Box<serde_json::value::RawValue>].
 /// Since serializers rarely fail, it's probably easier to use [arg] instead.
 pub fn try_arg<T: serde::Serialize>(arg: T) -> Result<Box<RawValue>, serde_json::Error> {
@@ -77,14 +111,102 @@ pub struct Request<'a> {
     /// The name of the RPC call
     pub method: &'a str,
     /// Parameters to the RPC call
-    pub params: &'a [Box<RawValue>],
-    /// Identifier for this Request, which should appear in the response
-    pub id: serde_json::Value,
-    /// jsonrpc field, MUST be "2.0"
+    pub params: Params<'a>,
+    /// Identifier for this Request, which should appear in the response. Requests without
+    /// one are notifications, the server doesn't answer them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<serde_json::Value>,
+    /// jsonrpc field, "2.0", or "1.0" for JSON-RPC 1.0 servers like btcd
     pub jsonrpc: Option<&'a str>,
 }
 
+impl<'a> Request<'a> {
+    /// Returns whether this request is a notification, i.e. it has no id and gets no response.
+    /// A null id makes a notification too, that's how JSON-RPC 1.0 spells them.
+    pub fn is_notification(&self) -> bool {
+        self.id.as_ref().map_or(true, serde_json::Value::is_null)
+    }
+}
+
+/// The version of the JSON-RPC protocol a client speaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Version {
+    /// JSON-RPC 1.0, as spoken by btcd's `btcjson`. Requests carry `"jsonrpc": "1.0"` and
+    /// notifications a null id. Responses with `"jsonrpc": "2.0"` are rejected, and so are
+    /// the ones missing the `result` or the `error` member, 1.0 asks for both.
+    V1,
+    /// JSON-RPC 2.0, the default. Responses without a `jsonrpc` field are accepted too, like
+    /// the ones of bitcoind.
+    V2,
+}
+
+impl Version {
+    /// The value of the `jsonrpc` field of the requests.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Version::V1 => "1.0",
+            Version::V2 => "2.0",
+        }
+    }
+
+    /// Whether a response with the given `jsonrpc` field answers a request of this version.
+    pub(crate) fn accepts(self, jsonrpc: Option<&str>) -> bool {
+        jsonrpc.map_or(true, |v| v == self.as_str())
+    }
+}
+
+impl Default for Version {
+    fn default() -> Version {
+        Version::V2
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The parameters of a request, either by position or by name.
+///
+/// To construct the values, one can use one of the shorthand methods [arg] or [try_arg].
+#[derive(Debug, Clone, Copy)]
+pub enum Params<'a> {
+    /// Parameters by position, sent as an array
+    ByPosition(&'a [Box<RawValue>]),
+    /// Parameters by name, sent as an object
+    ByName(&'a [(&'a str, Box<RawValue>)]),
+}
+
+impl<'a> From<&'a [Box<RawValue>]> for Params<'a> {
+    fn from(params: &'a [Box<RawValue>]) -> Params<'a> {
+        Params::ByPosition(params)
+    }
+}
+
+impl<'a> From<&'a [(&'a str, Box<RawValue>)]> for Params<'a> {
+    fn from(params: &'a [(&'a str, Box<RawValue>)]) -> Params<'a> {
+        Params::ByName(params)
+    }
+}
+
+impl<'a> Serialize for Params<'a> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Params::ByPosition(params) => params.serialize(serializer),
+            Params::ByName(params) => serializer.collect_map(params.iter().map(|(k, v)| (k, v))),
+        }
+    }
+}
+
+/// Returns whether any of the requests gets a response, i.e. they aren't all notifications.
+#[cfg(any(feature = "simple_http", feature = "simple_tcp", feature = "simple_uds"))]
+pub(crate) fn expects_response(reqs: &[Request]) -> bool {
+    reqs.iter().any(|r| !r.is_notification())
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(from = "RawResponse")]
 /// A JSONRPC response object
 pub struct Response {
     /// A result if there is one, or null
@@ -93,8 +215,47 @@ pub struct Response {
     pub error: Option<error::RpcError>,
     /// Identifier for this Request, which should match that of the request
     pub id: serde_json::Value,
-    /// jsonrpc field, MUST be "2.0"
+    /// jsonrpc field, the version of the request if present
     pub jsonrpc: Option<String>,
+    /// Whether the `result` member was present, even if null
+    #[serde(skip_serializing)]
+    pub has_result: bool,
+    /// Whether the `error` member was present, even if null
+    #[serde(skip_serializing)]
+    pub has_error: bool,
+}
+
+/// A response as received, telling a null member from a missing one.
+#[derive(Deserialize)]
+struct RawResponse {
+    #[serde(default, deserialize_with = "present")]
+    result: Option<Option<Box<RawValue>>>,
+    #[serde(default, deserialize_with = "present")]
+    error: Option<Option<error::RpcError>>,
+    id: serde_json::Value,
+    jsonrpc: Option<String>,
+}
+
+/// Deserializes a member that may be null, wrapped in `Some` since it's present.
+fn present<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+impl From<RawResponse> for Response {
+    fn from(raw: RawResponse) -> Response {
+        Response {
+            has_result: raw.result.is_some(),
+            has_error: raw.error.is_some(),
+            result: raw.result.unwrap_or_default(),
+            error: raw.error.unwrap_or_default(),
+            id: raw.id,
+            jsonrpc: raw.jsonrpc,
+        }
+    }
 }
 
 impl Response {
@@ -139,6 +300,8 @@ mod tests {
             error: None,
             id: From::from(81),
             jsonrpc: Some(String::from("2.0")),
+            has_result: true,
+            has_error: true,
         };
 
         let bill = Response {
@@ -146,6 +309,8 @@ mod tests {
             error: None,
             id: From::from(66),
             jsonrpc: Some(String::from("2.0")),
+            has_result: true,
+            has_error: true,
         };
 
         assert!(!joanna.is_none());
@@ -160,6 +325,8 @@ mod tests {
             error: None,
             id: serde_json::Value::Null,
             jsonrpc: Some(String::from("2.0")),
+            has_result: true,
+            has_error: true,
         };
         let recovered1: Vec<String> = response.result().unwrap();
         assert!(response.clone().check_error().is_ok());
@@ -197,6 +364,33 @@ mod tests {
         assert_eq!(batch_response.len(), 5);
     }
 
+    #[test]
+    fn request_serialization() {
+        let params = [arg(1), arg("two")];
+        let req = Request {
+            method: "test",
+            params: Params::ByPosition(&params),
+            id: Some(From::from(1)),
+            jsonrpc: Some("2.0"),
+        };
+        assert_eq!(
+            serde_json::to_string(&req).unwrap(),
+            r#"{"method":"test","params":[1,"two"],"id":1,"jsonrpc":"2.0"}"#
+        );
+
+        let named = [("height", arg(1)), ("verbose", arg(true))];
+        let notification = Request {
+            params: Params::ByName(&named),
+            id: None,
+            ..req
+        };
+        assert!(notification.is_notification());
+        assert_eq!(
+            serde_json::to_string(&notification).unwrap(),
+            r#"{"method":"test","params":{"height":1,"verbose":true},"jsonrpc":"2.0"}"#
+        );
+    }
+
     #[test]
     fn test_arg() {
         macro_rules! test_arg {
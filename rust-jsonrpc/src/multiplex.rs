@@ -0,0 +1,456 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Persistent connections shared by concurrent callers, for the transports sending
+//! newline-delimited JSON over a byte stream: [crate::simple_tcp] and [crate::simple_uds].
+//!
+//! Each request, or batch, is written on a line of its own, and so is each response. A
+//! reader thread hands the responses to the callers waiting for them, by request id, so
+//! responses can arrive in any order. Once the connection is closed, the next request opens
+//! a new one.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{fmt, net};
+
+use serde::Deserialize;
+
+use crate::context;
+use crate::util::{lock, HashableValue};
+
+/// A byte stream the requests are sent over.
+pub(crate) trait Stream: Read + Write + Sized + Send + Sync + 'static {
+    /// Returns another handle on the same stream.
+    fn try_clone(&self) -> io::Result<Self>;
+    /// Shuts down both directions of the stream, for all its handles.
+    fn shutdown(&self) -> io::Result<()>;
+}
+
+impl Stream for net::TcpStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        net::TcpStream::try_clone(self)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        net::TcpStream::shutdown(self, net::Shutdown::Both)
+    }
+}
+
+#[cfg(all(feature = "simple_uds", not(windows)))]
+impl Stream for std::os::unix::net::UnixStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        std::os::unix::net::UnixStream::try_clone(self)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        std::os::unix::net::UnixStream::shutdown(self, net::Shutdown::Both)
+    }
+}
+
+/// Why a request got no response.
+#[derive(Debug)]
+pub(crate) enum Failure {
+    /// Connecting or writing the request failed
+    Io(io::Error),
+    /// No response arrived before the deadline
+    Timeout,
+    /// The server sent a line larger than the largest response accepted
+    TooLarge,
+    /// The connection was closed before the response arrived
+    Closed,
+}
+
+/// How to open and use connections.
+pub(crate) struct Config<'a, S> {
+    /// Opens a new connection.
+    pub connect: Box<dyn Fn() -> io::Result<S> + 'a>,
+    /// How long to wait for a response, forever if unset.
+    pub timeout: Option<Duration>,
+    /// The largest response accepted, in bytes.
+    pub max_response_size: usize,
+}
+
+/// A connection that's opened when needed and shared by all requests.
+pub(crate) struct Multiplexed<S: Stream> {
+    conn: Mutex<Option<Arc<Connection<S>>>>,
+}
+
+impl<S: Stream> Multiplexed<S> {
+    /// Returns a handle that opens its connection on the first request.
+    pub(crate) fn new() -> Multiplexed<S> {
+        Multiplexed {
+            conn: Mutex::new(None),
+        }
+    }
+
+    /// Sends a request, or a batch, and waits for the response carrying one of `ids`.
+    pub(crate) fn request(
+        &self,
+        config: &Config<S>,
+        ids: Vec<serde_json::Value>,
+        body: Vec<u8>,
+    ) -> Result<String, Failure> {
+        let deadline = context::request_deadline(config.timeout);
+        let ids: Vec<_> = ids.into_iter().map(|id| HashableValue(Cow::Owned(id))).collect();
+        let body = line(body);
+        let (conn, (rx, _cancel)) = self.with_connection(config, |conn| {
+            let (tx, rx) = mpsc::channel();
+            // Cancelling the call stops the wait as if the connection was closed
+            let cancel = if context::is_cancellable() {
+                let tx = Mutex::new(tx.clone());
+                Some(context::on_cancel(move || {
+                    let _ = lock(&tx).send(Err(Failure::Closed));
+                }))
+            } else {
+                None
+            };
+            conn.expect(&ids, tx)?;
+            conn.send(&body)?;
+            Ok((rx, cancel))
+        })?;
+        let res = match deadline {
+            Some(deadline) => rx.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        // The ids a batch response left out would be waited for until the connection closes
+        conn.forget(&ids);
+        // Once the request is written, the server may have run it: it isn't sent again if the
+        // connection closes before the response arrives.
+        match res {
+            Ok(res) => res,
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(Failure::Timeout),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(Failure::Closed),
+        }
+    }
+
+    /// Sends a message that gets no response, like a notification.
+    pub(crate) fn send(&self, config: &Config<S>, body: Vec<u8>) -> Result<(), Failure> {
+        let body = line(body);
+        self.with_connection(config, |conn| conn.send(&body)).map(drop)
+    }
+
+    /// Runs `write` on the open connection, or on a new one if there is none or it was
+    /// closed, and returns the connection it ran on with its result. If the server closed a
+    /// connection that was already open under our feet, the message didn't leave and `write`
+    /// runs again on a new one, unless the call was cancelled.
+    fn with_connection<T, F>(
+        &self,
+        config: &Config<S>,
+        write: F,
+    ) -> Result<(Arc<Connection<S>>, T), Failure>
+    where
+        F: Fn(&Connection<S>) -> Result<T, Failure>,
+    {
+        loop {
+            let (conn, reused) = self.connection(config)?;
+            match write(&conn) {
+                Ok(res) => return Ok((conn, res)),
+                Err(Failure::Io(_)) | Err(Failure::Closed)
+                    if reused && !context::is_cancelled() =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns the open connection, or opens a new one. The returned flag tells whether the
+    /// connection was already open.
+    fn connection(&self, config: &Config<S>) -> Result<(Arc<Connection<S>>, bool), Failure> {
+        let mut conn = lock(&self.conn);
+        if let Some(ref c) = *conn {
+            if !c.is_closed() {
+                return Ok((c.clone(), true));
+            }
+        }
+        let c = Connection::open((config.connect)().map_err(Failure::Io)?, config)
+            .map_err(Failure::Io)?;
+        *conn = Some(c.clone());
+        Ok((c, false))
+    }
+}
+
+impl<S: Stream> Drop for Multiplexed<S> {
+    fn drop(&mut self) {
+        if let Some(ref conn) = *lock(&self.conn) {
+            conn.close(false);
+        }
+    }
+}
+
+/// A transport's clone opens its own connection.
+impl<S: Stream> Clone for Multiplexed<S> {
+    fn clone(&self) -> Self {
+        Multiplexed::new()
+    }
+}
+
+impl<S: Stream> fmt::Debug for Multiplexed<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let connected = lock(&self.conn).as_ref().map_or(false, |c| !c.is_closed());
+        f.debug_struct("Multiplexed").field("connected", &connected).finish()
+    }
+}
+
+/// Adds the newline ending a message. Newlines can only be whitespace in JSON, but raw values
+/// may hold some, so they are replaced by spaces.
+fn line(mut body: Vec<u8>) -> Vec<u8> {
+    for b in body.iter_mut().filter(|b| **b == b'\n') {
+        *b = b' ';
+    }
+    body.push(b'\n');
+    body
+}
+
+/// The callers waiting for a response, by request id.
+struct Pending {
+    /// Set once the connection is closed, no response will arrive anymore.
+    closed: bool,
+    waiting: HashMap<HashableValue<'static>, mpsc::Sender<Result<String, Failure>>>,
+}
+
+/// An open connection, shared by the callers and the reader thread.
+struct Connection<S> {
+    writer: Mutex<S>,
+    pending: Mutex<Pending>,
+    /// A handle on the stream, used to shut it down.
+    sock: S,
+}
+
+impl<S: Stream> Connection<S> {
+    fn is_closed(&self) -> bool {
+        lock(&self.pending).closed
+    }
+
+    /// Closes the connection and wakes up all callers still waiting for a response.
+    /// `too_large` tells whether it's closed because the server sent a line too large.
+    fn close(&self, too_large: bool) {
+        let mut pending = lock(&self.pending);
+        if pending.closed {
+            return;
+        }
+        pending.closed = true;
+        let _ = self.sock.shutdown();
+        for (_, tx) in pending.waiting.drain() {
+            let _ = tx.send(Err(if too_large {
+                Failure::TooLarge
+            } else {
+                Failure::Closed
+            }));
+        }
+    }
+
+    /// Starts the reader thread on a freshly opened stream.
+    fn open(sock: S, config: &Config<S>) -> io::Result<Arc<Connection<S>>> {
+        let reader = sock.try_clone()?;
+        let conn = Arc::new(Connection {
+            writer: Mutex::new(sock.try_clone()?),
+            pending: Mutex::new(Pending {
+                closed: false,
+                waiting: HashMap::new(),
+            }),
+            sock,
+        });
+        let reader_conn = conn.clone();
+        let max_size = config.max_response_size;
+        thread::Builder::new().name("jsonrpc-reader".to_owned()).spawn(move || {
+            let too_large = reader_conn.read_loop(&mut BufReader::new(reader), max_size);
+            reader_conn.close(too_large);
+        })?;
+        Ok(conn)
+    }
+
+    /// Registers `tx` as the receiver of the response for `ids`.
+    fn expect(
+        &self,
+        ids: &[HashableValue<'static>],
+        tx: mpsc::Sender<Result<String, Failure>>,
+    ) -> Result<(), Failure> {
+        let mut pending = lock(&self.pending);
+        if pending.closed {
+            return Err(Failure::Closed);
+        }
+        for id in ids {
+            pending.waiting.insert(id.clone(), tx.clone());
+        }
+        Ok(())
+    }
+
+    /// Stops waiting for the response for `ids`.
+    fn forget(&self, ids: &[HashableValue<'static>]) {
+        let mut pending = lock(&self.pending);
+        for id in ids {
+            pending.waiting.remove(id);
+        }
+    }
+
+    /// Writes a whole message, closing the connection if that fails.
+    fn send(&self, body: &[u8]) -> Result<(), Failure> {
+        let mut writer = lock(&self.writer);
+        if let Err(e) = writer.write_all(body).and_then(|_| writer.flush()) {
+            drop(writer);
+            self.close(false);
+            return Err(Failure::Io(e));
+        }
+        Ok(())
+    }
+
+    /// Reads responses until the connection is closed, handing them to their callers.
+    /// Returns whether it stopped because a line was larger than `max_size`.
+    fn read_loop<R: BufRead>(&self, reader: &mut R, max_size: usize) -> bool {
+        loop {
+            match read_line(reader, max_size) {
+                Ok(Some(line)) => {
+                    if let Ok(text) = String::from_utf8(line) {
+                        self.dispatch(text);
+                    }
+                }
+                Ok(None) => return false,
+                Err(Failure::TooLarge) => return true,
+                Err(_) => return false,
+            }
+        }
+    }
+
+    /// Hands a response, or the responses to a batch, to the caller waiting for it.
+    /// Responses nobody waits for anymore, and requests from the server, are dropped. An
+    /// error without id, sent for a request the server couldn't parse, closes the
+    /// connection.
+    fn dispatch(&self, text: String) {
+        #[derive(Deserialize)]
+        struct Message {
+            #[serde(default)]
+            id: serde_json::Value,
+            #[serde(default)]
+            error: Option<serde_json::Value>,
+        }
+
+        // A batch first: with all its fields optional, a message parses from an array too
+        let messages = match serde_json::from_str::<Vec<Message>>(&text) {
+            Ok(resps) => resps,
+            Err(_) => match serde_json::from_str::<Message>(&text) {
+                Ok(resp) => vec![resp],
+                Err(_) => return,
+            },
+        };
+
+        let mut pending = lock(&self.pending);
+        let mut tx = None;
+        let mut unattributed_error = false;
+        for message in messages {
+            unattributed_error |= message.id.is_null() && message.error.is_some();
+            if let Some(sender) = pending.waiting.remove(&HashableValue(Cow::Owned(message.id))) {
+                tx = Some(sender);
+            }
+        }
+        drop(pending);
+        match tx {
+            Some(tx) => {
+                let _ = tx.send(Ok(text));
+            }
+            // The server couldn't read one of the requests, and can't tell which one. Its
+            // caller would wait forever, so all of them fail.
+            None if unattributed_error => self.close(false),
+            None => {}
+        }
+    }
+}
+
+/// Reads a line of at most `max_size` bytes, without its newline. A last line without
+/// newline is returned as well, `None` is returned at the end of the stream.
+fn read_line<R: BufRead>(reader: &mut R, max_size: usize) -> Result<Option<Vec<u8>>, Failure> {
+    let mut line = Vec::new();
+    loop {
+        let buf = match reader.fill_buf() {
+            Ok(buf) => buf,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(Failure::Io(e)),
+        };
+        if buf.is_empty() {
+            return Ok(if line.is_empty() {
+                None
+            } else {
+                Some(line)
+            });
+        }
+        let (chunk, end) = match buf.iter().position(|&b| b == b'\n') {
+            Some(i) => (&buf[..i], true),
+            None => (buf, false),
+        };
+        if line.len() + chunk.len() > max_size {
+            return Err(Failure::TooLarge);
+        }
+        line.extend_from_slice(chunk);
+        let used = chunk.len() + end as usize;
+        reader.consume(used);
+        if end {
+            return Ok(Some(line));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let mut reader = io::Cursor::new(&b"{\"id\":1}\n\n[{\"id\":2}]\n{\"id\""[..]);
+        assert_eq!(read_line(&mut reader, 100).unwrap().unwrap(), b"{\"id\":1}");
+        assert_eq!(read_line(&mut reader, 100).unwrap().unwrap(), b"");
+        assert_eq!(read_line(&mut reader, 100).unwrap().unwrap(), b"[{\"id\":2}]");
+        assert_eq!(read_line(&mut reader, 100).unwrap().unwrap(), b"{\"id\"");
+        assert!(read_line(&mut reader, 100).unwrap().is_none());
+
+        let mut reader = io::Cursor::new(&b"0123456789\n"[..]);
+        assert!(matches!(read_line(&mut reader, 9), Err(Failure::TooLarge)));
+        let mut reader = io::Cursor::new(&b"0123456789\n"[..]);
+        assert_eq!(read_line(&mut reader, 10).unwrap().unwrap(), b"0123456789");
+
+        assert_eq!(line(b"{\"a\":\n1}".to_vec()), b"{\"a\": 1}\n");
+    }
+
+    #[test]
+    fn batch_response_missing_an_id() {
+        let server = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut sock, _) = server.accept().unwrap();
+            let mut reader = BufReader::new(sock.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            sock.write_all(b"[{\"result\":true,\"error\":null,\"id\":1}]\n").unwrap();
+            // Hold the connection open until the client closes it
+            let _ = reader.read_line(&mut line);
+        });
+
+        let config = Config {
+            connect: Box::new(move || net::TcpStream::connect(addr)),
+            timeout: Some(Duration::from_secs(5)),
+            max_response_size: 1000,
+        };
+        let mux = Multiplexed::new();
+        let body = b"[{\"method\":\"a\",\"id\":1},{\"method\":\"b\",\"id\":2}]".to_vec();
+        mux.request(&config, vec![1.into(), 2.into()], body).unwrap();
+
+        let conn = lock(&mux.conn).clone().unwrap();
+        assert!(!conn.is_closed());
+        assert!(lock(&conn.pending).waiting.is_empty());
+    }
+}
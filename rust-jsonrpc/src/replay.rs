@@ -0,0 +1,376 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Record and replay transports
+//!
+//! [RecordingTransport] writes every request it sends, along with the response, to a fixture
+//! file. [ReplayTransport] answers requests from such a file, without any server, which makes
+//! tests of code talking to a node fast and deterministic.
+//!
+//! Fixtures are JSON arrays of [Exchange]s, so they can be reviewed and edited by hand.
+//!
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::{error, fmt, fs, io};
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use serde_json::Value;
+
+use crate::client::Transport;
+use crate::error::RpcError;
+use crate::util::lock;
+use crate::{Request, Response};
+
+/// Error that can occur while recording or replaying.
+#[derive(Debug)]
+pub enum Error {
+    /// The fixture file couldn't be read or written.
+    Io(io::Error),
+    /// The fixture file isn't valid.
+    Json(serde_json::Error),
+    /// No response was recorded for the request.
+    NotRecorded {
+        /// The method of the request.
+        method: String,
+        /// The params of the request.
+        params: Value,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Error::Io(ref e) => write!(f, "couldn't access the fixture: {}", e),
+            Error::Json(ref e) => write!(f, "invalid fixture: {}", e),
+            Error::NotRecorded {
+                ref method,
+                ref params,
+            } => write!(f, "no recorded response for {}({})", method, params),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            Error::Json(ref e) => Some(e),
+            Error::NotRecorded {
+                ..
+            } => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<Error> for crate::Error {
+    fn from(e: Error) -> crate::Error {
+        match e {
+            Error::Json(e) => crate::Error::Json(e),
+            e => crate::Error::Transport(Box::new(e)),
+        }
+    }
+}
+
+/// A request and the response it got.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Exchange {
+    /// The method of the request.
+    pub method: String,
+    /// The params of the request.
+    pub params: Value,
+    /// The result of the call, null if it failed.
+    pub result: Value,
+    /// The error the call failed with, if it did.
+    pub error: Option<RpcError>,
+}
+
+impl Exchange {
+    fn new(req: &Request, resp: &Response) -> Result<Exchange, Error> {
+        let result = match resp.result {
+            Some(ref result) => serde_json::from_str(result.get())?,
+            None => Value::Null,
+        };
+        Ok(Exchange {
+            method: req.method.to_owned(),
+            params: params(req)?,
+            result,
+            error: resp.error.clone(),
+        })
+    }
+
+    /// Returns the recorded response, as an answer to `req`: with its id and its version, so
+    /// that the fixtures serve clients of any version. Requests without a version get "2.0".
+    fn response(&self, req: &Request) -> Result<Response, Error> {
+        Ok(Response {
+            result: Some(RawValue::from_string(self.result.to_string())?),
+            error: self.error.clone(),
+            id: req.id.clone().unwrap_or_default(),
+            jsonrpc: Some(req.jsonrpc.unwrap_or("2.0").to_owned()),
+            has_result: true,
+            has_error: true,
+        })
+    }
+}
+
+fn params(req: &Request) -> Result<Value, Error> {
+    Ok(serde_json::to_value(req.params)?)
+}
+
+/// A [Transport] recording all exchanges with the server it wraps to a fixture file.
+#[derive(Debug)]
+pub struct RecordingTransport<T> {
+    inner: T,
+    path: PathBuf,
+    exchanges: Mutex<Vec<Exchange>>,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+    /// Records the exchanges over `inner` to the file at `path`, replacing it.
+    ///
+    /// The file is rewritten after each exchange, so it's always complete.
+    pub fn new<P: AsRef<Path>>(inner: T, path: P) -> Self {
+        RecordingTransport {
+            inner,
+            path: path.as_ref().to_owned(),
+            exchanges: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the exchanges recorded so far.
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        lock(&self.exchanges).clone()
+    }
+
+    fn record(&self, new: Vec<Exchange>) -> Result<(), Error> {
+        let mut exchanges = lock(&self.exchanges);
+        exchanges.extend(new);
+        let json = serde_json::to_string_pretty(&*exchanges)?;
+        fs::write(&self.path, json + "\n")?;
+        Ok(())
+    }
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+    fn send_request(&self, req: Request) -> Result<Response, crate::Error> {
+        let resp = self.inner.send_request(req.clone())?;
+        self.record(vec![Exchange::new(&req, &resp)?])?;
+        Ok(resp)
+    }
+
+    fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, crate::Error> {
+        let resps = self.inner.send_batch(reqs)?;
+        let mut exchanges = Vec::with_capacity(resps.len());
+        for resp in &resps {
+            if let Some(req) = reqs.iter().find(|r| r.id.as_ref() == Some(&resp.id)) {
+                exchanges.push(Exchange::new(req, resp)?);
+            }
+        }
+        self.record(exchanges)?;
+        Ok(resps)
+    }
+
+    /// Notifications are sent, but not recorded since they get no response.
+    fn send_notification(&self, notification: Request) -> Result<(), crate::Error> {
+        self.inner.send_notification(notification)
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt_target(f)
+    }
+}
+
+/// A [Transport] answering requests with the responses recorded for them.
+///
+/// Requests are matched by method and params. If the same request was recorded several times,
+/// the responses are given back in order, and the last one over and over once they run out.
+#[derive(Debug)]
+pub struct ReplayTransport {
+    path: Option<PathBuf>,
+    exchanges: Vec<Exchange>,
+    /// Whether each exchange was replayed already.
+    replayed: Mutex<Vec<bool>>,
+}
+
+impl ReplayTransport {
+    /// Replays the exchanges recorded to the file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let exchanges = serde_json::from_slice(&fs::read(path.as_ref())?)?;
+        let mut tp = ReplayTransport::new(exchanges);
+        tp.path = Some(path.as_ref().to_owned());
+        Ok(tp)
+    }
+
+    /// Replays the given exchanges.
+    pub fn new(exchanges: Vec<Exchange>) -> Self {
+        ReplayTransport {
+            path: None,
+            replayed: Mutex::new(vec![false; exchanges.len()]),
+            exchanges,
+        }
+    }
+
+    fn answer(&self, req: &Request) -> Result<Response, Error> {
+        let params = params(req)?;
+        let mut replayed = lock(&self.replayed);
+        let matching: Vec<usize> = (0..self.exchanges.len())
+            .filter(|&i| {
+                self.exchanges[i].method == req.method && self.exchanges[i].params == params
+            })
+            .collect();
+        let i = match matching.iter().find(|&&i| !replayed[i]).or_else(|| matching.last()) {
+            Some(&i) => i,
+            None => {
+                return Err(Error::NotRecorded {
+                    method: req.method.to_owned(),
+                    params,
+                })
+            }
+        };
+        replayed[i] = true;
+        self.exchanges[i].response(req)
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send_request(&self, req: Request) -> Result<Response, crate::Error> {
+        Ok(self.answer(&req)?)
+    }
+
+    fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, crate::Error> {
+        let reqs = reqs.iter().filter(|r| !r.is_notification());
+        Ok(reqs.map(|r| self.answer(r)).collect::<Result<_, _>>()?)
+    }
+
+    /// Notifications aren't recorded, there is nothing to replay.
+    fn send_notification(&self, _: Request) -> Result<(), crate::Error> {
+        Ok(())
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.path {
+            Some(ref path) => write!(f, "replay({})", path.display()),
+            None => write!(f, "replay"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::{Client, Params};
+
+    /// Answers `add` with the sum of its params, and fails any other method.
+    struct Adder;
+
+    impl Transport for Adder {
+        fn send_request(&self, req: Request) -> Result<Response, crate::Error> {
+            let resp = match req.method {
+                "add" => {
+                    let params = match req.params {
+                        Params::ByPosition(params) => params,
+                        Params::ByName(_) => &[],
+                    };
+                    let sum: i64 = params.iter().map(|p| p.get().parse::<i64>().unwrap()).sum();
+                    serde_json::json!({"result": sum, "error": null, "id": req.id})
+                }
+                _ => serde_json::json!({
+                    "result": null,
+                    "error": {"code": -32601, "message": "Method not found"},
+                    "id": req.id,
+                }),
+            };
+            Ok(serde_json::from_value(resp).unwrap())
+        }
+
+        fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, crate::Error> {
+            reqs.iter().map(|r| self.send_request(r.clone())).collect()
+        }
+
+        fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "adder")
+        }
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let path = env::temp_dir().join(format!("jsonrpc-replay-{}.json", std::process::id()));
+
+        let client = Client::with_transport(RecordingTransport::new(Adder, &path));
+        assert_eq!(client.call::<i64>("add", &[crate::arg(1), crate::arg(2)]).unwrap(), 3);
+        assert!(client.call::<i64>("sub", &[crate::arg(1)]).is_err());
+        let reqs = [client.build_request("add", &[]), client.build_request("sub", &[])];
+        assert_eq!(client.send_batch(&reqs).unwrap().len(), 2);
+
+        let client = Client::with_transport(ReplayTransport::open(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(client.call::<i64>("add", &[crate::arg(1), crate::arg(2)]).unwrap(), 3);
+        match client.call::<i64>("sub", &[crate::arg(1)]) {
+            Err(crate::Error::Rpc(e)) => assert_eq!(e.code, -32601),
+            res => panic!("expected an RPC error, got {:?}", res),
+        }
+        let reqs = [client.build_request("sub", &[]), client.build_request("add", &[])];
+        let resps = client.send_batch(&reqs).unwrap();
+        assert_eq!(resps[1].as_ref().unwrap().result::<i64>().unwrap(), 0);
+
+        match client.call::<i64>("add", &[crate::arg(2), crate::arg(2)]) {
+            Err(crate::Error::Transport(e)) => {
+                assert_eq!(e.to_string(), "no recorded response for add([2,2])")
+            }
+            res => panic!("expected a transport error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn replays_in_order() {
+        let exchange = |height: u64| Exchange {
+            method: "getblockcount".to_owned(),
+            params: Value::Array(vec![]),
+            result: Value::from(height),
+            error: None,
+        };
+        let tp = ReplayTransport::new(vec![exchange(1), exchange(2)]);
+        let client = Client::with_transport(tp);
+        for &expected in &[1, 2, 2] {
+            assert_eq!(client.call::<u64>("getblockcount", &[]).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn replays_to_v1_clients() {
+        let tp = ReplayTransport::new(vec![Exchange {
+            method: "getblockcount".to_owned(),
+            params: Value::Array(vec![]),
+            result: Value::from(7),
+            error: None,
+        }]);
+        let client = Client::with_transport(tp).with_version(crate::Version::V1);
+        assert_eq!(client.call::<u64>("getblockcount", &[]).unwrap(), 7);
+    }
+}
@@ -0,0 +1,468 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Retrying transport
+//!
+//! A [Transport] wrapper that retries failed requests with exponential backoff and jitter.
+//!
+//! Requests are retried on I/O errors, timeouts, HTTP 5xx responses and on a configurable set
+//! of RPC error codes. Methods with side effects, like `sendrawtransaction` or `addnode`, are
+//! only retried on those RPC error codes, since they tell the server refused to run the
+//! request: after a timeout or a dropped connection there's no telling whether it ran.
+//!
+
+use std::collections::HashSet;
+use std::time::Duration;
+use std::{fmt, thread};
+
+use crate::client::Transport;
+use crate::context;
+use crate::util::random_u64;
+use crate::{Error, Request, Response};
+
+/// The RPC error codes retried by default. -28 is sent by bitcoind and btcd while warming up.
+pub const DEFAULT_RETRY_CODES: &[i32] = &[-28];
+
+/// The methods that aren't retried on transport errors by default, because running them twice
+/// has a different effect than running them once.
+pub const DEFAULT_NON_IDEMPOTENT: &[&str] = &[
+    "sendrawtransaction",
+    "submitblock",
+    "addnode",
+    "node",
+    "generate",
+    "generatetoaddress",
+    "setgenerate",
+    "sendtoaddress",
+    "sendmany",
+    "sendfrom",
+    "stop",
+];
+
+/// Returns whether running all of `reqs` twice has the same effect as running them once,
+/// going by [DEFAULT_NON_IDEMPOTENT].
+#[cfg(any(feature = "simple_http", feature = "async_http"))]
+pub(crate) fn is_idempotent(reqs: &[Request]) -> bool {
+    reqs.iter().all(|r| !DEFAULT_NON_IDEMPOTENT.contains(&r.method))
+}
+
+/// A [Transport] that retries failed requests on another transport.
+#[derive(Debug)]
+pub struct RetryTransport<T> {
+    inner: T,
+    max_retries: usize,
+    initial_delay: Duration,
+    max_delay: Duration,
+    multiplier: f64,
+    jitter: f64,
+    retry_codes: Vec<i32>,
+    non_idempotent: HashSet<String>,
+}
+
+impl<T: Transport> RetryTransport<T> {
+    /// Wraps `inner` with the default retry policy.
+    pub fn new(inner: T) -> Self {
+        Builder::new(inner).build()
+    }
+
+    /// Returns a builder for a [RetryTransport] wrapping `inner`.
+    pub fn builder(inner: T) -> Builder<T> {
+        Builder::new(inner)
+    }
+
+    /// Returns the wrapped transport.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    fn is_idempotent(&self, method: &str) -> bool {
+        !self.non_idempotent.contains(method)
+    }
+
+    fn is_retry_code(&self, response: &Response) -> bool {
+        match response.error {
+            Some(ref e) => self.retry_codes.contains(&e.code),
+            None => false,
+        }
+    }
+
+    /// Returns how long to wait before the given retry, counting from 0.
+    fn delay(&self, retry: usize) -> Duration {
+        let mut delay = self.initial_delay.as_secs_f64();
+        for _ in 0..retry {
+            delay *= self.multiplier;
+            if delay >= self.max_delay.as_secs_f64() {
+                break;
+            }
+        }
+        let delay = delay.min(self.max_delay.as_secs_f64());
+        // Takes off a random part of up to `jitter` of the delay, so that clients failing at
+        // the same time don't all come back at the same time.
+        let random = random_u64() as f64 / std::u64::MAX as f64;
+        Duration::from_secs_f64(delay * (1.0 - self.jitter * random))
+    }
+
+    /// Runs `send` until it succeeds, fails in a way `retryable` doesn't accept, or we're out
+    /// of retries, or of time before the deadline of the call.
+    fn with_retries<R, F, C>(&self, mut send: F, retryable: C) -> Result<R, Error>
+    where
+        F: FnMut() -> Result<R, Error>,
+        C: Fn(&Result<R, Error>) -> bool,
+    {
+        let mut retry = 0;
+        loop {
+            let result = send();
+            if retry >= self.max_retries || !retryable(&result) || context::is_cancelled() {
+                return result;
+            }
+            // Don't wait for a retry that would start past the deadline of the call
+            let delay = self.delay(retry);
+            if context::remaining().map_or(false, |remaining| remaining <= delay) {
+                return result;
+            }
+            thread::sleep(delay);
+            retry += 1;
+        }
+    }
+}
+
+impl<T: Transport> Transport for RetryTransport<T> {
+    fn send_request(&self, req: Request) -> Result<Response, Error> {
+        let idempotent = self.is_idempotent(req.method);
+        self.with_retries(
+            || self.inner.send_request(req.clone()),
+            |result| match *result {
+                Ok(ref resp) => self.is_retry_code(resp),
+                Err(ref e) => idempotent && e.is_retryable(),
+            },
+        )
+    }
+
+    fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, Error> {
+        let idempotent = reqs.iter().all(|r| self.is_idempotent(r.method));
+        self.with_retries(
+            || self.inner.send_batch(reqs),
+            |result| match *result {
+                // If every request got refused, none of them ran and we can send them all
+                // again. Otherwise only if running the others twice is harmless. There's no
+                // telling whether notifications were refused, they get no response.
+                Ok(ref resps) => {
+                    if idempotent {
+                        resps.iter().any(|r| self.is_retry_code(r))
+                    } else {
+                        !resps.is_empty()
+                            && resps.len() == reqs.len()
+                            && resps.iter().all(|r| self.is_retry_code(r))
+                    }
+                }
+                Err(ref e) => idempotent && e.is_retryable(),
+            },
+        )
+    }
+
+    fn send_notification(&self, notification: Request) -> Result<(), Error> {
+        let idempotent = self.is_idempotent(notification.method);
+        self.with_retries(
+            || self.inner.send_notification(notification.clone()),
+            |result| match *result {
+                Ok(()) => false,
+                Err(ref e) => idempotent && e.is_retryable(),
+            },
+        )
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt_target(f)
+    }
+}
+
+/// Builder for a [RetryTransport].
+#[derive(Debug)]
+pub struct Builder<T> {
+    tp: RetryTransport<T>,
+}
+
+impl<T: Transport> Builder<T> {
+    /// Constructs a new [Builder] wrapping `inner`, with the default retry policy: up to 3
+    /// retries, waiting 100ms then twice as long each time up to 10s, with up to half of each
+    /// delay taken off at random.
+    pub fn new(inner: T) -> Self {
+        Builder {
+            tp: RetryTransport {
+                inner,
+                max_retries: 3,
+                initial_delay: Duration::from_millis(100),
+                max_delay: Duration::from_secs(10),
+                multiplier: 2.0,
+                jitter: 0.5,
+                retry_codes: DEFAULT_RETRY_CODES.to_vec(),
+                non_idempotent: DEFAULT_NON_IDEMPOTENT.iter().map(|m| m.to_string()).collect(),
+            },
+        }
+    }
+
+    /// Sets how many times a request is retried before giving up.
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.tp.max_retries = max_retries;
+        self
+    }
+
+    /// Sets how long to wait before the first retry.
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.tp.initial_delay = delay;
+        self
+    }
+
+    /// Sets the longest we wait between two attempts.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.tp.max_delay = delay;
+        self
+    }
+
+    /// Sets by how much the delay grows after each retry.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.tp.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Sets the largest part of each delay that may be taken off at random, between 0 (always
+    /// wait the full delay) and 1.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.tp.jitter = jitter.max(0.0).min(1.0);
+        self
+    }
+
+    /// Also retries requests that failed with the given RPC error code.
+    ///
+    /// This is done even for non-idempotent methods, so only add codes with which the server
+    /// refuses to run a request at all.
+    pub fn retry_code(mut self, code: i32) -> Self {
+        if !self.tp.retry_codes.contains(&code) {
+            self.tp.retry_codes.push(code);
+        }
+        self
+    }
+
+    /// Sets the RPC error codes to retry on, replacing the default ones.
+    pub fn retry_codes(mut self, codes: &[i32]) -> Self {
+        self.tp.retry_codes = codes.to_vec();
+        self
+    }
+
+    /// Never retries `method` after a transport error.
+    pub fn non_idempotent(mut self, method: &str) -> Self {
+        self.tp.non_idempotent.insert(method.to_owned());
+        self
+    }
+
+    /// Allows retrying `method` after a transport error, even if it's in
+    /// [DEFAULT_NON_IDEMPOTENT].
+    pub fn idempotent(mut self, method: &str) -> Self {
+        self.tp.non_idempotent.remove(method);
+        self
+    }
+
+    /// Builds the final [RetryTransport].
+    pub fn build(self) -> RetryTransport<T> {
+        self.tp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::io;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use serde_json::value::RawValue;
+
+    use super::*;
+    use crate::error::RpcError;
+    use crate::Client;
+    use crate::Params;
+
+    /// Answers with the given results in order, counting the attempts.
+    struct Scripted {
+        results: Mutex<VecDeque<Result<i32, io::ErrorKind>>>,
+        attempts: AtomicUsize,
+    }
+
+    impl Scripted {
+        /// `Ok(0)` is a successful response, `Ok(code)` an RPC error.
+        fn new(results: Vec<Result<i32, io::ErrorKind>>) -> Scripted {
+            Scripted {
+                results: Mutex::new(results.into()),
+                attempts: AtomicUsize::new(0),
+            }
+        }
+
+        fn next(&self, id: &serde_json::Value) -> Result<Response, Error> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            let code = self.results.lock().unwrap().pop_front().unwrap();
+            let code = code.map_err(|kind| Error::Transport(Box::new(io::Error::from(kind))))?;
+            Ok(Response {
+                result: Some(RawValue::from_string("true".to_owned()).unwrap()),
+                error: if code == 0 {
+                    None
+                } else {
+                    Some(RpcError {
+                        code,
+                        message: "nope".to_owned(),
+                        data: None,
+                    })
+                },
+                id: id.clone(),
+                jsonrpc: Some("2.0".to_owned()),
+                has_result: true,
+                has_error: true,
+            })
+        }
+    }
+
+    impl Transport for Scripted {
+        fn send_request(&self, req: Request) -> Result<Response, Error> {
+            self.next(&req.id.unwrap_or_default())
+        }
+
+        fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, Error> {
+            reqs.iter().map(|r| self.next(r.id.as_ref().unwrap())).collect()
+        }
+
+        fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "scripted")
+        }
+    }
+
+    fn retrying(results: Vec<Result<i32, io::ErrorKind>>) -> RetryTransport<Scripted> {
+        RetryTransport::builder(Scripted::new(results))
+            .initial_delay(Duration::from_millis(1))
+            .build()
+    }
+
+    fn attempts(tp: &RetryTransport<Scripted>) -> usize {
+        tp.inner().attempts.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let tp = retrying(vec![
+            Err(io::ErrorKind::ConnectionRefused),
+            Ok(-28),
+            Err(io::ErrorKind::TimedOut),
+            Ok(0),
+        ]);
+        let client = Client::with_transport(tp);
+        assert!(client.call::<bool>("getblockcount", &[]).unwrap());
+
+        let tp = retrying(vec![Ok(-8)]);
+        let client = Client::with_transport(tp);
+        assert!(client.call::<bool>("getblockcount", &[]).is_err());
+    }
+
+    #[test]
+    fn gives_up() {
+        let tp = retrying(vec![Err(io::ErrorKind::ConnectionReset); 4]);
+        let req = Request {
+            method: "getblockcount",
+            params: Params::ByPosition(&[]),
+            id: Some(serde_json::Value::from(1)),
+            jsonrpc: Some("2.0"),
+        };
+        assert!(tp.send_request(req).is_err());
+        assert_eq!(attempts(&tp), 4);
+    }
+
+    #[test]
+    fn non_idempotent_methods() {
+        let req = Request {
+            method: "sendrawtransaction",
+            params: Params::ByPosition(&[]),
+            id: Some(serde_json::Value::from(1)),
+            jsonrpc: Some("2.0"),
+        };
+
+        // The server may have got the transaction before timing out.
+        let tp = retrying(vec![Err(io::ErrorKind::TimedOut), Ok(0)]);
+        assert!(tp.send_request(req.clone()).is_err());
+        assert_eq!(attempts(&tp), 1);
+
+        // But while warming up it didn't even look at it.
+        let tp = retrying(vec![Ok(-28), Ok(0)]);
+        assert!(tp.send_request(req.clone()).unwrap().error.is_none());
+        assert_eq!(attempts(&tp), 2);
+
+        let tp = RetryTransport::builder(Scripted::new(vec![Err(io::ErrorKind::TimedOut), Ok(0)]))
+            .initial_delay(Duration::from_millis(1))
+            .idempotent("sendrawtransaction")
+            .build();
+        assert!(tp.send_request(req).is_ok());
+        assert_eq!(attempts(&tp), 2);
+    }
+
+    #[test]
+    fn batches() {
+        let reqs = [
+            Request {
+                method: "getblockcount",
+                params: Params::ByPosition(&[]),
+                id: Some(serde_json::Value::from(1)),
+                jsonrpc: Some("2.0"),
+            },
+            Request {
+                method: "addnode",
+                params: Params::ByPosition(&[]),
+                id: Some(serde_json::Value::from(2)),
+                jsonrpc: Some("2.0"),
+            },
+        ];
+
+        // addnode ran, so the batch can't be sent again.
+        let tp = retrying(vec![Ok(-28), Ok(0)]);
+        assert!(tp.send_batch(&reqs).is_ok());
+        assert_eq!(attempts(&tp), 2);
+
+        let tp = retrying(vec![Ok(-28), Ok(-28), Ok(0), Ok(0)]);
+        let resps = tp.send_batch(&reqs).unwrap();
+        assert!(resps.iter().all(|r| r.error.is_none()));
+        assert_eq!(attempts(&tp), 4);
+
+        let tp = retrying(vec![Ok(0), Ok(-28), Ok(0), Ok(0)]);
+        assert!(tp.send_batch(&reqs[..1]).is_ok());
+        assert!(tp.send_batch(&reqs[..1]).is_ok());
+        assert_eq!(attempts(&tp), 3);
+    }
+
+    #[test]
+    fn backoff() {
+        let tp = RetryTransport::builder(Scripted::new(vec![]))
+            .initial_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(350))
+            .jitter(0.0)
+            .build();
+        assert_eq!(tp.delay(0), Duration::from_millis(100));
+        assert_eq!(tp.delay(1), Duration::from_millis(200));
+        assert_eq!(tp.delay(2), Duration::from_millis(350));
+        assert_eq!(tp.delay(50), Duration::from_millis(350));
+
+        let tp = RetryTransport::builder(Scripted::new(vec![]))
+            .initial_delay(Duration::from_millis(100))
+            .jitter(0.5)
+            .build();
+        for _ in 0..100 {
+            let delay = tp.delay(1);
+            assert!(delay > Duration::from_millis(100) && delay <= Duration::from_millis(200));
+        }
+    }
+}
@@ -0,0 +1,750 @@
+// Rust JSON-RPC Library
+// Written in 2015 by
+//     Andrew Poelstra <apoelstra@wpsoftware.net>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # JSON-RPC server
+//!
+//! A [Server] maps method names to handlers, and answers requests by calling them. Handlers
+//! are typed: their params are deserialized from the request, either positional or named,
+//! and their result is serialized into the response. Batches and notifications are handled,
+//! and malformed requests get the standard errors of [crate::error::StandardError].
+//!
+//! The server can answer over HTTP, like bitcoind or btcd do, or over raw TCP or Unix sockets
+//! with the `simple_tcp` and `simple_uds` features. It also implements [Transport], so a
+//! [Client] can call it in-process, which is handy for test doubles.
+//!
+//! ```
+//! use jsonrpc::server::Server;
+//! use jsonrpc::Client;
+//!
+//! let server = Server::new()
+//!     .register("add", |(a, b): (i64, i64)| Ok(a + b))
+//!     .register("echo", |msg: String| Ok(msg));
+//!
+//! let client = Client::with_transport(server);
+//! let sum: i64 = client.call("add", &[jsonrpc::arg(1), jsonrpc::arg(2)]).unwrap();
+//! assert_eq!(sum, 3);
+//! ```
+//!
+//! [Client]: crate::Client
+
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+#[cfg(any(feature = "simple_http", feature = "simple_tcp", feature = "simple_uds"))]
+use std::{io, sync::Arc, thread};
+
+#[cfg(any(feature = "simple_http", feature = "simple_tcp"))]
+use std::net::TcpListener;
+#[cfg(feature = "simple_http")]
+use std::net::TcpStream;
+#[cfg(all(feature = "simple_uds", not(windows)))]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::client::Transport;
+use crate::error::{result_to_response, standard_error, RpcError, StandardError};
+use crate::{Request, Response};
+
+/// The largest request body the HTTP listener accepts.
+#[cfg(feature = "simple_http")]
+pub const MAX_REQUEST_SIZE: usize = 32 * 1024 * 1024;
+
+/// How long the HTTP listener waits for a request, including for the next request on an
+/// idle connection.
+#[cfg(feature = "simple_http")]
+const HTTP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+type Handler = Box<dyn Fn(Option<Value>) -> Result<Value, RpcError> + Send + Sync>;
+type Fallback = Box<dyn Fn(&str, Option<Value>) -> Result<Value, RpcError> + Send + Sync>;
+
+/// A JSON-RPC server, dispatching requests to the handlers registered for their method.
+pub struct Server {
+    methods: HashMap<String, Handler>,
+    fallback: Option<Fallback>,
+    /// The `Authorization` header the HTTP listener expects, if any.
+    #[cfg(feature = "simple_http")]
+    auth: Option<crate::auth::Secret>,
+}
+
+impl Server {
+    /// Creates a server without any method.
+    pub fn new() -> Server {
+        Server {
+            methods: HashMap::new(),
+            fallback: None,
+            #[cfg(feature = "simple_http")]
+            auth: None,
+        }
+    }
+
+    /// Registers a handler for `method`, replacing any previous one.
+    ///
+    /// The params of the request are deserialized into `P`. Positional params can go into a
+    /// tuple, a `Vec` or a struct, and named params into a struct or a map. A request without
+    /// params can go into `()`, or into a struct whose fields all have defaults. Requests
+    /// whose params don't fit get an "Invalid params" error, with the reason as data.
+    ///
+    /// Use [serde_json::Value] as `P` to get the params as they are, e.g. to forward them.
+    pub fn register<P, R, F>(mut self, method: &str, handler: F) -> Self
+    where
+        P: DeserializeOwned,
+        R: Serialize,
+        F: Fn(P) -> Result<R, RpcError> + Send + Sync + 'static,
+    {
+        let handler = move |params: Option<Value>| {
+            let result = handler(parse_params(params)?)?;
+            serde_json::to_value(result).map_err(|e| internal_error(e.to_string()))
+        };
+        self.methods.insert(method.to_owned(), Box::new(handler));
+        self
+    }
+
+    /// Sets a handler for the methods that weren't registered, instead of answering "Method
+    /// not found". It gets the method name and the params as they are.
+    pub fn fallback<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&str, Option<Value>) -> Result<Value, RpcError> + Send + Sync + 'static,
+    {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Requires HTTP clients to authenticate with `user` and `pass`, like
+    /// [crate::simple_http::Builder::auth] does. Other clients get a 401 response.
+    #[cfg(feature = "simple_http")]
+    pub fn auth<S: AsRef<str>>(mut self, user: S, pass: Option<S>) -> Self {
+        let pass = pass.as_ref().map_or("", |p| p.as_ref());
+        self.auth = Some(crate::auth::Credentials::new(user.as_ref(), pass).header());
+        self
+    }
+
+    /// Calls the handler of `method` with the given params, as if a request came in.
+    ///
+    /// A panicking handler doesn't take the server down: the call fails with an "Internal
+    /// error" instead.
+    pub fn call(&self, method: &str, params: Option<Value>) -> Result<Value, RpcError> {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            match (self.methods.get(method), &self.fallback) {
+                (Some(handler), _) => handler(params),
+                (None, Some(fallback)) => fallback(method, params),
+                (None, None) => Err(standard_error(StandardError::MethodNotFound, None)),
+            }
+        }));
+        result.unwrap_or_else(|_| Err(internal_error("handler panicked".to_owned())))
+    }
+
+    /// Answers a serialized request or batch of requests, returning the serialized response.
+    /// Nothing is returned when there is nothing to answer, i.e. when all the requests were
+    /// notifications.
+    pub fn handle(&self, body: &[u8]) -> Option<String> {
+        let response = match serde_json::from_slice(body) {
+            Ok(request) => self.respond(request)?,
+            Err(_) => error_response(StandardError::ParseError),
+        };
+        Some(response.to_string())
+    }
+
+    /// Answers a request or a batch of requests.
+    fn respond(&self, request: Value) -> Option<Value> {
+        match request {
+            Value::Array(ref requests) if requests.is_empty() => {
+                Some(error_response(StandardError::InvalidRequest))
+            }
+            Value::Array(requests) => {
+                let responses: Vec<_> =
+                    requests.into_iter().filter_map(|r| self.respond_one(r)).collect();
+                if responses.is_empty() {
+                    None
+                } else {
+                    Some(Value::Array(responses))
+                }
+            }
+            request => self.respond_one(request),
+        }
+    }
+
+    /// Answers a single request, unless it is a notification, i.e. it has no id.
+    fn respond_one(&self, request: Value) -> Option<Value> {
+        let mut request = match request {
+            Value::Object(request) => request,
+            _ => return Some(error_response(StandardError::InvalidRequest)),
+        };
+        let id = request.remove("id");
+        let jsonrpc = match request.remove("jsonrpc") {
+            Some(Value::String(jsonrpc)) => Some(jsonrpc),
+            _ => None,
+        };
+        let valid_id = match id {
+            None | Some(Value::Null) | Some(Value::Number(_)) | Some(Value::String(_)) => true,
+            _ => false,
+        };
+        let method = match request.remove("method") {
+            Some(Value::String(method)) if valid_id => method,
+            _ => return Some(error_response(StandardError::InvalidRequest)),
+        };
+        let params = match request.remove("params") {
+            None | Some(Value::Null) => None,
+            Some(params @ Value::Array(_)) | Some(params @ Value::Object(_)) => Some(params),
+            Some(_) => {
+                return Some(to_value(result_to_response(
+                    Err(standard_error(StandardError::InvalidRequest, None)),
+                    id.unwrap_or(Value::Null),
+                )))
+            }
+        };
+
+        let result = self.call(&method, params);
+        id.map(|id| to_value(answer(result, id, jsonrpc)))
+    }
+
+    /// Serves HTTP clients on `listener`, answering each connection on its own thread. Only
+    /// `POST` requests are answered, whatever their path. Requests made only of notifications
+    /// get an empty "204 No Content" response.
+    ///
+    /// This runs until accepting a connection fails, so it usually gets a thread of its own.
+    #[cfg(feature = "simple_http")]
+    pub fn serve_http(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone();
+            thread::spawn(move || server.serve_http_connection(stream));
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "simple_http")]
+    fn serve_http_connection(&self, stream: TcpStream) -> io::Result<()> {
+        use std::io::{BufReader, Write};
+        use std::time::Instant;
+
+        use crate::http::{self, BodyLength, ParseError};
+
+        stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+        let mut reader = BufReader::new(stream);
+        loop {
+            let deadline = Instant::now() + HTTP_TIMEOUT;
+            let head = match http::read_request_head(&mut reader, deadline) {
+                Ok(head) => head,
+                Err(ParseError::Malformed) => {
+                    return write_http(reader.get_mut(), "400 Bad Request", None, false)
+                }
+                // The client closed the connection, or left it idle
+                Err(_) => return Ok(()),
+            };
+
+            let stream = reader.get_mut();
+            if head.method != "POST" {
+                return write_http(stream, "405 Method Not Allowed", None, false);
+            }
+            if self.auth.is_some()
+                && head.header("Authorization") != self.auth.as_ref().map(|a| a.expose())
+            {
+                return write_http(stream, "401 Unauthorized", None, false);
+            }
+            match head.body_length() {
+                Ok(BodyLength::Fixed(len)) if len > MAX_REQUEST_SIZE => {
+                    return write_http(stream, "413 Payload Too Large", None, false)
+                }
+                Ok(_) => {}
+                Err(_) => return write_http(stream, "400 Bad Request", None, false),
+            }
+            if head.header("Expect").map_or(false, |e| e.eq_ignore_ascii_case("100-continue")) {
+                stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+            }
+
+            let body = match http::read_request_body(&mut reader, &head, MAX_REQUEST_SIZE, deadline)
+            {
+                Ok(body) => body,
+                Err(ParseError::Malformed) => {
+                    return write_http(reader.get_mut(), "400 Bad Request", None, false)
+                }
+                Err(ParseError::TooLarge) => {
+                    return write_http(reader.get_mut(), "413 Payload Too Large", None, false)
+                }
+                Err(_) => return Ok(()),
+            };
+            let keep_alive = head.keep_alive();
+            match self.handle(&body) {
+                Some(response) => {
+                    write_http(reader.get_mut(), "200 OK", Some(&response), keep_alive)?
+                }
+                None => write_http(reader.get_mut(), "204 No Content", None, keep_alive)?,
+            }
+            if !keep_alive {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Serves clients of [crate::simple_tcp::TcpTransport] on `listener`, answering each
+    /// connection on its own thread. Requests are read as a stream of JSON values, and each
+    /// response is written on a line of its own as soon as it is ready.
+    ///
+    /// This runs until accepting a connection fails, so it usually gets a thread of its own.
+    #[cfg(feature = "simple_tcp")]
+    pub fn serve_tcp(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone();
+            thread::spawn(move || server.serve_stream(&stream, &stream));
+        }
+        Ok(())
+    }
+
+    /// Serves clients of [crate::simple_uds::UdsTransport] on `listener`, like
+    /// [Server::serve_tcp] does.
+    #[cfg(all(feature = "simple_uds", not(windows)))]
+    pub fn serve_uds(self: Arc<Self>, listener: UnixListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream: UnixStream = stream?;
+            let server = self.clone();
+            thread::spawn(move || server.serve_stream(&stream, &stream));
+        }
+        Ok(())
+    }
+
+    /// Answers the JSON values read from `reader` until it ends. The stream can't be read any
+    /// further after invalid JSON, so the connection is closed after a "Parse error".
+    #[cfg(any(feature = "simple_tcp", all(feature = "simple_uds", not(windows))))]
+    fn serve_stream<R: io::Read, W: io::Write>(&self, reader: R, mut writer: W) -> io::Result<()> {
+        let reader = io::BufReader::new(reader);
+        for request in serde_json::Deserializer::from_reader(reader).into_iter::<Value>() {
+            let response = match request {
+                Ok(request) => self.respond(request),
+                Err(ref e) if e.is_io() || e.is_eof() => return Ok(()),
+                Err(_) => {
+                    serde_json::to_writer(&mut writer, &error_response(StandardError::ParseError))?;
+                    writer.write_all(b"\n")?;
+                    return writer.flush();
+                }
+            };
+            if let Some(response) = response {
+                serde_json::to_writer(&mut writer, &response)?;
+                writer.write_all(b"\n")?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server::new()
+    }
+}
+
+impl fmt::Debug for Server {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut methods: Vec<_> = self.methods.keys().collect();
+        methods.sort();
+        f.debug_struct("Server")
+            .field("methods", &methods)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+/// Calls the server in-process, without serializing anything but the params and results.
+impl Transport for Server {
+    fn send_request(&self, req: Request) -> Result<Response, crate::Error> {
+        let params = serde_json::to_value(req.params)?;
+        let result = self.call(req.method, Some(params));
+        Ok(answer(result, req.id.unwrap_or_default(), req.jsonrpc.map(str::to_owned)))
+    }
+
+    fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, crate::Error> {
+        let mut responses = Vec::with_capacity(reqs.len());
+        for req in reqs {
+            if req.is_notification() {
+                self.send_notification(req.clone())?;
+            } else {
+                responses.push(self.send_request(req.clone())?);
+            }
+        }
+        Ok(responses)
+    }
+
+    fn send_notification(&self, notification: Request) -> Result<(), crate::Error> {
+        let params = serde_json::to_value(notification.params)?;
+        let _ = self.call(notification.method, Some(params));
+        Ok(())
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "in-process server")
+    }
+}
+
+/// Deserializes params, trying an empty array, an empty object and null in turn when there
+/// are none, so that any type able to stand for "no params" fits.
+fn parse_params<P: DeserializeOwned>(params: Option<Value>) -> Result<P, RpcError> {
+    let invalid = |e: serde_json::Error| {
+        standard_error(StandardError::InvalidParams, Some(crate::arg(e.to_string())))
+    };
+    let empty = match params {
+        None => true,
+        Some(Value::Array(ref params)) => params.is_empty(),
+        Some(Value::Object(ref params)) => params.is_empty(),
+        Some(_) => false,
+    };
+    if !empty {
+        return serde_json::from_value(params.unwrap_or(Value::Null)).map_err(invalid);
+    }
+    let candidates = [Value::Array(Vec::new()), Value::Object(Default::default()), Value::Null];
+    let mut error = None;
+    for candidate in candidates.iter() {
+        match serde_json::from_value(candidate.clone()) {
+            Ok(params) => return Ok(params),
+            Err(e) => error = error.or(Some(e)),
+        }
+    }
+    Err(invalid(error.expect("there are candidates")))
+}
+
+fn internal_error(message: String) -> RpcError {
+    standard_error(StandardError::InternalError, Some(crate::arg(message)))
+}
+
+/// Returns the response to a request that can't be identified.
+fn error_response(error: StandardError) -> Value {
+    to_value(result_to_response(Err(standard_error(error, None)), Value::Null))
+}
+
+/// Returns the response to a request with the given id and `jsonrpc` field. The field is
+/// echoed, so that JSON-RPC 1.0 clients get a response of their version, and is "2.0" if the
+/// request had none.
+fn answer(result: Result<Value, RpcError>, id: Value, jsonrpc: Option<String>) -> Response {
+    let mut response = result_to_response(result, id);
+    if jsonrpc.is_some() {
+        response.jsonrpc = jsonrpc;
+    }
+    response
+}
+
+fn to_value(response: Response) -> Value {
+    serde_json::to_value(response).expect("responses serialize")
+}
+
+/// Writes an HTTP response, with a JSON body if any.
+#[cfg(feature = "simple_http")]
+fn write_http(
+    stream: &mut TcpStream,
+    status: &str,
+    body: Option<&str>,
+    keep_alive: bool,
+) -> io::Result<()> {
+    use std::io::Write;
+
+    let mut response = format!("HTTP/1.1 {}\r\n", status);
+    if status.starts_with("401") {
+        response.push_str("WWW-Authenticate: Basic realm=\"jsonrpc\"\r\n");
+    } else if status.starts_with("405") {
+        response.push_str("Allow: POST\r\n");
+    }
+    if !keep_alive {
+        response.push_str("Connection: close\r\n");
+    }
+    match body {
+        Some(body) => {
+            response.push_str("Content-Type: application/json\r\n");
+            response.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+            response.push_str(body);
+        }
+        None if status.starts_with("204") => response.push_str("\r\n"),
+        None => response.push_str("Content-Length: 0\r\n\r\n"),
+    }
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{Client, Params};
+
+    #[derive(Deserialize)]
+    struct Transfer {
+        from: String,
+        to: String,
+        #[serde(default)]
+        amount: u64,
+    }
+
+    fn server() -> Server {
+        Server::new()
+            .register("add", |(a, b): (i64, i64)| Ok(a + b))
+            .register("transfer", |t: Transfer| Ok(format!("{} {} -> {}", t.amount, t.from, t.to)))
+            .register("ping", |()| Ok("pong"))
+            .register("fail", |()| -> Result<(), _> {
+                Err(RpcError {
+                    code: -1,
+                    message: "failed".to_owned(),
+                    data: None,
+                })
+            })
+            .register("panic", |()| -> Result<(), _> { panic!("oops") })
+    }
+
+    fn handle(server: &Server, request: Value) -> Value {
+        serde_json::from_str(&server.handle(request.to_string().as_bytes()).unwrap()).unwrap()
+    }
+
+    fn error_code(response: &Value) -> i64 {
+        response["error"]["code"].as_i64().unwrap()
+    }
+
+    #[test]
+    fn params() {
+        let server = server();
+        let resp = handle(&server, json!({"method": "add", "params": [1, 2], "id": 1}));
+        assert_eq!(resp["result"], 3);
+        assert_eq!(resp["id"], 1);
+        assert_eq!(resp["jsonrpc"], "2.0");
+        let resp = handle(&server, json!({"jsonrpc": "1.0", "method": "ping", "id": 1}));
+        assert_eq!(resp["jsonrpc"], "1.0");
+
+        let transfer = json!({"method": "transfer", "params": {"from": "a", "to": "b"}, "id": 2});
+        assert_eq!(handle(&server, transfer)["result"], "0 a -> b");
+        let transfer = json!({"method": "transfer", "params": ["a", "b", 5], "id": 3});
+        assert_eq!(handle(&server, transfer)["result"], "5 a -> b");
+
+        for params in &[json!(null), json!([]), json!({})] {
+            let resp = handle(&server, json!({"method": "ping", "params": params, "id": "x"}));
+            assert_eq!(resp["result"], "pong");
+        }
+        assert_eq!(handle(&server, json!({"method": "ping", "id": "x"}))["result"], "pong");
+
+        let resp = handle(&server, json!({"method": "add", "params": [1], "id": 4}));
+        assert_eq!(error_code(&resp), -32602);
+        assert!(resp["error"]["data"].is_string());
+        let resp = handle(&server, json!({"method": "add", "params": {"a": 1, "b": 2}, "id": 5}));
+        assert_eq!(error_code(&resp), -32602);
+    }
+
+    #[test]
+    fn errors() {
+        let server = server();
+        assert_eq!(
+            server.handle(b"{\"method\":"),
+            Some(error_response(StandardError::ParseError).to_string())
+        );
+
+        let resp = handle(&server, json!({"method": "nope", "id": 1}));
+        assert_eq!(error_code(&resp), -32601);
+        assert_eq!(error_code(&handle(&server, json!({"method": "fail", "id": 1}))), -1);
+        assert_eq!(error_code(&handle(&server, json!({"method": "panic", "id": 1}))), -32603);
+
+        for request in &[
+            json!([]),
+            json!(1),
+            json!({"id": 1}),
+            json!({"method": 1, "id": 1}),
+            json!({"method": "ping", "id": [1]}),
+            json!({"method": "ping", "params": 1, "id": 1}),
+        ] {
+            let resp = handle(&server, request.clone());
+            assert_eq!(error_code(&resp), -32600, "{}", request);
+        }
+    }
+
+    #[test]
+    fn batches_and_notifications() {
+        let server = server();
+        assert_eq!(server.handle(br#"{"method": "ping"}"#), None);
+        assert_eq!(server.handle(br#"[{"method": "ping"}, {"method": "nope"}]"#), None);
+
+        let batch = json!([
+            {"method": "add", "params": [1, 1], "id": 1},
+            {"method": "ping"},
+            5,
+            {"method": "nope", "id": 2},
+        ]);
+        let resp = handle(&server, batch);
+        let resp = resp.as_array().unwrap();
+        assert_eq!(resp.len(), 3);
+        assert_eq!(resp[0]["result"], 2);
+        assert_eq!(error_code(&resp[1]), -32600);
+        assert_eq!(resp[1]["id"], Value::Null);
+        assert_eq!(error_code(&resp[2]), -32601);
+    }
+
+    #[test]
+    fn fallback() {
+        let server = server().fallback(|method, params| Ok(json!([method, params])));
+        assert_eq!(
+            handle(&server, json!({"method": "add", "params": [1, 2], "id": 1}))["result"],
+            3
+        );
+        let resp = handle(&server, json!({"method": "other", "params": [1], "id": 1}));
+        assert_eq!(resp["result"], json!(["other", [1]]));
+    }
+
+    #[test]
+    fn in_process() {
+        let client = Client::with_transport(server());
+        assert_eq!(client.call::<i64>("add", &[crate::arg(2), crate::arg(3)]).unwrap(), 5);
+        let reqs = [client.build_request("ping", &[]), client.build_request("nope", &[])];
+        let resps = client.send_batch(&reqs).unwrap();
+        assert_eq!(resps[0].as_ref().unwrap().result::<String>().unwrap(), "pong");
+        assert_eq!(resps[1].as_ref().unwrap().error.as_ref().unwrap().code, -32601);
+
+        let params = [("from", crate::arg("a")), ("to", crate::arg("b"))];
+        assert_eq!(client.call_by_name::<String>("transfer", &params).unwrap(), "0 a -> b");
+
+        let client = Client::with_transport(server()).with_version(crate::Version::V1);
+        assert_eq!(client.call::<String>("ping", &[]).unwrap(), "pong");
+    }
+
+    #[test]
+    fn notifications() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counted = count.clone();
+        let server = server().register("count", move |()| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        });
+        let client = Client::with_transport(server);
+
+        client
+            .send_notification(client.build_notification("count", Params::ByPosition(&[])))
+            .unwrap();
+        let reqs = [
+            client.build_notification("count", Params::ByName(&[])),
+            client.build_request("ping", &[]),
+            client.build_notification("count", Params::ByPosition(&[])),
+        ];
+        let resps = client.send_batch(&reqs).unwrap();
+        assert!(resps[0].is_none());
+        assert_eq!(resps[1].as_ref().unwrap().result::<String>().unwrap(), "pong");
+        assert!(resps[2].is_none());
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[cfg(feature = "simple_http")]
+    #[test]
+    fn http() {
+        use std::io::{Read, Write};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Arc::new(server().auth("user", Some("pass")));
+        thread::spawn(move || server.serve_http(listener));
+
+        let url = addr.to_string();
+        let client =
+            Client::simple_http(&url, Some("user".to_owned()), Some("pass".to_owned())).unwrap();
+        for _ in 0..3 {
+            assert_eq!(client.call::<i64>("add", &[crate::arg(2), crate::arg(3)]).unwrap(), 5);
+        }
+        let params =
+            [("from", crate::arg("a")), ("to", crate::arg("b")), ("amount", crate::arg(1))];
+        assert_eq!(client.call_by_name::<String>("transfer", &params).unwrap(), "1 a -> b");
+
+        // Notifications get an empty response
+        let ping = client.build_notification("ping", Params::ByPosition(&[]));
+        client.send_notification(ping.clone()).unwrap();
+        let reqs = [ping, client.build_notification("add", Params::ByPosition(&[]))];
+        assert!(client.send_batch(&reqs).unwrap().iter().all(Option::is_none));
+
+        let client = Client::simple_http(&url, Some("user".to_owned()), None).unwrap();
+        match client.call::<i64>("add", &[crate::arg(2), crate::arg(3)]) {
+            Err(crate::Error::Transport(e)) => assert!(e.to_string().contains("401"), "{}", e),
+            res => panic!("expected a 401, got {:?}", res),
+        }
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let auth = format!("Basic {}", base64::encode("user:pass"));
+        let body = r#"{"jsonrpc": "2.0", "method": "ping"}"#;
+        write!(
+            stream,
+            "POST / HTTP/1.1\r\nAuthorization: {}\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            auth,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).unwrap();
+        assert!(resp.starts_with("HTTP/1.1 204 No Content\r\n"), "{}", resp);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).unwrap();
+        assert!(resp.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"), "{}", resp);
+    }
+
+    #[cfg(feature = "simple_tcp")]
+    #[test]
+    fn tcp() {
+        use std::io::{BufRead, BufReader, Write};
+
+        use std::net::TcpStream;
+
+        use crate::simple_tcp::TcpTransport;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || Arc::new(server()).serve_tcp(listener));
+
+        let client = Client::with_transport(TcpTransport::new(addr));
+        assert_eq!(client.call::<i64>("add", &[crate::arg(2), crate::arg(3)]).unwrap(), 5);
+        // Over the same connection
+        assert_eq!(client.call::<i64>("add", &[crate::arg(4), crate::arg(3)]).unwrap(), 7);
+
+        // Several requests on one connection, the notification isn't answered
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(br#"{"method": "ping"} {"method": "ping", "id": 1}{"method": "add", "params": [1, 2], "id": 2}"#)
+            .unwrap();
+        stream.write_all(b"\n").unwrap();
+        let mut lines = BufReader::new(&stream).lines();
+        let mut response =
+            || serde_json::from_str::<Value>(&lines.next().unwrap().unwrap()).unwrap();
+        assert_eq!(response()["result"], "pong");
+        assert_eq!(response()["result"], 3);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"{nope").unwrap();
+        let mut resp = String::new();
+        BufReader::new(&stream).read_line(&mut resp).unwrap();
+        assert_eq!(error_code(&serde_json::from_str(&resp).unwrap()), -32700);
+    }
+
+    #[cfg(all(feature = "simple_uds", not(windows)))]
+    #[test]
+    fn uds() {
+        use crate::simple_uds::UdsTransport;
+
+        let path = std::env::temp_dir().join(format!("jsonrpc-server-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        thread::spawn(move || Arc::new(server()).serve_uds(listener));
+
+        let client = Client::with_transport(UdsTransport::new(&path));
+        assert_eq!(client.call::<i64>("add", &[crate::arg(2), crate::arg(3)]).unwrap(), 5);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,5 +1,6 @@
-//! This module implements a synchronous transport over a raw TcpListener. Note that
-//! it does not handle TCP over Unix Domain Sockets, see `simple_uds` for this.
+//! This module implements a synchronous transport over a raw TcpListener, sending
+//! newline-delimited JSON over a persistent connection. Note that it does not handle TCP over
+//! Unix Domain Sockets, see `simple_uds` for this.
 
 use std::{error, fmt, io, net, time};
 
@@ -7,7 +8,8 @@ use serde;
 use serde_json;
 
 use crate::client::Transport;
-use crate::{Request, Response};
+use crate::multiplex::{Config, Failure, Multiplexed};
+use crate::{ErrorKind, Request, Response};
 
 /// Error that can occur while using the TCP transport.
 #[derive(Debug)]
@@ -16,15 +18,40 @@ pub enum Error {
     SocketError(io::Error),
     /// We didn't receive a complete response till the deadline ran out
     Timeout,
+    /// The response was larger than the transport's `max_response_size`
+    ResponseTooLarge,
+    /// The connection was closed before the response arrived
+    ConnectionClosed,
     /// JSON parsing error.
     Json(serde_json::Error),
 }
 
+impl Error {
+    /// Returns the kind of the error, see [crate::Error::kind].
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::SocketError(ref e) => ErrorKind::of_io(e),
+            Error::Timeout => ErrorKind::Timeout,
+            Error::ResponseTooLarge => ErrorKind::Protocol,
+            Error::ConnectionClosed => ErrorKind::Connection,
+            Error::Json(_) => ErrorKind::Deserialization,
+        }
+    }
+
+    /// Returns whether sending the request again may succeed: after socket errors, timeouts
+    /// and closed connections.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.kind(), ErrorKind::Connection | ErrorKind::Timeout)
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match *self {
             Error::SocketError(ref e) => write!(f, "Couldn't connect to host: {}", e),
             Error::Timeout => f.write_str("Didn't receive response data in time, timed out."),
+            Error::ResponseTooLarge => f.write_str("response larger than the maximum size"),
+            Error::ConnectionClosed => f.write_str("connection closed before the response arrived"),
             Error::Json(ref e) => write!(f, "JSON error: {}", e),
         }
     }
@@ -36,7 +63,7 @@ impl error::Error for Error {
 
         match *self {
             SocketError(ref e) => Some(e),
-            Timeout => None,
+            Timeout | ResponseTooLarge | ConnectionClosed => None,
             Json(ref e) => Some(e),
         }
     }
@@ -48,6 +75,17 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<Failure> for Error {
+    fn from(e: Failure) -> Self {
+        match e {
+            Failure::Io(e) => Error::SocketError(e),
+            Failure::Timeout => Error::Timeout,
+            Failure::TooLarge => Error::ResponseTooLarge,
+            Failure::Closed => Error::ConnectionClosed,
+        }
+    }
+}
+
 impl From<serde_json::Error> for Error {
     fn from(e: serde_json::Error) -> Self {
         Error::Json(e)
@@ -64,12 +102,22 @@ impl From<Error> for crate::Error {
 }
 
 /// Simple synchronous TCP transport.
+///
+/// It keeps a connection open and sends all requests over it, each on a line of its own. The
+/// server must answer on a line of its own as well, but can do so in any order: the transport
+/// can be used by many threads at once. If the connection is closed, the next request opens a
+/// new one.
 #[derive(Debug, Clone)]
 pub struct TcpTransport {
     /// The internet socket address to connect to
     pub addr: net::SocketAddr,
-    /// The read and write timeout to use for this connection
+    /// How long to wait for a response, and the write timeout of the socket
     pub timeout: Option<time::Duration>,
+    /// How long to wait for the connection to be established, forever if unset
+    pub connect_timeout: Option<time::Duration>,
+    /// The largest response accepted, in bytes
+    pub max_response_size: usize,
+    conn: Multiplexed<net::TcpStream>,
 }
 
 impl TcpTransport {
@@ -78,35 +126,66 @@ impl TcpTransport {
         TcpTransport {
             addr,
             timeout: None,
+            connect_timeout: None,
+            max_response_size: crate::DEFAULT_MAX_RESPONSE_SIZE,
+            conn: Multiplexed::new(),
         }
     }
 
-    fn request<R>(&self, req: impl serde::Serialize) -> Result<R, Error>
+    fn connect(&self) -> io::Result<net::TcpStream> {
+        let sock = match self.connect_timeout {
+            Some(timeout) => net::TcpStream::connect_timeout(&self.addr, timeout)?,
+            None => net::TcpStream::connect(self.addr)?,
+        };
+        sock.set_write_timeout(self.timeout)?;
+        sock.set_nodelay(true)?;
+        Ok(sock)
+    }
+
+    fn config(&self) -> Config<'_, net::TcpStream> {
+        Config {
+            connect: Box::new(move || self.connect()),
+            timeout: self.timeout,
+            max_response_size: self.max_response_size,
+        }
+    }
+
+    /// Sends the request and waits for the response carrying one of `ids`.
+    fn request<R>(
+        &self,
+        req: impl serde::Serialize,
+        ids: Vec<serde_json::Value>,
+    ) -> Result<R, Error>
     where
         R: for<'a> serde::de::Deserialize<'a>,
     {
-        let mut sock = net::TcpStream::connect(&self.addr)?;
-        sock.set_read_timeout(self.timeout)?;
-        sock.set_write_timeout(self.timeout)?;
-
-        serde_json::to_writer(&mut sock, &req)?;
+        let resp = self.conn.request(&self.config(), ids, serde_json::to_vec(&req)?)?;
+        Ok(serde_json::from_str(&resp)?)
+    }
 
-        // NOTE: we don't check the id there, so it *must* be synchronous
-        let resp: R = serde_json::Deserializer::from_reader(&mut sock)
-            .into_iter()
-            .next()
-            .ok_or(Error::Timeout)??;
-        Ok(resp)
+    /// Sends a message that gets no response.
+    fn send(&self, req: impl serde::Serialize) -> Result<(), Error> {
+        Ok(self.conn.send(&self.config(), serde_json::to_vec(&req)?)?)
     }
 }
 
 impl Transport for TcpTransport {
     fn send_request(&self, req: Request) -> Result<Response, crate::Error> {
-        Ok(self.request(req)?)
+        let id = req.id.clone().unwrap_or_default();
+        Ok(self.request(req, vec![id])?)
     }
 
     fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, crate::Error> {
-        Ok(self.request(reqs)?)
+        if !crate::expects_response(reqs) {
+            self.send(reqs)?;
+            return Ok(vec![]);
+        }
+        let ids = reqs.iter().filter(|r| !r.is_notification()).filter_map(|r| r.id.clone());
+        Ok(self.request(reqs, ids.collect())?)
+    }
+
+    fn send_notification(&self, notification: Request) -> Result<(), crate::Error> {
+        Ok(self.send(notification)?)
     }
 
     fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -117,40 +196,43 @@ impl Transport for TcpTransport {
 #[cfg(test)]
 mod tests {
     use std::{
-        io::{Read, Write},
+        io::{BufRead, BufReader, Read, Write},
+        sync::{mpsc, Arc},
         thread,
     };
 
     use super::*;
-    use crate::Client;
+    use crate::{Client, Params};
 
     // Test a dummy request / response over a raw TCP transport
     #[test]
     fn sanity_check_tcp_transport() {
         let addr: net::SocketAddr =
             net::SocketAddrV4::new(net::Ipv4Addr::new(127, 0, 0, 1), 0).into();
-        let server = net::TcpListener::bind(&addr).unwrap();
+        let server = net::TcpListener::bind(addr).unwrap();
         let addr = server.local_addr().unwrap();
         let dummy_req = Request {
             method: "arandommethod",
-            params: &[],
-            id: serde_json::Value::Number(4242242.into()),
+            params: Params::ByPosition(&[]),
+            id: Some(serde_json::Value::Number(4242242.into())),
             jsonrpc: Some("2.0"),
         };
-        let dummy_req_ser = serde_json::to_vec(&dummy_req).unwrap();
+        let mut dummy_req_ser = serde_json::to_vec(&dummy_req).unwrap();
+        dummy_req_ser.push(b'\n');
         let dummy_resp = Response {
             result: None,
             error: None,
             id: serde_json::Value::Number(4242242.into()),
             jsonrpc: Some("2.0".into()),
+            has_result: true,
+            has_error: true,
         };
         let dummy_resp_ser = serde_json::to_vec(&dummy_resp).unwrap();
 
         let client_thread = thread::spawn(move || {
-            let transport = TcpTransport {
-                addr,
-                timeout: Some(time::Duration::from_secs(5)),
-            };
+            let mut transport = TcpTransport::new(addr);
+            transport.timeout = Some(time::Duration::from_secs(5));
+            transport.connect_timeout = Some(time::Duration::from_secs(5));
             let client = Client::with_transport(transport);
 
             client.send_request(dummy_req.clone()).unwrap()
@@ -166,8 +248,184 @@ mod tests {
         assert_eq!(recv_req, dummy_req_ser);
 
         stream.write_all(&dummy_resp_ser).unwrap();
+        stream.write_all(b"\n").unwrap();
         stream.flush().unwrap();
         let recv_resp = client_thread.join().unwrap();
         assert_eq!(serde_json::to_vec(&recv_resp).unwrap(), dummy_resp_ser);
     }
+
+    #[test]
+    fn response_too_large() {
+        let server = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = server.accept().unwrap();
+            // Closing with the request unread would reset the connection
+            BufReader::new(&stream).read_line(&mut String::new()).unwrap();
+            let resp = format!(r#"{{"result":"{}","error":null,"id":1}}"#, "00".repeat(100));
+            stream.write_all(resp.as_bytes()).unwrap();
+        });
+
+        let mut transport = TcpTransport::new(addr);
+        transport.timeout = Some(time::Duration::from_secs(5));
+        transport.max_response_size = 100;
+        let req = Request {
+            method: "getblock",
+            params: Params::ByPosition(&[]),
+            id: Some(serde_json::Value::Number(1.into())),
+            jsonrpc: Some("2.0"),
+        };
+        match transport.request::<Response>(req, vec![1.into()]) {
+            Err(Error::ResponseTooLarge) => {}
+            res => panic!("expected the response to be too large, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn concurrent_requests() {
+        const CALLERS: usize = 8;
+
+        let server = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let (closed_tx, closed_rx) = mpsc::channel();
+        let server_thread = thread::spawn(move || {
+            // All callers share one connection, answered in the reverse order
+            let (stream, _) = server.accept().unwrap();
+            let mut lines = BufReader::new(&stream).lines();
+            let mut reqs: Vec<serde_json::Value> = (0..CALLERS)
+                .map(|_| serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap())
+                .collect();
+            reqs.reverse();
+            for req in reqs {
+                let resp = serde_json::json!({"result": req["params"][0], "id": req["id"]});
+                writeln!(&stream, "{}", resp).unwrap();
+            }
+            // The next request reconnects once this connection is closed
+            drop(lines);
+            drop(stream);
+            closed_tx.send(()).unwrap();
+            let (stream, _) = server.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(&stream).read_line(&mut line).unwrap();
+            let req: serde_json::Value = serde_json::from_str(&line).unwrap();
+            writeln!(&stream, "{}", serde_json::json!({"result": "again", "id": req["id"]}))
+                .unwrap();
+        });
+
+        let mut transport = TcpTransport::new(addr);
+        transport.timeout = Some(time::Duration::from_secs(5));
+        let client = Arc::new(Client::with_transport(transport));
+        let callers: Vec<_> = (0..CALLERS)
+            .map(|i| {
+                let client = client.clone();
+                thread::spawn(move || client.call::<usize>("echo", &[crate::arg(i)]).unwrap())
+            })
+            .collect();
+        for (i, caller) in callers.into_iter().enumerate() {
+            assert_eq!(caller.join().unwrap(), i);
+        }
+
+        // Give the reader time to see the connection closed, a request written to it would
+        // fail without being sent again
+        closed_rx.recv().unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+        assert_eq!(client.call::<String>("echo", &[]).unwrap(), "again");
+        server_thread.join().unwrap();
+    }
+
+    #[test]
+    fn unanswered_requests() {
+        let server = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let server_thread = thread::spawn(move || {
+            // A request the server couldn't parse gets an error without id
+            let (stream, _) = server.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(&stream).read_line(&mut line).unwrap();
+            let parse_error = serde_json::json!({
+                "jsonrpc": "2.0",
+                "error": {"code": -32700, "message": "Parse error"},
+                "id": null,
+            });
+            writeln!(&stream, "{}", parse_error).unwrap();
+
+            // The connection is closed after reading the second request
+            let (stream, _) = server.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let req: serde_json::Value = serde_json::from_str(&line).unwrap();
+            writeln!(&stream, "{}", serde_json::json!({"result": "first", "id": req["id"]}))
+                .unwrap();
+            reader.read_line(&mut line).unwrap();
+            server
+        });
+
+        // Without timeout, the callers would wait forever
+        let client = Client::with_transport(TcpTransport::new(addr));
+        let closed = |res: Result<String, crate::Error>| match res {
+            Err(crate::Error::Transport(e)) => {
+                assert!(matches!(e.downcast_ref(), Some(Error::ConnectionClosed)), "{}", e)
+            }
+            res => panic!("expected the connection to be closed, got {:?}", res),
+        };
+        closed(client.call("nonsense", &[]));
+        assert_eq!(client.call::<String>("getblockcount", &[]).unwrap(), "first");
+        // The server may have run the request, it isn't sent again
+        closed(client.call("sendrawtransaction", &[]));
+        let server = server_thread.join().unwrap();
+        server.set_nonblocking(true).unwrap();
+        assert!(server.accept().is_err());
+    }
+
+    #[test]
+    fn deadlines_and_cancellation() {
+        use crate::{CallOptions, CancelToken};
+
+        // Reads the requests but never answers them
+        let server = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in server.incoming() {
+                thread::spawn(move || {
+                    let _ = BufReader::new(stream.unwrap()).lines().count();
+                });
+            }
+        });
+
+        let mut transport = TcpTransport::new(addr);
+        transport.timeout = Some(time::Duration::from_secs(60));
+        let client = Client::with_transport(transport)
+            .with_method_timeout("getblockcount", time::Duration::from_millis(50));
+
+        let start = time::Instant::now();
+        let options = CallOptions::new().timeout(time::Duration::from_millis(50));
+        match client.call_with_options::<u64>("getblock", &[], &options) {
+            Err(crate::Error::DeadlineExceeded) => {}
+            res => panic!("expected the deadline to pass, got {:?}", res),
+        }
+        match client.call::<u64>("getblockcount", &[]) {
+            Err(crate::Error::DeadlineExceeded) => {}
+            res => panic!("expected the deadline to pass, got {:?}", res),
+        }
+        assert!(start.elapsed() < time::Duration::from_secs(10));
+
+        let token = CancelToken::new();
+        let canceller = token.clone();
+        thread::spawn(move || {
+            thread::sleep(time::Duration::from_millis(50));
+            canceller.cancel();
+        });
+        let options = CallOptions::new().cancel_token(token.clone());
+        match client.call_with_options::<u64>("getblock", &[], &options) {
+            Err(crate::Error::Cancelled) => {}
+            res => panic!("expected the call to be cancelled, got {:?}", res),
+        }
+        // Calls using a cancelled token aren't sent at all
+        match client.call_with_options::<u64>("getblock", &[], &options) {
+            Err(crate::Error::Cancelled) => {}
+            res => panic!("expected the call to be cancelled, got {:?}", res),
+        }
+        assert!(start.elapsed() < time::Duration::from_secs(10));
+    }
 }
@@ -1,13 +1,16 @@
-//! This module implements a synchronous transport over a raw TcpListener.
+//! This module implements a synchronous transport over a Unix Domain Socket, sending
+//! newline-delimited JSON over a persistent connection.
 
 use std::os::unix::net::UnixStream;
-use std::{error, fmt, io, path, time};
+use std::sync::mpsc;
+use std::{error, fmt, io, path, thread, time};
 
 use serde;
 use serde_json;
 
 use crate::client::Transport;
-use crate::{Request, Response};
+use crate::multiplex::{Config, Failure, Multiplexed};
+use crate::{ErrorKind, Request, Response};
 
 /// Error that can occur while using the UDS transport.
 #[derive(Debug)]
@@ -16,15 +19,40 @@ pub enum Error {
     SocketError(io::Error),
     /// We didn't receive a complete response till the deadline ran out
     Timeout,
+    /// The response was larger than the transport's `max_response_size`
+    ResponseTooLarge,
+    /// The connection was closed before the response arrived
+    ConnectionClosed,
     /// JSON parsing error.
     Json(serde_json::Error),
 }
 
+impl Error {
+    /// Returns the kind of the error, see [crate::Error::kind].
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::SocketError(ref e) => ErrorKind::of_io(e),
+            Error::Timeout => ErrorKind::Timeout,
+            Error::ResponseTooLarge => ErrorKind::Protocol,
+            Error::ConnectionClosed => ErrorKind::Connection,
+            Error::Json(_) => ErrorKind::Deserialization,
+        }
+    }
+
+    /// Returns whether sending the request again may succeed: after socket errors, timeouts
+    /// and closed connections.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.kind(), ErrorKind::Connection | ErrorKind::Timeout)
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match *self {
             Error::SocketError(ref e) => write!(f, "Couldn't connect to host: {}", e),
             Error::Timeout => f.write_str("Didn't receive response data in time, timed out."),
+            Error::ResponseTooLarge => f.write_str("response larger than the maximum size"),
+            Error::ConnectionClosed => f.write_str("connection closed before the response arrived"),
             Error::Json(ref e) => write!(f, "JSON error: {}", e),
         }
     }
@@ -36,7 +64,7 @@ impl error::Error for Error {
 
         match *self {
             SocketError(ref e) => Some(e),
-            Timeout => None,
+            Timeout | ResponseTooLarge | ConnectionClosed => None,
             Json(ref e) => Some(e),
         }
     }
@@ -48,6 +76,17 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<Failure> for Error {
+    fn from(e: Failure) -> Self {
+        match e {
+            Failure::Io(e) => Error::SocketError(e),
+            Failure::Timeout => Error::Timeout,
+            Failure::TooLarge => Error::ResponseTooLarge,
+            Failure::Closed => Error::ConnectionClosed,
+        }
+    }
+}
+
 impl From<serde_json::Error> for Error {
     fn from(e: serde_json::Error) -> Self {
         Error::Json(e)
@@ -64,12 +103,22 @@ impl From<Error> for crate::error::Error {
 }
 
 /// Simple synchronous UDS transport.
+///
+/// It keeps a connection open and sends all requests over it, each on a line of its own. The
+/// server must answer on a line of its own as well, but can do so in any order: the transport
+/// can be used by many threads at once. If the connection is closed, the next request opens a
+/// new one.
 #[derive(Debug, Clone)]
 pub struct UdsTransport {
     /// The path to the Unix Domain Socket
     pub sockpath: path::PathBuf,
-    /// The read and write timeout to use
+    /// How long to wait for a response, and the write timeout of the socket
     pub timeout: Option<time::Duration>,
+    /// How long to wait for the connection to be established, forever if unset
+    pub connect_timeout: Option<time::Duration>,
+    /// The largest response accepted, in bytes
+    pub max_response_size: usize,
+    conn: Multiplexed<UnixStream>,
 }
 
 impl UdsTransport {
@@ -78,35 +127,79 @@ impl UdsTransport {
         UdsTransport {
             sockpath: sockpath.as_ref().to_path_buf(),
             timeout: None,
+            connect_timeout: None,
+            max_response_size: crate::DEFAULT_MAX_RESPONSE_SIZE,
+            conn: Multiplexed::new(),
+        }
+    }
+
+    fn connect(&self) -> io::Result<UnixStream> {
+        let sock = match self.connect_timeout {
+            Some(timeout) => {
+                // There's no connect with timeout for Unix sockets: connect on another thread,
+                // which drops the socket if it connects too late.
+                let (tx, rx) = mpsc::channel();
+                let sockpath = self.sockpath.clone();
+                thread::Builder::new().name("jsonrpc-uds-connect".to_owned()).spawn(move || {
+                    let _ = tx.send(UnixStream::connect(sockpath));
+                })?;
+                match rx.recv_timeout(timeout) {
+                    Ok(res) => res?,
+                    Err(_) => {
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))
+                    }
+                }
+            }
+            None => UnixStream::connect(&self.sockpath)?,
+        };
+        sock.set_write_timeout(self.timeout)?;
+        Ok(sock)
+    }
+
+    fn config(&self) -> Config<'_, UnixStream> {
+        Config {
+            connect: Box::new(move || self.connect()),
+            timeout: self.timeout,
+            max_response_size: self.max_response_size,
         }
     }
 
-    fn request<R>(&self, req: impl serde::Serialize) -> Result<R, Error>
+    /// Sends the request and waits for the response carrying one of `ids`.
+    fn request<R>(
+        &self,
+        req: impl serde::Serialize,
+        ids: Vec<serde_json::Value>,
+    ) -> Result<R, Error>
     where
         R: for<'a> serde::de::Deserialize<'a>,
     {
-        let mut sock = UnixStream::connect(&self.sockpath)?;
-        sock.set_read_timeout(self.timeout)?;
-        sock.set_write_timeout(self.timeout)?;
-
-        serde_json::to_writer(&mut sock, &req)?;
+        let resp = self.conn.request(&self.config(), ids, serde_json::to_vec(&req)?)?;
+        Ok(serde_json::from_str(&resp)?)
+    }
 
-        // NOTE: we don't check the id there, so it *must* be synchronous
-        let resp: R = serde_json::Deserializer::from_reader(&mut sock)
-            .into_iter()
-            .next()
-            .ok_or(Error::Timeout)??;
-        Ok(resp)
+    /// Sends a message that gets no response.
+    fn send(&self, req: impl serde::Serialize) -> Result<(), Error> {
+        Ok(self.conn.send(&self.config(), serde_json::to_vec(&req)?)?)
     }
 }
 
 impl Transport for UdsTransport {
     fn send_request(&self, req: Request) -> Result<Response, crate::error::Error> {
-        Ok(self.request(req)?)
+        let id = req.id.clone().unwrap_or_default();
+        Ok(self.request(req, vec![id])?)
     }
 
     fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, crate::error::Error> {
-        Ok(self.request(reqs)?)
+        if !crate::expects_response(reqs) {
+            self.send(reqs)?;
+            return Ok(vec![]);
+        }
+        let ids = reqs.iter().filter(|r| !r.is_notification()).filter_map(|r| r.id.clone());
+        Ok(self.request(reqs, ids.collect())?)
+    }
+
+    fn send_notification(&self, notification: Request) -> Result<(), crate::error::Error> {
+        Ok(self.send(notification)?)
     }
 
     fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -124,7 +217,7 @@ mod tests {
     };
 
     use super::*;
-    use crate::Client;
+    use crate::{Client, Params};
 
     // Test a dummy request / response over an UDS
     #[test]
@@ -136,25 +229,27 @@ mod tests {
         let server = UnixListener::bind(&socket_path).unwrap();
         let dummy_req = Request {
             method: "getinfo",
-            params: &[],
-            id: serde_json::Value::Number(111.into()),
+            params: Params::ByPosition(&[]),
+            id: Some(serde_json::Value::Number(111.into())),
             jsonrpc: Some("2.0"),
         };
-        let dummy_req_ser = serde_json::to_vec(&dummy_req).unwrap();
+        let mut dummy_req_ser = serde_json::to_vec(&dummy_req).unwrap();
+        dummy_req_ser.push(b'\n');
         let dummy_resp = Response {
             result: None,
             error: None,
             id: serde_json::Value::Number(111.into()),
             jsonrpc: Some("2.0".into()),
+            has_result: true,
+            has_error: true,
         };
         let dummy_resp_ser = serde_json::to_vec(&dummy_resp).unwrap();
 
         let cli_socket_path = socket_path.clone();
         let client_thread = thread::spawn(move || {
-            let transport = UdsTransport {
-                sockpath: cli_socket_path,
-                timeout: Some(time::Duration::from_secs(5)),
-            };
+            let mut transport = UdsTransport::new(cli_socket_path);
+            transport.timeout = Some(time::Duration::from_secs(5));
+            transport.connect_timeout = Some(time::Duration::from_secs(5));
             let client = Client::with_transport(transport);
 
             client.send_request(dummy_req.clone()).unwrap()
@@ -170,6 +265,7 @@ mod tests {
         assert_eq!(recv_req, dummy_req_ser);
 
         stream.write_all(&dummy_resp_ser).unwrap();
+        stream.write_all(b"\n").unwrap();
         stream.flush().unwrap();
         let recv_resp = client_thread.join().unwrap();
         assert_eq!(serde_json::to_vec(&recv_resp).unwrap(), dummy_resp_ser);
@@ -0,0 +1,278 @@
+//! A minimal SOCKS5 client (RFC 1928), with optional username/password authentication
+//! (RFC 1929).
+//!
+//! Host names are sent to the proxy as they are, so that it resolves them itself. This is what
+//! makes `.onion` addresses reachable through Tor, and it keeps DNS requests from leaking.
+
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const USER_PASS_AUTH: u8 = 2;
+const NO_ACCEPTABLE_AUTH: u8 = 0xff;
+const CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+/// Opens a TCP connection to the first address `addr` resolves to, waiting at most `timeout`
+/// for each.
+pub(crate) fn connect_timeout<A: ToSocketAddrs>(
+    addr: A,
+    timeout: Duration,
+) -> io::Result<TcpStream> {
+    let mut last_err = None;
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(sock) => return Ok(sock),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any address")
+    }))
+}
+
+/// Connects to `host:port` through the SOCKS5 proxy at `proxy`, authenticating with `auth`
+/// if the proxy asks for it.
+pub(crate) fn connect(
+    proxy: (&str, u16),
+    auth: Option<(&str, &str)>,
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> io::Result<TcpStream> {
+    let mut sock = connect_timeout(proxy, timeout)?;
+    sock.set_read_timeout(Some(timeout))?;
+    sock.set_write_timeout(Some(timeout))?;
+
+    // Greeting, offering the authentication methods we can do
+    match auth {
+        Some(_) => sock.write_all(&[VERSION, 2, NO_AUTH, USER_PASS_AUTH])?,
+        None => sock.write_all(&[VERSION, 1, NO_AUTH])?,
+    }
+    let mut reply = [0u8; 2];
+    sock.read_exact(&mut reply)?;
+    if reply[0] != VERSION {
+        return Err(invalid_data("proxy doesn't speak SOCKS5"));
+    }
+    match (reply[1], auth) {
+        (NO_AUTH, _) => {}
+        (USER_PASS_AUTH, Some((user, pass))) => authenticate(&mut sock, user, pass)?,
+        (NO_ACCEPTABLE_AUTH, _) | (USER_PASS_AUTH, None) => {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "proxy requires an authentication method we don't support",
+            ))
+        }
+        _ => return Err(invalid_data("proxy chose an authentication method we didn't offer")),
+    }
+
+    let mut request = vec![VERSION, CONNECT, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(ATYP_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(ATYP_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if host.len() > 255 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "host name too long"));
+            }
+            request.push(ATYP_DOMAIN);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    sock.write_all(&request)?;
+
+    let mut reply = [0u8; 4];
+    sock.read_exact(&mut reply)?;
+    if reply[0] != VERSION {
+        return Err(invalid_data("proxy doesn't speak SOCKS5"));
+    }
+    if reply[1] != 0 {
+        return Err(reply_error(reply[1]));
+    }
+    // Skip the address the proxy bound, we don't need it
+    let len = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            sock.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        _ => return Err(invalid_data("proxy replied with an unknown address type")),
+    };
+    let mut bound = vec![0u8; len + 2];
+    sock.read_exact(&mut bound)?;
+
+    Ok(sock)
+}
+
+/// Username/password authentication, from RFC 1929.
+fn authenticate(sock: &mut TcpStream, user: &str, pass: &str) -> io::Result<()> {
+    if user.len() > 255 || pass.len() > 255 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "proxy credentials too long"));
+    }
+    let mut request = vec![1, user.len() as u8];
+    request.extend_from_slice(user.as_bytes());
+    request.push(pass.len() as u8);
+    request.extend_from_slice(pass.as_bytes());
+    sock.write_all(&request)?;
+
+    let mut reply = [0u8; 2];
+    sock.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "proxy refused the credentials",
+        ));
+    }
+    Ok(())
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Turns the reply code of a failed request into an error.
+fn reply_error(code: u8) -> io::Error {
+    let (kind, msg) = match code {
+        2 => (io::ErrorKind::PermissionDenied, "connection not allowed by the proxy's rules"),
+        3 => (io::ErrorKind::Other, "network unreachable"),
+        4 => (io::ErrorKind::Other, "host unreachable"),
+        5 => (io::ErrorKind::ConnectionRefused, "connection refused"),
+        6 => (io::ErrorKind::TimedOut, "TTL expired"),
+        7 => (io::ErrorKind::Other, "command not supported by the proxy"),
+        8 => (io::ErrorKind::Other, "address type not supported by the proxy"),
+        _ => (io::ErrorKind::Other, "general SOCKS server failure"),
+    };
+    io::Error::new(kind, format!("SOCKS5 proxy: {}", msg))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+
+    use super::*;
+
+    /// Runs a SOCKS5 proxy for a single connection. It checks the credentials if given any,
+    /// then sends the requested host and port on the returned channel and hands the
+    /// connection over to `serve`, without connecting anywhere.
+    pub(crate) fn fake_proxy<F>(
+        credentials: Option<(&'static str, &'static str)>,
+        serve: F,
+    ) -> (SocketAddr, std::sync::mpsc::Receiver<(String, u16)>)
+    where
+        F: FnOnce(TcpStream) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+            let mut greeting = [0u8; 2];
+            sock.read_exact(&mut greeting).unwrap();
+            let mut methods = vec![0u8; greeting[1] as usize];
+            sock.read_exact(&mut methods).unwrap();
+
+            if let Some((user, pass)) = credentials {
+                if !methods.contains(&USER_PASS_AUTH) {
+                    sock.write_all(&[VERSION, NO_ACCEPTABLE_AUTH]).unwrap();
+                    return;
+                }
+                sock.write_all(&[VERSION, USER_PASS_AUTH]).unwrap();
+                let mut len = [0u8; 2];
+                sock.read_exact(&mut len).unwrap();
+                let mut got_user = vec![0u8; len[1] as usize];
+                sock.read_exact(&mut got_user).unwrap();
+                sock.read_exact(&mut len[..1]).unwrap();
+                let mut got_pass = vec![0u8; len[0] as usize];
+                sock.read_exact(&mut got_pass).unwrap();
+                let ok = got_user == user.as_bytes() && got_pass == pass.as_bytes();
+                sock.write_all(&[
+                    1,
+                    if ok {
+                        0
+                    } else {
+                        1
+                    },
+                ])
+                .unwrap();
+                if !ok {
+                    return;
+                }
+            } else {
+                sock.write_all(&[VERSION, NO_AUTH]).unwrap();
+            }
+
+            let mut request = [0u8; 4];
+            sock.read_exact(&mut request).unwrap();
+            assert_eq!(request[..3], [VERSION, CONNECT, 0]);
+            let host = match request[3] {
+                ATYP_DOMAIN => {
+                    let mut len = [0u8; 1];
+                    sock.read_exact(&mut len).unwrap();
+                    let mut host = vec![0u8; len[0] as usize];
+                    sock.read_exact(&mut host).unwrap();
+                    String::from_utf8(host).unwrap()
+                }
+                ATYP_IPV4 => {
+                    let mut ip = [0u8; 4];
+                    sock.read_exact(&mut ip).unwrap();
+                    IpAddr::from(ip).to_string()
+                }
+                atyp => panic!("unexpected address type {}", atyp),
+            };
+            let mut port = [0u8; 2];
+            sock.read_exact(&mut port).unwrap();
+            tx.send((host, u16::from_be_bytes(port))).unwrap();
+
+            sock.write_all(&[VERSION, 0, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0]).unwrap();
+            serve(sock);
+        });
+        (addr, rx)
+    }
+
+    #[test]
+    fn domain_passthrough() {
+        let (proxy, requested) = fake_proxy(None, |mut sock| sock.write_all(b"hi").unwrap());
+        let port = proxy.port();
+        let mut sock =
+            connect(("127.0.0.1", port), None, "abc.onion", 8334, Duration::from_secs(5)).unwrap();
+        let mut hi = [0u8; 2];
+        sock.read_exact(&mut hi).unwrap();
+        assert_eq!(&hi, b"hi");
+        assert_eq!(requested.recv().unwrap(), ("abc.onion".to_owned(), 8334));
+    }
+
+    #[test]
+    fn authentication() {
+        let (proxy, requested) = fake_proxy(Some(("user", "pass")), |_| {});
+        let auth = Some(("user", "pass"));
+        connect(("127.0.0.1", proxy.port()), auth, "10.0.0.1", 80, Duration::from_secs(5)).unwrap();
+        assert_eq!(requested.recv().unwrap(), ("10.0.0.1".to_owned(), 80));
+
+        let (proxy, _) = fake_proxy(Some(("user", "pass")), |_| {});
+        let auth = Some(("user", "wrong"));
+        let err =
+            connect(("127.0.0.1", proxy.port()), auth, "abc.onion", 80, Duration::from_secs(5))
+                .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        // The proxy wants credentials, we have none
+        let (proxy, _) = fake_proxy(Some(("user", "pass")), |_| {});
+        let res =
+            connect(("127.0.0.1", proxy.port()), None, "abc.onion", 80, Duration::from_secs(5));
+        assert!(res.is_err());
+    }
+}
@@ -13,7 +13,11 @@
 //
 
 use std::borrow::Cow;
-use std::hash::{Hash, Hasher};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
 
 use serde_json::Value;
 
@@ -69,6 +73,23 @@ impl<'a> Hash for HashableValue<'a> {
     }
 }
 
+/// Locks a mutex, ignoring poisoning: the data it protects stays consistent on panics.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Returns a random number, good enough for WebSocket keys and masks or for jittering retry
+/// delays, which only need to be unpredictable, not cryptographically secure.
+pub(crate) fn random_u64() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    if let Ok(now) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(now.as_nanos());
+    }
+    hasher.finish()
+}
+
 #[cfg(test)]
 mod tests {
     use std::borrow::Cow;